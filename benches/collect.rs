@@ -0,0 +1,36 @@
+use std::fs;
+
+use codesync::Matches;
+use criterion::{criterion_group, criterion_main, Criterion};
+use tempfile::TempDir;
+
+/// Number of files in the synthetic tree. Large enough for per-file setup costs to show up.
+const FILES: usize = 3000;
+
+fn synthetic_tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for i in 0..FILES {
+        let subdir = dir.path().join(format!("dir{}", i % 30));
+        fs::create_dir_all(&subdir).unwrap();
+        let mut contents = String::new();
+        for line in 0..50 {
+            if line % 25 == 0 {
+                contents.push_str(&format!("// CODESYNC(label-{}, 2)\n", i / 2));
+            } else {
+                contents.push_str("fn some_code() -> usize { 42 }\n");
+            }
+        }
+        fs::write(subdir.join(format!("file{i}.rs")), contents).unwrap();
+    }
+    dir
+}
+
+fn collect(c: &mut Criterion) {
+    let dir = synthetic_tree();
+    c.bench_function("collect_in", |b| {
+        b.iter(|| Matches::collect_in(dir.path()).unwrap())
+    });
+}
+
+criterion_group!(benches, collect);
+criterion_main!(benches);
@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use codesync::{
+    bench::{find_codesync_pattern, find_codesync_pattern_kmp, Matcher},
+    Matches,
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Size of the synthetic buffers in bytes
+const BUFFER_SIZE: usize = 1 << 20;
+
+type Finder = fn(&[u8]) -> Option<usize>;
+
+const CODE_LINE: &str = "    let result = compute_something(input, &config)?;\n";
+const MATCH_LINE: &str = "    // CODESYNC(some-label, 2)\n";
+
+/// A buffer of source-like lines where one in every `every` lines contains the pattern. If `every`
+/// is zero the pattern never occurs.
+fn buffer_with_density(every: usize) -> Vec<u8> {
+    let mut buf = String::with_capacity(BUFFER_SIZE);
+    let mut i = 1;
+    while buf.len() < BUFFER_SIZE {
+        if every != 0 && i % every == 0 {
+            buf.push_str(MATCH_LINE);
+        } else {
+            buf.push_str(CODE_LINE);
+        }
+        i += 1;
+    }
+    buf.into_bytes()
+}
+
+/// Worst case for the search: the buffer is full of prefixes of the pattern that never complete.
+fn near_matches_buffer() -> Vec<u8> {
+    let prefixes = ["C", "CO", "COD", "CODE", "CODES", "CODESY", "CODESYN"];
+    let mut buf = String::with_capacity(BUFFER_SIZE);
+    for prefix in prefixes.iter().cycle() {
+        if buf.len() >= BUFFER_SIZE {
+            break;
+        }
+        buf.push_str(prefix);
+    }
+    buf.into_bytes()
+}
+
+fn count_matches(mut haystack: &[u8], find: Finder) -> usize {
+    let mut count = 0;
+    while let Some(idx) = find(haystack) {
+        count += 1;
+        haystack = &haystack[idx + 1..];
+    }
+    count
+}
+
+fn search(c: &mut Criterion) {
+    let finders: [(&str, Finder); 2] = [
+        ("memchr", find_codesync_pattern),
+        ("kmp", find_codesync_pattern_kmp),
+    ];
+    let mut buffers = vec![];
+    for every in [0, 1000, 100, 10, 1] {
+        let name = if every == 0 {
+            "no-matches".to_string()
+        } else {
+            format!("1-in-{every}-lines")
+        };
+        buffers.push((name, buffer_with_density(every)));
+    }
+    buffers.push(("near-matches".to_string(), near_matches_buffer()));
+
+    let mut group = c.benchmark_group("search");
+    group.throughput(Throughput::Bytes(BUFFER_SIZE as u64));
+    for (name, buf) in &buffers {
+        for (finder, find) in finders {
+            group.bench_with_input(BenchmarkId::new(finder, name), buf, |b, buf| {
+                b.iter(|| count_matches(black_box(buf), find))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn parse_args(c: &mut Criterion) {
+    let matcher = Matcher::default();
+    let inputs = [
+        ("label", "(my-label) trailing text"),
+        ("label-and-count", "(my-label, 3) trailing text"),
+        ("extra-whitespace", "(  my-label  ,   12  ) trailing text"),
+        ("variable-count", "(my-label, $N_VARIANTS) trailing text"),
+        ("malformed", "my-label, 3) trailing text"),
+        ("missing-paren", "(my-label, 3 trailing text"),
+    ];
+    let mut group = c.benchmark_group("parse_args");
+    for (name, input) in inputs {
+        group.bench_with_input(BenchmarkId::from_parameter(name), input, |b, input| {
+            b.iter(|| matcher.parse_args(black_box(input)))
+        });
+    }
+    group.finish();
+}
+
+/// A single line packed with `markers` occurrences of the keyword, half of them rejected for being
+/// immediately followed by an identifier character.
+fn long_line(markers: usize) -> String {
+    let mut line = "// ".to_string();
+    for i in 0..markers {
+        line.push_str(if i % 2 == 0 {
+            "CODESYNCX "
+        } else {
+            "CODESYNC(some-label) "
+        });
+    }
+    line.push('\n');
+    line
+}
+
+/// Searching a line should take time linear in its length regardless of how many occurrences of
+/// the keyword it contains, so throughput should stay flat as the number of markers grows.
+fn long_lines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("long_line");
+    for markers in [10, 1_000, 100_000] {
+        let line = long_line(markers);
+        group.throughput(Throughput::Bytes(line.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(markers), &line, |b, line| {
+            b.iter(|| Matches::from_buffer(Path::new("file.rs"), black_box(line)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, search, parse_args, long_lines);
+criterion_main!(benches);
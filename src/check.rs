@@ -0,0 +1,122 @@
+//! Checks run on comments that were already collected, without searching again.
+//!
+//! [`check_count_override`] previews the count check on the comments sharing a label, e.g., to
+//! show in an editor what changing a count would report.
+
+use std::ops::Range;
+
+use crate::{count_units, Comment, CountSemantics, CountUnit};
+
+/// The kind of a [`Violation`]. Each kind corresponds to the code of a diagnostic reported by
+/// `codesync check`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    IncorrectCount,
+}
+
+impl ViolationKind {
+    /// The code of the corresponding diagnostic, e.g., `incorrect-count`.
+    pub fn code(self) -> &'static str {
+        match self {
+            ViolationKind::IncorrectCount => "incorrect-count",
+        }
+    }
+}
+
+/// A problem found by [`check_count_override`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// The span in bytes of the offending text within the file.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(kind: ViolationKind, span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+/// The violations the count check would report if every comment in `comments`, the comments
+/// sharing a label, had the explicit count `count`, e.g., to preview changing a count in an editor
+/// without searching again. Since all comments would have the same count, they're either all
+/// correct, returning no violations, or all reported with a violation of kind
+/// [`ViolationKind::IncorrectCount`] each. Violations are in the same order as `comments` and span
+/// the whole comment in its file, see [`Comment::file`].
+pub fn check_count_override(
+    comments: &[Comment],
+    count: u16,
+    semantics: CountSemantics,
+    unit: CountUnit,
+) -> Vec<Violation> {
+    let expected = semantics.total(count);
+    let found = count_units(comments, unit);
+    if found == expected as usize {
+        return vec![];
+    }
+    let unit = match (unit, expected) {
+        (CountUnit::Occurrence, 1) => "comment",
+        (CountUnit::Occurrence, _) => "comments",
+        (CountUnit::File, 1) => "file",
+        (CountUnit::File, _) => "files",
+    };
+    comments
+        .iter()
+        .map(|comment| {
+            let message = format!(
+                "expected {expected} {unit} with label `{}`, found {found}",
+                comment.label()
+            );
+            Violation::new(ViolationKind::IncorrectCount, comment.span(), message)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::Matches;
+
+    #[test]
+    fn count_override() {
+        let matches = Matches::from_buffer(
+            Path::new("file.rs"),
+            "// CODESYNC(a, 2)\n// CODESYNC(a, 3)\n// CODESYNC(a)\n",
+        );
+        let comments = &matches.group_by_label()["a"];
+        let check = |count, semantics| {
+            check_count_override(comments, count, semantics, CountUnit::Occurrence)
+        };
+
+        assert!(check(3, CountSemantics::Total).is_empty());
+        assert!(check(2, CountSemantics::Others).is_empty());
+
+        let under = check(4, CountSemantics::Total);
+        let spans: Vec<_> = under.iter().map(|v| v.span.clone()).collect();
+        assert_eq!(spans, [3..17, 21..35, 39..50]);
+        assert!(under
+            .iter()
+            .all(|v| v.kind == ViolationKind::IncorrectCount));
+        assert_eq!(
+            under[0].message,
+            "expected 4 comments with label `a`, found 3"
+        );
+
+        let over = check(1, CountSemantics::Total);
+        assert_eq!(over.len(), 3);
+        assert_eq!(
+            over[0].message,
+            "expected 1 comment with label `a`, found 3"
+        );
+
+        let files = check_count_override(comments, 1, CountSemantics::Total, CountUnit::File);
+        assert!(files.is_empty());
+    }
+}
@@ -0,0 +1,732 @@
+//! Project configuration read from a `codesync.toml` file.
+//!
+//! Only a small subset of TOML is supported: tables, `key = value` pairs, and comments. Values can
+//! be integers, booleans, strings, or arrays of those.
+//!
+//! Subdirectories can contain their own `codesync.toml` overriding style settings, e.g.,
+//! `label_pattern`, for the files under them. See [`ScopedConfigs`].
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Name of the configuration file looked up at the root of the project.
+pub const CONFIG_FILE: &str = "codesync.toml";
+
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// Counts that can be referenced as `$NAME` instead of writing a literal count, e.g.,
+    /// `CODESYNC(my-label, $NAME)`. Defined in the `[vars]` table. Only allowed in the root
+    /// configuration.
+    pub vars: HashMap<String, u16>,
+    /// Regex all labels must match, like `--label-pattern`.
+    pub label_pattern: Option<regex::Regex>,
+    /// Casing all labels must use, like `--consistent-casing`, e.g., `"kebab-case"`. The name is
+    /// validated by the caller, including by `codesync validate-config`.
+    pub consistent_casing: Option<String>,
+    /// Words written in lowercase in Title Case unless they're the first word, like
+    /// `--small-word`, e.g., `["of", "the"]`.
+    pub small_words: Option<Vec<String>>,
+    /// Keywords used instead of the default one in files matching a glob, as `(glob, keyword)`
+    /// pairs in the order they're defined, e.g., `"**/*.md" = "DOCSYNC"`. Defined in the
+    /// `[keywords]` table. Only allowed in the root configuration. See
+    /// [`crate::CollectOptions::keyword_for`].
+    pub keywords: Vec<(String, String)>,
+    /// Old labels mapped to the label they were renamed to, e.g., `old-label = "new-label"`.
+    /// Comments with an old label are counted with the new one. Defined in the `[aliases]` table.
+    /// Only allowed in the root configuration. See [`Config::canonical_label`].
+    pub aliases: HashMap<String, String>,
+    /// Level each diagnostic code is reported at, e.g., `missing-note = "warn"`, overriding its
+    /// default. Defined in the `[severity]` table. Only allowed in the root configuration. Codes
+    /// are validated by the caller.
+    pub severity: HashMap<String, Level>,
+}
+
+/// The level a diagnostic is reported at, see [`Config::severity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Report the diagnostic as an error, making the check fail.
+    Error,
+    /// Report the diagnostic as a warning, which doesn't make the check fail.
+    Warn,
+    /// Don't report the diagnostic.
+    Allow,
+}
+
+impl Level {
+    /// Parse a level written as `error`, `warn`, or `allow`.
+    pub fn parse(s: &str) -> Option<Level> {
+        match s {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "allow" => Some(Level::Allow),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Allow => "allow",
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file in `root` returning the default configuration if there's none.
+    pub fn load(root: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        match fs::read_to_string(root.as_ref().join(CONFIG_FILE)) {
+            Ok(contents) => Config::parse(&contents),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(ConfigError::Io(err)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, ConfigError> {
+        Config::parse_scoped(contents, false)
+    }
+
+    /// Parse a configuration. Nested configurations, i.e., the ones in subdirectories of the
+    /// project, can't define `[vars]`.
+    fn parse_scoped(contents: &str, nested: bool) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        for entry in parse_toml(contents)? {
+            config.add(entry, nested)?;
+        }
+        Ok(config)
+    }
+
+    /// The label comments with `label` are counted as, following aliases until reaching a label
+    /// that isn't renamed, e.g., `c` for `a` with `a = "b"` and `b = "c"`.
+    pub fn canonical_label<'a>(&'a self, mut label: &'a str) -> &'a str {
+        // Cycles are rejected when parsing so this terminates
+        while let Some(new) = self.aliases.get(label) {
+            label = new;
+        }
+        label
+    }
+
+    /// Override settings with the ones defined in a `nested` configuration.
+    fn merge(&mut self, nested: Config) {
+        self.vars.extend(nested.vars);
+        if nested.label_pattern.is_some() {
+            self.label_pattern = nested.label_pattern;
+        }
+        if nested.consistent_casing.is_some() {
+            self.consistent_casing = nested.consistent_casing;
+        }
+        if nested.small_words.is_some() {
+            self.small_words = nested.small_words;
+        }
+    }
+
+    /// Check the configuration file in `root` and the nested ones under it, see
+    /// [`nested_config_dirs`], returning all problems found instead of stopping at the first one. A
+    /// missing file is valid. Syntax errors stop validation of their file since the rest of it
+    /// can't be interpreted reliably.
+    pub fn validate(root: impl AsRef<Path>) -> Vec<ConfigError> {
+        let root = root.as_ref();
+        let mut errors = Config::validate_file(root, false);
+        for dir in nested_config_dirs(root) {
+            errors.extend(Config::validate_file(&dir, true).into_iter().map(|err| {
+                ConfigError::Nested {
+                    dir: dir.clone(),
+                    err: Box::new(err),
+                }
+            }));
+        }
+        errors
+    }
+
+    fn validate_file(dir: &Path, nested: bool) -> Vec<ConfigError> {
+        let contents = match fs::read_to_string(dir.join(CONFIG_FILE)) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return vec![],
+            Err(err) => return vec![ConfigError::Io(err)],
+        };
+        let entries = match parse_toml(&contents) {
+            Ok(entries) => entries,
+            Err(err) => return vec![err],
+        };
+        let mut config = Config::default();
+        entries
+            .into_iter()
+            .filter_map(|entry| config.add(entry, nested).err())
+            .collect()
+    }
+
+    fn add(&mut self, entry: Entry, nested: bool) -> Result<(), ConfigError> {
+        match (entry.table.as_str(), entry.value) {
+            ("vars", _) if nested => Err(ConfigError::syntax(
+                entry.line,
+                "`[vars]` can only be defined in the root configuration",
+            )),
+            ("vars", Value::Integer(n)) => {
+                let n = u16::try_from(n).map_err(|_| {
+                    ConfigError::syntax(
+                        entry.line,
+                        format!("`{}` must be between 0 and {}", entry.key, u16::MAX),
+                    )
+                })?;
+                self.vars.insert(entry.key, n);
+                Ok(())
+            }
+            ("vars", _) => Err(ConfigError::syntax(
+                entry.line,
+                format!("`{}` must be an integer", entry.key),
+            )),
+            ("keywords", _) if nested => Err(ConfigError::syntax(
+                entry.line,
+                "`[keywords]` can only be defined in the root configuration",
+            )),
+            ("keywords", Value::String(keyword))
+                if !keyword.is_empty() && !keyword.contains(['\n', '\r']) =>
+            {
+                self.keywords.push((entry.key, keyword));
+                Ok(())
+            }
+            ("keywords", _) => Err(ConfigError::syntax(
+                entry.line,
+                format!("keyword for `{}` must be a non-empty string", entry.key),
+            )),
+            ("aliases", _) if nested => Err(ConfigError::syntax(
+                entry.line,
+                "`[aliases]` can only be defined in the root configuration",
+            )),
+            ("aliases", Value::String(new)) if !new.is_empty() => {
+                // Aliases without cycles lead every label to a label that isn't an alias, so the
+                // new one can only create a cycle if that's its own key, including `a = "a"`
+                if self.aliases.contains_key(&entry.key) {
+                    return Err(ConfigError::syntax(
+                        entry.line,
+                        format!("alias for `{}` is defined more than once", entry.key),
+                    ));
+                }
+                if self.canonical_label(&new) == entry.key {
+                    return Err(ConfigError::syntax(
+                        entry.line,
+                        format!("aliasing `{}` to `{new}` creates a cycle", entry.key),
+                    ));
+                }
+                self.aliases.insert(entry.key, new);
+                Ok(())
+            }
+            ("aliases", _) => Err(ConfigError::syntax(
+                entry.line,
+                format!("alias for `{}` must be a non-empty string", entry.key),
+            )),
+            ("severity", _) if nested => Err(ConfigError::syntax(
+                entry.line,
+                "`[severity]` can only be defined in the root configuration",
+            )),
+            ("severity", Value::String(level)) if Level::parse(&level).is_some() => {
+                self.severity
+                    .insert(entry.key, Level::parse(&level).unwrap());
+                Ok(())
+            }
+            ("severity", _) => Err(ConfigError::syntax(
+                entry.line,
+                format!(
+                    "level for `{}` must be one of \"error\", \"warn\", or \"allow\"",
+                    entry.key
+                ),
+            )),
+            ("", Value::String(pattern)) if entry.key == "label_pattern" => {
+                let re = regex::Regex::new(&pattern).map_err(|err| {
+                    ConfigError::syntax(entry.line, format!("invalid `label_pattern`: {err}"))
+                })?;
+                self.label_pattern = Some(re);
+                Ok(())
+            }
+            ("", Value::String(case)) if entry.key == "consistent_casing" => {
+                self.consistent_casing = Some(case);
+                Ok(())
+            }
+            ("", Value::Array(words)) if entry.key == "small_words" => {
+                let words = words
+                    .into_iter()
+                    .map(|word| match word {
+                        Value::String(word) => Ok(word),
+                        _ => Err(ConfigError::syntax(
+                            entry.line,
+                            "`small_words` must be an array of strings",
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?;
+                self.small_words = Some(words);
+                Ok(())
+            }
+            ("", _) if entry.key == "small_words" => Err(ConfigError::syntax(
+                entry.line,
+                "`small_words` must be an array of strings",
+            )),
+            ("", _) if entry.key == "label_pattern" || entry.key == "consistent_casing" => Err(
+                ConfigError::syntax(entry.line, format!("`{}` must be a string", entry.key)),
+            ),
+            ("", _) => Err(ConfigError::syntax(
+                entry.line,
+                format!("unknown key `{}`", entry.key),
+            )),
+            (table, _) => Err(ConfigError::syntax(
+                entry.line,
+                format!("unknown table `[{table}]`"),
+            )),
+        }
+    }
+}
+
+/// The effective configuration of each directory in a project. A `codesync.toml` in a
+/// subdirectory overrides the settings inherited from its parent directories for all files under
+/// it, e.g., to require a stricter `label_pattern`. Configurations are loaded lazily the first time
+/// a file in their directory is looked up.
+#[derive(Debug)]
+pub struct ScopedConfigs {
+    root: PathBuf,
+    /// Effective configuration for every directory looked up so far, including the root
+    configs: HashMap<PathBuf, Config>,
+    /// Whether each directory looked up by [`ScopedConfigs::scope_dir`] has its own configuration
+    has_config: HashMap<PathBuf, bool>,
+}
+
+impl ScopedConfigs {
+    /// Load the configuration at the root of the project.
+    pub fn new(root: impl AsRef<Path>) -> Result<ScopedConfigs, ConfigError> {
+        let root = root.as_ref().to_path_buf();
+        let config = Config::load(&root)?;
+        Ok(ScopedConfigs {
+            configs: HashMap::from([(root.clone(), config)]),
+            has_config: HashMap::new(),
+            root,
+        })
+    }
+
+    /// The configuration at the root of the project.
+    pub fn root(&self) -> &Config {
+        &self.configs[&self.root]
+    }
+
+    /// The effective configuration for a file, i.e., the root configuration merged with the ones
+    /// in every directory between the root and the file. Files outside the root use the root
+    /// configuration.
+    pub fn for_file(&mut self, path: &Path) -> Result<&Config, ConfigError> {
+        let mut pending = vec![];
+        let mut dir = path.parent();
+        let mut current = loop {
+            match dir {
+                Some(d) if self.configs.contains_key(d) => break d.to_path_buf(),
+                Some(d) if d.starts_with(&self.root) => {
+                    pending.push(d.to_path_buf());
+                    dir = d.parent();
+                }
+                _ => {
+                    pending.clear();
+                    break self.root.clone();
+                }
+            }
+        };
+        for dir in pending.into_iter().rev() {
+            let mut config = self.configs[&current].clone();
+            config.merge(load_nested(&dir)?);
+            self.configs.insert(dir.clone(), config);
+            current = dir;
+        }
+        Ok(&self.configs[&current])
+    }
+
+    /// The closest directory between a file and the root with its own `codesync.toml`, or the
+    /// root if there's none. Used to split counts by directory, e.g., to check each plugin of a
+    /// project independently.
+    pub fn scope_dir(&mut self, path: &Path) -> PathBuf {
+        let mut dir = path.parent();
+        while let Some(d) = dir.filter(|d| d.starts_with(&self.root) && *d != self.root) {
+            let has_config = *self
+                .has_config
+                .entry(d.to_path_buf())
+                .or_insert_with(|| d.join(CONFIG_FILE).is_file());
+            if has_config {
+                return d.to_path_buf();
+            }
+            dir = d.parent();
+        }
+        self.root.clone()
+    }
+}
+
+/// Directories strictly under `root` with their own `codesync.toml`, i.e., the nested
+/// configurations [`ScopedConfigs`] may load. Hidden and ignored directories are skipped like when
+/// collecting with the default options. Sorted by path.
+pub fn nested_config_dirs(root: impl AsRef<Path>) -> Vec<PathBuf> {
+    let root = root.as_ref();
+    let mut dirs: Vec<_> = ignore::WalkBuilder::new(root)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.depth() > 1 && entry.file_name() == CONFIG_FILE)
+        .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// The configuration defined in `dir` by its own `codesync.toml`, without the settings inherited
+/// from its parent directories.
+pub fn load_nested(dir: &Path) -> Result<Config, ConfigError> {
+    let in_dir = |err| ConfigError::Nested {
+        dir: dir.to_path_buf(),
+        err: Box::new(err),
+    };
+    match fs::read_to_string(dir.join(CONFIG_FILE)) {
+        Ok(contents) => Config::parse_scoped(&contents, true).map_err(in_dir),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(in_dir(ConfigError::Io(err))),
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Syntax {
+        line: usize,
+        msg: String,
+    },
+    /// An error in the configuration of a subdirectory.
+    Nested {
+        dir: PathBuf,
+        err: Box<ConfigError>,
+    },
+}
+
+impl ConfigError {
+    fn syntax(line: usize, msg: impl Into<String>) -> Self {
+        ConfigError::Syntax {
+            line,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read `{CONFIG_FILE}`: {err}"),
+            ConfigError::Syntax { line, msg } => write!(f, "{CONFIG_FILE}:{line}: {msg}"),
+            ConfigError::Nested { dir, err } => write!(f, "in `{}`: {err}", dir.display()),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Integer(i64),
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+}
+
+/// A `key = value` pair in a table. Keys before any table header belong to the table `""`.
+struct Entry {
+    table: String,
+    key: String,
+    value: Value,
+    /// 1-based line number where the entry is defined
+    line: usize,
+}
+
+fn parse_toml(contents: &str) -> Result<Vec<Entry>, ConfigError> {
+    let mut entries = vec![];
+    let mut table = String::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line_no = i + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                return Err(ConfigError::syntax(line_no, "unclosed table header"));
+            };
+            table = name.trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(ConfigError::syntax(line_no, "expected `key = value`"));
+        };
+        let key = key.trim().trim_matches('"').to_string();
+        let value = parse_value(value.trim())
+            .ok_or_else(|| ConfigError::syntax(line_no, format!("invalid value for `{key}`")))?;
+        entries.push(Entry {
+            table: table.clone(),
+            key,
+            value,
+            line: line_no,
+        });
+    }
+    Ok(entries)
+}
+
+/// Remove a trailing `#` comment taking care of not stripping `#` inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if let Some(inner) = s.strip_prefix('[') {
+        let inner = inner.strip_suffix(']')?.trim();
+        let inner = inner.strip_suffix(',').unwrap_or(inner);
+        if inner.trim().is_empty() {
+            return Some(Value::Array(vec![]));
+        }
+        return split_array(inner)
+            .into_iter()
+            .map(|item| parse_value(item.trim()))
+            .collect::<Option<_>>()
+            .map(Value::Array);
+    }
+    if let Some(inner) = s.strip_prefix('"') {
+        return Some(Value::String(unescape(inner.strip_suffix('"')?)?));
+    }
+    match s {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => s.replace('_', "").parse().ok().map(Value::Integer),
+    }
+}
+
+/// Split the items of an array on commas outside strings.
+fn split_array(s: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\\' if in_string && !escaped => {
+                escaped = true;
+                continue;
+            }
+            '"' if !escaped => in_string = !in_string,
+            ',' if !in_string => {
+                items.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    items.push(&s[start..]);
+    items
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            't' => result.push('\t'),
+            '\\' => result.push('\\'),
+            '"' => result.push('"'),
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_vars() {
+        let config =
+            Config::parse("# counts\n[vars]\nN_VARIANTS = 3 # enum variants\nOTHER = 1_0\n")
+                .unwrap();
+        assert_eq!(config.vars.get("N_VARIANTS"), Some(&3));
+        assert_eq!(config.vars.get("OTHER"), Some(&10));
+    }
+
+    #[test]
+    fn reject_invalid_vars() {
+        assert!(Config::parse("[vars]\nN = \"3\"\n").is_err());
+        assert!(Config::parse("[vars]\nN = 70000\n").is_err());
+        assert!(Config::parse("[unknown]\nN = 1\n").is_err());
+    }
+
+    #[test]
+    fn parse_keywords() {
+        let config =
+            Config::parse("[keywords]\n\"**/*.md\" = \"DOCSYNC\"\n\"docs/**\" = \"SYNC\"\n")
+                .unwrap();
+        assert_eq!(
+            config.keywords,
+            vec![
+                ("**/*.md".to_string(), "DOCSYNC".to_string()),
+                ("docs/**".to_string(), "SYNC".to_string()),
+            ]
+        );
+        assert!(Config::parse("[keywords]\n\"*.md\" = \"\"\n").is_err());
+        assert!(Config::parse_scoped("[keywords]\n\"*.md\" = \"DOCSYNC\"\n", true).is_err());
+    }
+
+    #[test]
+    fn parse_aliases() {
+        let config = Config::parse("[aliases]\na = \"b\"\nb = \"c\"\n").unwrap();
+        assert_eq!(config.canonical_label("a"), "c");
+        assert_eq!(config.canonical_label("b"), "c");
+        assert_eq!(config.canonical_label("c"), "c");
+        assert!(Config::parse("[aliases]\na = \"b\"\nb = \"a\"\n").is_err());
+        assert!(Config::parse("[aliases]\na = \"a\"\n").is_err());
+        assert!(Config::parse("[aliases]\na = \"b\"\nb = \"c\"\na = \"a\"\n").is_err());
+        assert!(Config::parse("[aliases]\na = \"b\"\na = \"c\"\n").is_err());
+        assert!(Config::parse("[aliases]\na = 1\n").is_err());
+        assert!(Config::parse_scoped("[aliases]\na = \"b\"\n", true).is_err());
+    }
+
+    #[test]
+    fn parse_small_words() {
+        let config = Config::parse("small_words = [\"of\", \"the\"]\n").unwrap();
+        assert_eq!(config.small_words.unwrap(), ["of", "the"]);
+        assert!(Config::parse("small_words = \"of\"\n").is_err());
+        assert!(Config::parse("small_words = [1]\n").is_err());
+    }
+
+    #[test]
+    fn parse_severity() {
+        let config =
+            Config::parse("[severity]\nmissing-note = \"warn\"\nmalformed = \"allow\"\n").unwrap();
+        assert_eq!(config.severity["missing-note"], Level::Warn);
+        assert_eq!(config.severity["malformed"], Level::Allow);
+        assert!(Config::parse("[severity]\nmalformed = \"deny\"\n").is_err());
+        assert!(Config::parse("[severity]\nmalformed = 1\n").is_err());
+        assert!(Config::parse_scoped("[severity]\nmalformed = \"warn\"\n", true).is_err());
+    }
+
+    #[test]
+    fn validate_reports_all_problems() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE),
+            "unknown = 1\n[vars]\nN = 1\nM = \"2\"\n",
+        )
+        .unwrap();
+        let lines: Vec<_> = Config::validate(dir.path())
+            .into_iter()
+            .map(|err| match err {
+                ConfigError::Syntax { line, .. } => line,
+                err => panic!("{err}"),
+            })
+            .collect();
+        assert_eq!(lines, vec![1, 4]);
+        assert!(Config::validate(dir.path().join("missing")).is_empty());
+
+        fs::write(dir.path().join(CONFIG_FILE), "").unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("a/b").join(CONFIG_FILE), "[vars]\nN = 1\n").unwrap();
+        assert_eq!(nested_config_dirs(dir.path()), vec![dir.path().join("a/b")]);
+        match &Config::validate(dir.path())[..] {
+            [ConfigError::Nested { dir: nested, err }] => {
+                assert_eq!(*nested, dir.path().join("a/b"));
+                assert!(matches!(**err, ConfigError::Syntax { line: 2, .. }));
+            }
+            errors => panic!("{errors:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_style_settings() {
+        let config =
+            Config::parse("label_pattern = \"^[a-z-]+$\"\nconsistent_casing = \"kebab-case\"\n")
+                .unwrap();
+        assert!(config.label_pattern.unwrap().is_match("my-label"));
+        assert_eq!(config.consistent_casing.as_deref(), Some("kebab-case"));
+        assert!(Config::parse("label_pattern = \"(\"\n").is_err());
+        assert!(Config::parse("consistent_casing = 1\n").is_err());
+    }
+
+    #[test]
+    fn scoped_configs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("strict/inner")).unwrap();
+        fs::create_dir_all(root.join("loose")).unwrap();
+        fs::write(
+            root.join(CONFIG_FILE),
+            "consistent_casing = \"kebab-case\"\n[vars]\nN = 3\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("strict").join(CONFIG_FILE),
+            "label_pattern = \"^strict-\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("loose").join(CONFIG_FILE), "[vars]\nM = 1\n").unwrap();
+
+        let mut configs = ScopedConfigs::new(root).unwrap();
+        assert_eq!(configs.root().vars.get("N"), Some(&3));
+
+        let config = configs.for_file(&root.join("a.rs")).unwrap();
+        assert!(config.label_pattern.is_none());
+
+        let config = configs.for_file(&root.join("strict/inner/a.rs")).unwrap();
+        assert!(config
+            .label_pattern
+            .as_ref()
+            .unwrap()
+            .is_match("strict-label"));
+        assert_eq!(config.consistent_casing.as_deref(), Some("kebab-case"));
+
+        assert!(matches!(
+            configs.for_file(&root.join("loose/a.rs")),
+            Err(ConfigError::Nested { .. })
+        ));
+    }
+
+    #[test]
+    fn scope_dirs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("plugins/a/src")).unwrap();
+        fs::create_dir_all(root.join("plugins/b")).unwrap();
+        fs::write(root.join("plugins/a").join(CONFIG_FILE), "").unwrap();
+
+        let mut configs = ScopedConfigs::new(root).unwrap();
+        assert_eq!(
+            configs.scope_dir(&root.join("plugins/a/src/lib.rs")),
+            root.join("plugins/a")
+        );
+        assert_eq!(configs.scope_dir(&root.join("plugins/b/lib.rs")), root);
+        assert_eq!(configs.scope_dir(&root.join("a.rs")), root);
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(
+            parse_value(r#"["a, b", "c\"d", 1, true]"#),
+            Some(Value::Array(vec![
+                Value::String("a, b".to_string()),
+                Value::String("c\"d".to_string()),
+                Value::Integer(1),
+                Value::Bool(true),
+            ]))
+        );
+        assert_eq!(parse_value("[]"), Some(Value::Array(vec![])));
+        assert_eq!(parse_value("nope"), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Longer explanations for the codes attached to diagnostics, printed with `--explain <CODE>`.
+
+/// Explanation for each diagnostic code, sorted by code.
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "canonical-spacing",
+        "A comment's arguments aren't written with canonical spacing: exactly one space after the \
+comma and no whitespace between the keyword and the opening parenthesis, after the opening \
+parenthesis, before the comma, or before the closing parenthesis, e.g., `CODESYNC(my-label, 2)`. \
+Consistent spacing makes comments easier to search for. Run `codesync fix --spacing` to fix it. \
+Enabled with `--canonical-spacing`.",
+    ),
+    (
+        "case-collision",
+        "Two distinct labels only differ in casing or word separators, e.g., `MyLabel` and \
+`my-label`. Labels are compared exactly, so such comments are counted separately even though \
+they most likely refer to the same thing. Pick one spelling and use it in every comment. Enabled \
+with `--warn-case-collisions`.",
+    ),
+    (
+        "consistent-casing",
+        "A label doesn't use the casing required with `--consistent-casing` or the \
+`consistent_casing` setting in `codesync.toml`. Using a single casing makes labels easier to \
+search for. Rename the label as suggested in the diagnostic.",
+    ),
+    (
+        "count-not-on-first",
+        "An explicit count is written on a comment other than the first occurrence of its label, \
+ordered by file path and then position. Keeping the count on the first occurrence makes it easy to \
+find. Move the count to the first comment. Enabled with `--count-on-first`.",
+    ),
+    (
+        "deprecated-label",
+        "A comment uses a label renamed in the `[aliases]` table of `codesync.toml`. The comment \
+is counted with the new label, so a label can be renamed incrementally, but the alias is meant to \
+be temporary. Rename the comment to the new label and remove the alias once no comment uses it.",
+    ),
+    (
+        "empty",
+        "No codesync comments were found. This usually means codesync ran in the wrong directory \
+or the files containing comments are ignored, e.g., by `.gitignore`. Run codesync at the root of \
+the project or try `--no-default-ignores`. Enabled with `--fail-on-empty`.",
+    ),
+    (
+        "empty-label",
+        "A comment has an empty label, e.g., `CODESYNC()` or `CODESYNC(, 2)`. All comments with \
+an empty label would be counted together, which is rarely intended. Write a descriptive label or \
+pass `--allow-empty-label`.",
+    ),
+    (
+        "extra-whitespace",
+        "There's whitespace between the keyword and the opening parenthesis or around an argument, \
+e.g., `CODESYNC (my-label)` or `CODESYNC( my-label )`. The whitespace is ignored but makes \
+comments harder to search for. Remove it. Enabled with `--no-extra-whitespace`.",
+    ),
+    (
+        "forbidden-label",
+        "A comment uses a label passed to `--forbid-label`, typically a placeholder like `TODO` \
+used while drafting a change. Replace it with a descriptive label before merging.",
+    ),
+    (
+        "incorrect-count",
+        "The number of comments with a label doesn't match their count, or comments with the same \
+label specify different counts. This is the main check of codesync: it means a comment was added \
+or removed, so code that must be kept in sync may have been missed. Review every comment with the \
+label (`codesync show <LABEL>`), update the code that's out of sync, and fix the count.",
+    ),
+    (
+        "invalid-count",
+        "The second argument of a comment isn't a count or a note. A count must be an integer \
+between 0 and 65535 or a `$NAME` variable defined in `codesync.toml`, e.g., \
+`CODESYNC(my-label, 3)`. With `--strict-trailing-comma`, a comma followed by an empty count, e.g., \
+`CODESYNC(my-label,)`, is also reported; otherwise such a comment is read as having no count.",
+    ),
+    (
+        "label-length",
+        "A label is longer than the limit given with `--max-label-length`. Long labels often \
+contain prose that belongs in the rest of the comment. Shorten the label.",
+    ),
+    (
+        "label-pattern",
+        "A label doesn't match the regex given with `--label-pattern` or the `label_pattern` \
+setting in `codesync.toml`. Rename the label to follow the project's conventions.",
+    ),
+    (
+        "malformed",
+        "The keyword appears without valid arguments, e.g., `CODESYNC` alone or `CODESYNC(label` \
+without a closing parenthesis. Every occurrence of the keyword must be followed by a label in \
+parentheses, optionally followed by a count and a note in double quotes, e.g., \
+`CODESYNC(my-label)` or `CODESYNC(my-label, 3, \"keep in sync\")`. \
+Brackets can be used instead of parentheses, e.g., `CODESYNC[my-label]`, but not mixed with them. \
+Arguments must be on the same line as the keyword and cannot contain the keyword again, as in \
+`CODESYNC(CODESYNC(my-label))`. With `--bare-labels`, a label can also be \
+written without parentheses, e.g., `CODESYNC my-label`.",
+    ),
+    (
+        "missing-note",
+        "A comment has no note, or an empty one, and `--require-note` is given. Notes explain \
+what must be kept in sync and why. Add the note as the last argument between double quotes with \
+`, \"explanation\"`, e.g., `CODESYNC(my-label, 2, \"update the parser when adding a token\")` or \
+`CODESYNC(my-label, \"update the parser when adding a token\")`. Notes cannot contain double \
+quotes or closing parentheses.",
+    ),
+    (
+        "mixed-separators",
+        "Labels only differ in word separators, e.g., `my-label` and `my_label`. They are counted \
+together because of `--normalize-separators`, but mixing spellings makes labels harder to search \
+for. Pick one spelling and use it in every comment.",
+    ),
+    (
+        "numeric-label",
+        "A label only has digits, e.g., `CODESYNC(42)`. A single argument is always the label, so \
+this was most likely meant as a count with a missing label, e.g., `CODESYNC(my-label, 42)`. Add \
+the label or, if numeric labels are intended, pass `--allow-numeric-label`. This is a warning and \
+doesn't make the check fail.",
+    ),
+    (
+        "undefined-var",
+        "A count references a variable, e.g., `CODESYNC(my-label, $N)`, that isn't defined in the \
+`[vars]` table of `codesync.toml`. Define the variable or use a literal count.",
+    ),
+    (
+        "unused-suppression",
+        "A `codesync:allow(...)` directive didn't suppress any diagnostic, e.g., because the \
+comment was fixed or the check isn't enabled. Remove the directive, or the codes in it that are no \
+longer reported, so suppressions don't go stale. This is a warning and doesn't make the check fail.",
+    ),
+];
+
+/// The explanation for a diagnostic code, or `None` if the code is unknown.
+pub fn explanation(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .binary_search_by_key(&code, |(c, _)| c)
+        .ok()
+        .map(|i| EXPLANATIONS[i].1)
+}
+
+/// All codes with an explanation.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_code_is_explained() {
+        assert!(EXPLANATIONS.windows(2).all(|w| w[0].0 < w[1].0));
+        let source = include_str!("main.rs");
+        for (i, _) in source.match_indices(".with_code(\"") {
+            let rest = &source[i + ".with_code(\"".len()..];
+            let code = &rest[..rest.find('"').unwrap()];
+            assert!(
+                explanation(code).is_some(),
+                "missing explanation for `{code}`"
+            );
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Suggested repairs for common typos in codesync comments, applied by `codesync fix`.
+//!
+//! Some typos make a match invalid, e.g., a missing closing parenthesis, while others produce a
+//! valid comment with a suspicious label, e.g., `CODESYNC(my-label; 2)` has label `my-label; 2`.
+//! Both are considered.
+//!
+//! Fixes only replace bytes from the keyword through the end of the arguments, so the delimiters
+//! of the surrounding source comment, e.g., `/*` and `*/`, are never changed.
+
+use std::ops::Range;
+
+use codesync::{ArgsError, Comment, InvalidMatch};
+
+/// A replacement for a span of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub span: Range<usize>,
+    pub replacement: String,
+    /// Whether the fix is very likely what was intended, in which case it's applied with `--yes`.
+    pub confident: bool,
+}
+
+/// Closing comment delimiters that may follow the arguments on the same line.
+const COMMENT_CLOSERS: &[&str] = &["*/", "-->", "-}", "*)"];
+
+/// Brackets mistakenly used instead of parentheses, as `(opening, closing)`.
+const WRONG_BRACKETS: &[(char, char)] = &[('{', '}'), ('（', '）')];
+
+/// Suggest a fix for an invalid match in `source`, the contents of the match's file.
+pub fn suggest_for_invalid(source: &str, m: &InvalidMatch) -> Option<Fix> {
+    let after_keyword = m.span().end;
+    let line_end = source[after_keyword..]
+        .find('\n')
+        .map_or(source.len(), |i| after_keyword + i);
+    match m.error {
+        ArgsError::MissingClosingParen { start, end }
+        | ArgsError::MissingClosingBracket { start, end } => {
+            let args = &source[start..end];
+            let args = COMMENT_CLOSERS
+                .iter()
+                .filter_map(|closer| args.find(closer).map(|i| &args[..i]))
+                .min_by_key(|args| args.len())
+                .unwrap_or(args)
+                .trim_end();
+            let pos = start + args.len();
+            let close = if args.starts_with('[') { "]" } else { ")" };
+            Some(Fix {
+                span: pos..pos,
+                replacement: close.to_string(),
+                confident: true,
+            })
+        }
+        // Drop the trailing comma and whitespace, e.g., `CODESYNC(foo, )` to `CODESYNC(foo)`
+        ArgsError::EmptyCount { start, end } => Some(Fix {
+            span: start..end,
+            replacement: String::new(),
+            confident: true,
+        }),
+        ArgsError::MismatchedBrackets { start, end } => {
+            let close = if source[start..].starts_with('[') {
+                "]"
+            } else {
+                ")"
+            };
+            Some(Fix {
+                span: end - 1..end,
+                replacement: close.to_string(),
+                confident: true,
+            })
+        }
+        ArgsError::Malformed => {
+            let rest = &source[after_keyword..line_end];
+            let gap = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+            let mut chars = rest[gap..].chars();
+            let open = chars.next()?;
+            let (_, close) = WRONG_BRACKETS.iter().find(|(o, _)| *o == open)?;
+            let inner_start = after_keyword + gap + open.len_utf8();
+            let inner_len = source[inner_start..line_end].find(*close)?;
+            let inner = &source[inner_start..inner_start + inner_len];
+            Some(Fix {
+                span: after_keyword + gap..inner_start + inner_len + close.len_utf8(),
+                replacement: format!("({inner})"),
+                confident: true,
+            })
+        }
+        ArgsError::InvalidCount { .. }
+        | ArgsError::CountOutOfRange { .. }
+        | ArgsError::NestedKeyword { .. } => None,
+    }
+}
+
+/// Suggest a fix for a valid comment whose label looks like a typo, e.g., a count separated with
+/// `;` or a label wrapped in smart quotes. The fix only replaces bytes in
+/// [`Comment::label_byte_range`].
+pub fn suggest_for_comment(comment: Comment) -> Option<Fix> {
+    if comment.count_arg().is_some() {
+        return None;
+    }
+    let raw = comment.label_arg().raw();
+    let start = comment.label_byte_range().start;
+
+    // `CODESYNC(my-label; 2)`
+    if let Some((label, count)) = raw.split_once(';') {
+        if !label.trim().is_empty() && is_count(count.trim()) {
+            let pos = start + label.len();
+            return Some(Fix {
+                span: pos..pos + 1,
+                replacement: ",".to_string(),
+                confident: true,
+            });
+        }
+    }
+
+    // `CODESYNC(my-label 2)`
+    let trimmed = raw.trim_end();
+    if let Some(i) = trimmed.rfind([' ', '\t']) {
+        if !trimmed[..i].trim().is_empty() && is_count(&trimmed[i + 1..]) {
+            let pos = start + trimmed[..i].trim_end().len();
+            return Some(Fix {
+                span: pos..pos,
+                replacement: ",".to_string(),
+                confident: false,
+            });
+        }
+    }
+
+    // `CODESYNC(“my-label”)`
+    let label = comment.label();
+    for (open, close) in [('“', '”'), ('‘', '’')] {
+        if let Some(inner) = label.strip_prefix(open).and_then(|l| l.strip_suffix(close)) {
+            let offset = raw.find(open)?;
+            let span_start = start + offset;
+            return Some(Fix {
+                span: span_start..span_start + label.len(),
+                replacement: inner.to_string(),
+                confident: false,
+            });
+        }
+    }
+    None
+}
+
+/// Suggest a fix giving a comment canonical spacing, see [`Comment::spacing_edits`]. `source` is
+/// the contents of the comment's file. The arguments are rewritten in their [canonical] form, which
+/// also normalizes counts, e.g., `CODESYNC( foo,03)` becomes `CODESYNC(foo, 3)`, while notes are
+/// kept as written between their quotes.
+///
+/// [canonical]: codesync::Arg::canonical
+pub fn suggest_spacing(source: &str, comment: Comment) -> Option<Fix> {
+    if comment.spacing_edits().is_empty() {
+        return None;
+    }
+    let span = comment.span();
+    let label = comment.label_arg();
+    // Everything before the label is the keyword, any whitespace, and the opening bracket
+    let prefix = &source[span.start..label.span().start];
+    let open = prefix.chars().last()?;
+    let keyword = prefix[..prefix.len() - open.len_utf8()].trim_end();
+    let close = source[span.clone()].chars().last()?;
+    let count = comment
+        .count_arg()
+        .map(|count| format!(", {}", count.canonical()))
+        .unwrap_or_default();
+    let note = comment
+        .note_arg()
+        .map(|note| format!(", \"{}\"", note.value()))
+        .unwrap_or_default();
+    Some(Fix {
+        replacement: format!("{keyword}{open}{}{count}{note}{close}", label.canonical()),
+        span,
+        confident: true,
+    })
+}
+
+/// Whether `s` looks like a count, i.e., an integer or a `$NAME` variable.
+fn is_count(s: &str) -> bool {
+    match s.strip_prefix('$') {
+        Some(name) => !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'),
+        None => !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+/// Whether two fixes replace some of the same bytes, in which case only one of them can be applied.
+/// Insertions at the same offset don't overlap.
+pub fn overlaps(a: &Fix, b: &Fix) -> bool {
+    a.span.start < b.span.end && b.span.start < a.span.end
+}
+
+/// Apply fixes to `source`.
+///
+/// # Panics
+///
+/// If two fixes overlap, see [`overlaps`].
+pub fn apply(source: &str, fixes: &mut [Fix]) -> String {
+    fixes.sort_by_key(|fix| fix.span.start);
+    let mut result = String::with_capacity(source.len());
+    let mut last = 0;
+    for fix in fixes.iter() {
+        assert!(fix.span.start >= last, "fixes must not overlap");
+        result.push_str(&source[last..fix.span.start]);
+        result.push_str(&fix.replacement);
+        last = fix.span.end;
+    }
+    result.push_str(&source[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use codesync::Matches;
+
+    use super::*;
+
+    /// Apply the fix suggested for the first match in `line`, returning the fixed line and whether
+    /// the fix is confident.
+    fn fix(line: &str) -> Option<(String, bool)> {
+        let matches = Matches::from_buffer(Path::new("file.rs"), line);
+        let fix = match matches.invalid_matches().next() {
+            Some(m) => suggest_for_invalid(line, &m),
+            None => suggest_for_comment(matches.comments().next()?),
+        }?;
+        let confident = fix.confident;
+        Some((apply(line, &mut [fix]), confident))
+    }
+
+    #[test]
+    fn fixes_invalid_matches() {
+        assert_eq!(
+            fix("// CODESYNC(foo, 2\n"),
+            Some(("// CODESYNC(foo, 2)\n".to_string(), true))
+        );
+        assert_eq!(
+            fix("/* CODESYNC(foo */"),
+            Some(("/* CODESYNC(foo) */".to_string(), true))
+        );
+        assert_eq!(
+            fix("// CODESYNC{foo, 2} text"),
+            Some(("// CODESYNC(foo, 2) text".to_string(), true))
+        );
+        assert_eq!(
+            fix("// CODESYNC（foo）"),
+            Some(("// CODESYNC(foo)".to_string(), true))
+        );
+        assert_eq!(
+            fix("// CODESYNC[foo, 2)"),
+            Some(("// CODESYNC[foo, 2]".to_string(), true))
+        );
+        assert_eq!(
+            fix("// CODESYNC[foo"),
+            Some(("// CODESYNC[foo]".to_string(), true))
+        );
+        assert_eq!(fix("// CODESYNC(foo, x)"), None);
+        assert_eq!(fix("// CODESYNC"), None);
+    }
+
+    #[test]
+    fn fixes_spacing() {
+        let fix = |line: &str| {
+            let matches = Matches::from_buffer(Path::new("file.rs"), line);
+            let fix = suggest_spacing(line, matches.comments().next()?)?;
+            Some(apply(line, &mut [fix]))
+        };
+        assert_eq!(
+            fix("// CODESYNC(foo,2)"),
+            Some("// CODESYNC(foo, 2)".to_string())
+        );
+        assert_eq!(
+            fix("// CODESYNC(foo,  2)"),
+            Some("// CODESYNC(foo, 2)".to_string())
+        );
+        assert_eq!(
+            fix("// CODESYNC( foo, 2 )"),
+            Some("// CODESYNC(foo, 2)".to_string())
+        );
+        assert_eq!(
+            fix("// CODESYNC [ foo ] x"),
+            Some("// CODESYNC[foo] x".to_string())
+        );
+        assert_eq!(
+            fix("/* CODESYNC( foo ,$N ) */"),
+            Some("/* CODESYNC(foo, $N) */".to_string())
+        );
+        assert_eq!(
+            fix(r#"// CODESYNC(foo,"keep  in sync" )"#),
+            Some(r#"// CODESYNC(foo, "keep  in sync")"#.to_string())
+        );
+        assert_eq!(fix("// CODESYNC(foo, 2)"), None);
+    }
+
+    #[test]
+    fn fixes_suspicious_labels() {
+        assert_eq!(
+            fix("// CODESYNC(foo; 2)"),
+            Some(("// CODESYNC(foo, 2)".to_string(), true))
+        );
+        assert_eq!(
+            fix("// CODESYNC(foo $N)"),
+            Some(("// CODESYNC(foo, $N)".to_string(), false))
+        );
+        assert_eq!(
+            fix("// CODESYNC(“foo”)"),
+            Some(("// CODESYNC(foo)".to_string(), false))
+        );
+        assert_eq!(fix("// CODESYNC(foo bar)"), None);
+        assert_eq!(fix("// CODESYNC(foo, 2)"), None);
+    }
+
+    #[test]
+    fn overlapping_fixes() {
+        let fix = |span: Range<usize>| Fix {
+            span,
+            replacement: "x".to_string(),
+            confident: true,
+        };
+        assert!(overlaps(&fix(0..4), &fix(3..5)));
+        assert!(overlaps(&fix(0..10), &fix(3..5)));
+        assert!(!overlaps(&fix(0..3), &fix(3..5)));
+        assert!(!overlaps(&fix(3..3), &fix(3..3)));
+        assert_eq!(apply("abcdef", &mut [fix(4..5), fix(0..2)]), "xcdxf");
+    }
+
+    #[test]
+    #[should_panic(expected = "fixes must not overlap")]
+    fn applying_overlapping_fixes_panics() {
+        let fix = |span: Range<usize>| Fix {
+            span,
+            replacement: "x".to_string(),
+            confident: true,
+        };
+        apply("abcdef", &mut [fix(0..4), fix(3..5)]);
+    }
+
+    #[test]
+    fn fixes_keep_comment_delimiters() {
+        let wrappers = [("// ", "\n"), ("/* ", " */"), ("<!-- ", " -->"), ("# ", "")];
+        let bodies = [
+            "CODESYNC(foo, 2",
+            "CODESYNC{foo}",
+            "CODESYNC[foo, 2)",
+            "CODESYNC(foo; 2)",
+            "CODESYNC(foo 2)",
+            "CODESYNC(“foo”)",
+            "CODESYNC( foo ,2 )",
+        ];
+        for (open, close) in wrappers {
+            for body in bodies {
+                let line = format!("x {open}{body}{close}");
+                let matches = Matches::from_buffer(Path::new("file.rs"), &line);
+                let fix = match matches.invalid_matches().next() {
+                    Some(m) => suggest_for_invalid(&line, &m),
+                    None => {
+                        let comment = matches.comments().next().unwrap();
+                        let fix = suggest_for_comment(comment)
+                            .or_else(|| suggest_spacing(&line, comment))
+                            .unwrap();
+                        assert!(comment.span().start <= fix.span.start);
+                        assert!(fix.span.end <= comment.span().end);
+                        Some(fix)
+                    }
+                }
+                .unwrap();
+                // Nothing before the keyword is touched
+                assert!(fix.span.start >= line.find(body).unwrap(), "{line}");
+                let fixed = apply(&line, &mut [fix]);
+                let prefix = format!("x {open}CODESYNC");
+                assert!(fixed.starts_with(&prefix), "{fixed}");
+                let args = fixed.strip_suffix(close).unwrap();
+                assert!(args.ends_with([')', ']']), "{fixed}");
+            }
+        }
+    }
+}
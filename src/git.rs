@@ -0,0 +1,357 @@
+//! Information from git: lines changed since a ref, used to restrict style checks to new code,
+//! the commit that last changed each line, used to annotate diagnostics with `--blame`, and the
+//! tracked files, used to skip untracked ones with `--git-tracked`, and the files with
+//! uncommitted changes, used by `fix --only-clean` and `--only-dirty`. Also locates hooks for
+//! `install-hook`.
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// 1-based line ranges, in the current version of each file, added or modified since a ref.
+#[derive(Debug, Default)]
+pub struct ChangedLines {
+    /// Keyed by paths joined to the directory `git diff` ran in, without `.` components
+    files: HashMap<PathBuf, Vec<Range<usize>>>,
+}
+
+impl ChangedLines {
+    /// Run `git diff` against `since` in `dir` for the files under it. This compares against the
+    /// working tree, so uncommitted changes to tracked files count as changed. Untracked files are
+    /// not included. Paths are joined to `dir`, so they can be compared with the paths of files
+    /// searched from it.
+    pub fn since(dir: &Path, since: &str) -> io::Result<Self> {
+        let output = Command::new("git")
+            .args([
+                "diff",
+                "--unified=0",
+                "--no-color",
+                "--no-ext-diff",
+                "--relative",
+            ])
+            .arg(since)
+            .arg("--")
+            .current_dir(dir)
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`git diff {since}` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(Self::parse(&String::from_utf8_lossy(&output.stdout), dir))
+    }
+
+    /// Parse the output of `git diff` run in `dir`.
+    fn parse(diff: &str, dir: &Path) -> Self {
+        let mut changed = Self::default();
+        let mut file = None;
+        for line in diff.lines() {
+            if let Some(path) = line.strip_prefix("+++ ") {
+                file = path
+                    .strip_prefix("b/")
+                    .map(|path| without_cur_dir(&dir.join(path)));
+            } else if let (Some(file), Some(hunk)) = (&file, line.strip_prefix("@@ ")) {
+                if let Some(lines) = parse_hunk_header(hunk) {
+                    changed.files.entry(file.clone()).or_default().push(lines);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Whether any line in `lines` (1-based, exclusive end) of `path` was changed.
+    pub fn overlaps(&self, path: &Path, lines: Range<usize>) -> bool {
+        self.files
+            .get(&without_cur_dir(path))
+            .is_some_and(|ranges| {
+                ranges
+                    .iter()
+                    .any(|r| r.start < lines.end && lines.start < r.end)
+            })
+    }
+}
+
+/// `path` without `.` components, e.g., `src/a.rs` for `./src/./a.rs`.
+fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Run `git ls-files` in `dir` and return the tracked files under it, joined to `dir`. Fails if
+/// `dir` isn't in a git repository.
+pub fn tracked_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args(["ls-files", "-z", "--"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`git ls-files` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(parse_ls_files(&String::from_utf8_lossy(&output.stdout))
+        .map(|path| dir.join(path))
+        .collect())
+}
+
+/// Run `git status` in `dir` and return the files in its repository with uncommitted changes,
+/// staged or not, including untracked ones. Paths are joined to the root of the repository. Fails
+/// if `dir` isn't in a git repository.
+pub fn dirty_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let git = |args: &[&str]| -> io::Result<String> {
+        let output = Command::new("git").args(args).current_dir(dir).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`git {}` failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim_end());
+    let status = git(&["status", "--porcelain", "-z", "--untracked-files=all"])?;
+    Ok(parse_status(&status).map(|path| root.join(path)).collect())
+}
+
+/// Parse the output of `git status --porcelain -z`: an `XY path` entry per file, where `XY` is
+/// the status, terminated by NUL bytes. Renamed and copied files are followed by an extra entry
+/// with their original path, which is skipped.
+fn parse_status(output: &str) -> impl Iterator<Item = &str> {
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+    std::iter::from_fn(move || {
+        let entry = entries.next()?;
+        let (status, path) = (entry.get(..2)?, entry.get(3..)?);
+        if status.contains(['R', 'C']) {
+            entries.next();
+        }
+        Some(path)
+    })
+}
+
+/// The path of the hook called `name`, e.g., `pre-commit`, in the repository containing the current
+/// directory, whether it exists or not. This respects `core.hooksPath` and works in worktrees.
+pub fn hook_path(name: &str) -> io::Result<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--git-path"])
+        .arg(format!("hooks/{name}"))
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`git rev-parse` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim_end(),
+    ))
+}
+
+/// Parse the output of `git ls-files -z`, where paths are terminated by NUL bytes so they are
+/// never quoted.
+fn parse_ls_files(output: &str) -> impl Iterator<Item = &str> {
+    output.split('\0').filter(|path| !path.is_empty())
+}
+
+/// The commit that last changed a line, as reported by `git blame`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// The abbreviated hash of the commit, or `None` if the line isn't committed yet.
+    pub commit: Option<String>,
+    pub author: String,
+}
+
+impl fmt::Display for BlameLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.commit {
+            Some(commit) => write!(f, "{} in {commit}", self.author),
+            None => write!(f, "uncommitted changes"),
+        }
+    }
+}
+
+/// `git blame` for files in the current directory, run at most once per file.
+#[derive(Debug, Default)]
+pub struct Blame {
+    /// The blame of each line of a file, or `None` if it couldn't be computed, e.g., because the
+    /// file isn't tracked or git isn't available.
+    files: HashMap<PathBuf, Option<Vec<BlameLine>>>,
+}
+
+impl Blame {
+    /// The commit that last changed `line` (1-based) of `path`, or `None` if unknown.
+    pub fn line(&mut self, path: &Path, line: usize) -> Option<&BlameLine> {
+        let lines = self
+            .files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Self::run(path));
+        lines.as_ref()?.get(line.checked_sub(1)?)
+    }
+
+    fn run(path: &Path) -> Option<Vec<BlameLine>> {
+        let output = Command::new("git")
+            .args(["blame", "--line-porcelain", "--"])
+            .arg(path)
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| Self::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Parse the output of `git blame --line-porcelain`, where every line of the file is preceded
+    /// by a header starting with the commit hash and followed by `key value` lines.
+    fn parse(porcelain: &str) -> Vec<BlameLine> {
+        let mut lines = vec![];
+        let mut commit = None;
+        let mut author = String::new();
+        for line in porcelain.lines() {
+            if line.starts_with('\t') {
+                lines.push(BlameLine {
+                    commit: commit.take(),
+                    author: std::mem::take(&mut author),
+                });
+            } else if let Some(name) = line.strip_prefix("author ") {
+                author = name.to_string();
+            } else if let Some(hash) = line.split(' ').next().filter(|h| is_hash(h)) {
+                commit = (!hash.bytes().all(|b| b == b'0')).then(|| hash[..8].to_string());
+            }
+        }
+        lines
+    }
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() >= 40 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Parse the new side of a hunk header, e.g., `-10,2 +12,3 @@`, as a range of lines. Returns
+/// `None` for hunks that only delete lines.
+fn parse_hunk_header(hunk: &str) -> Option<Range<usize>> {
+    let new = hunk.split_whitespace().find_map(|s| s.strip_prefix('+'))?;
+    let (start, len) = match new.split_once(',') {
+        Some((start, len)) => (start.parse().ok()?, len.parse().ok()?),
+        None => (new.parse().ok()?, 1),
+    };
+    (len > 0).then_some(start..start + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_diff() {
+        let diff = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1 +1 @@
+-old
++new
+@@ -10,2 +12,3 @@ fn main() {
+@@ -20,3 +24,0 @@
+diff --git a/gone.rs b/gone.rs
+--- a/gone.rs
++++ /dev/null
+@@ -1,2 +0,0 @@
+";
+        let changed = ChangedLines::parse(diff, Path::new("."));
+        assert_eq!(changed.files.len(), 1);
+        assert!(changed.overlaps(Path::new("./a.rs"), 1..2));
+        assert!(!changed.overlaps(Path::new("a.rs"), 2..12));
+        assert!(changed.overlaps(Path::new("a.rs"), 14..15));
+        assert!(!changed.overlaps(Path::new("a.rs"), 24..26));
+
+        let changed = ChangedLines::parse(diff, Path::new("/work/proj"));
+        assert!(changed.overlaps(Path::new("/work/proj/a.rs"), 1..2));
+        assert!(!changed.overlaps(Path::new("a.rs"), 1..2));
+    }
+
+    #[test]
+    fn changed_lines_in_another_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=codesync",
+                    "-c",
+                    "user.email=codesync@example.com",
+                ])
+                .args(args)
+                .current_dir(dir.path())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init"]);
+        std::fs::write(dir.path().join("a.rs"), "a\nb\n").unwrap();
+        git(&["add", "a.rs"]);
+        git(&["commit", "-m", "a"]);
+        std::fs::write(dir.path().join("a.rs"), "a\nc\n").unwrap();
+
+        // As with an absolute `--path` pointing outside the current directory
+        let changed = ChangedLines::since(dir.path(), "HEAD").unwrap();
+        let path = dir.path().join("a.rs");
+        assert!(changed.overlaps(&path, 2..3));
+        assert!(!changed.overlaps(&path, 1..2));
+    }
+
+    #[test]
+    fn parse_tracked_files() {
+        let output = "a.rs\0dir/with space.rs\0dir/\"quoted\".rs\0";
+        assert_eq!(
+            parse_ls_files(output).collect::<Vec<_>>(),
+            ["a.rs", "dir/with space.rs", "dir/\"quoted\".rs"]
+        );
+        assert_eq!(parse_ls_files("").count(), 0);
+    }
+
+    #[test]
+    fn parse_dirty_files() {
+        let output = " M a.rs\0M  dir/with space.rs\0R  new.rs\0old.rs\0?? untracked.rs\0";
+        assert_eq!(
+            parse_status(output).collect::<Vec<_>>(),
+            ["a.rs", "dir/with space.rs", "new.rs", "untracked.rs"]
+        );
+        assert_eq!(parse_status("").count(), 0);
+    }
+
+    #[test]
+    fn parse_blame() {
+        let porcelain = "\
+4f1e9a8c2b7d3e6f5a4b3c2d1e0f9a8b7c6d5e4f 1 1 2
+author Ada Lovelace
+author-mail <ada@example.com>
+summary Add engine
+filename a.rs
+\t// CODESYNC(foo)
+4f1e9a8c2b7d3e6f5a4b3c2d1e0f9a8b7c6d5e4f 2 2
+author Ada Lovelace
+filename a.rs
+\tfn main() {}
+0000000000000000000000000000000000000000 3 3 1
+author Not Committed Yet
+filename a.rs
+\t// CODESYNC(bar)
+";
+        let lines = Blame::parse(porcelain);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].to_string(), "Ada Lovelace in 4f1e9a8c");
+        assert_eq!(lines[1], lines[0]);
+        assert_eq!(lines[2].commit, None);
+        assert_eq!(lines[2].to_string(), "uncommitted changes");
+    }
+}
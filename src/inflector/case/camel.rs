@@ -230,6 +230,24 @@ mod tests {
         )
     }
 
+    #[test]
+    fn preserves_acronyms() {
+        let acronyms = HashSet::from(["ID".to_string(), "HTTP".to_string(), "URL".to_string()]);
+        for (convertable_string, expected) in [
+            ("user_id", "userID"),
+            ("userId", "userID"),
+            ("userID", "userID"),
+            ("userIDValue", "userIDValue"),
+            ("HTTPServer", "httpServer"),
+            ("parse_http_url", "parseHTTPURL"),
+            ("parseHTTPURL", "parseHTTPURL"),
+            ("id_value", "idValue"),
+            ("validate_identity", "validateIdentity"),
+        ] {
+            assert_eq!(to_camel_case(convertable_string, &acronyms), expected);
+        }
+    }
+
     #[test]
     fn is_correct_from_camel_case() {
         let convertable_string: String = "fooBar".to_owned();
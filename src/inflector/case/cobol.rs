@@ -0,0 +1,134 @@
+use super::*;
+/// Converts a `&str` to `COBOL-CASE` `String`
+///
+/// ```
+/// use codesync::inflector::case::to_cobol_case;
+///
+/// assert_eq!(to_cobol_case("foo_bar"), "FOO-BAR");
+/// assert_eq!(to_cobol_case("HTTP Foo bar"), "HTTP-FOO-BAR");
+/// assert_eq!(to_cobol_case("Foo bar"), "FOO-BAR");
+/// assert_eq!(to_cobol_case("Foo Bar"), "FOO-BAR");
+/// assert_eq!(to_cobol_case("FooBar"), "FOO-BAR");
+/// assert_eq!(to_cobol_case("fooBar"), "FOO-BAR");
+/// assert_eq!(to_cobol_case("FOO_BAR"), "FOO-BAR");
+/// ```
+pub fn to_cobol_case(non_cobol_case_string: &str) -> String {
+    to_case_snake_like(non_cobol_case_string, "-", "upper")
+}
+
+/// Determines of a `&str` is `COBOL-CASE`
+///
+/// ```
+/// use codesync::inflector::case::is_cobol_case;
+///
+/// assert!(is_cobol_case("FOO-BAR-STRING-THAT-IS-REALLY-REALLY-LONG"));
+/// assert!(is_cobol_case("FOO-BAR1-STRING-THAT-IS-REALLY-REALLY-LONG"));
+///
+/// assert!(!is_cobol_case("FOO_BAR_STRING_THAT_IS_REALLY_REALLY_LONG"));
+/// assert!(!is_cobol_case("foo-bar-string-that-is-really-really-long"));
+/// assert!(!is_cobol_case("Foo-Bar-String-That-Is-Really-Really-Long"));
+/// assert!(!is_cobol_case("FooBarIsAReallyReallyLongString"));
+/// assert!(!is_cobol_case("fooBarIsAReallyReallyLongString"));
+/// ```
+pub fn is_cobol_case(test_string: &str) -> bool {
+    test_string == to_cobol_case(test_string)
+}
+
+/// Converts a `&str` to `COBOL-CASE` `String` optionally starting a new word at each run of digits
+/// following a letter
+///
+/// ```
+/// use codesync::inflector::case::to_cobol_case_with_digits;
+///
+/// assert_eq!(to_cobol_case_with_digits("fooBar3", false), "FOO-BAR3");
+/// assert_eq!(to_cobol_case_with_digits("fooBar3", true), "FOO-BAR-3");
+/// ```
+pub fn to_cobol_case_with_digits(convertable_string: &str, split_digits: bool) -> String {
+    to_case_snake_like_with_digits(convertable_string, "-", "upper", split_digits)
+}
+
+/// Converts a `&str` to `SCREAMING-KEBAB-CASE`, another name for `COBOL-CASE`
+///
+/// ```
+/// use codesync::inflector::case::to_screaming_kebab_case;
+///
+/// assert_eq!(to_screaming_kebab_case("foo_bar"), "FOO-BAR");
+/// assert_eq!(to_screaming_kebab_case("fooBar"), "FOO-BAR");
+/// ```
+pub fn to_screaming_kebab_case(convertable_string: &str) -> String {
+    to_cobol_case(convertable_string)
+}
+
+/// Determines if a `&str` is `SCREAMING-KEBAB-CASE`, another name for `COBOL-CASE`
+///
+/// ```
+/// use codesync::inflector::case::{is_screaming_kebab_case, to_screaming_kebab_case};
+///
+/// assert!(is_screaming_kebab_case("FOO-BAR"));
+/// assert!(is_screaming_kebab_case(&to_screaming_kebab_case("foo-bar")));
+/// assert!(!is_screaming_kebab_case("foo-bar"));
+/// assert!(!is_screaming_kebab_case("FOO_BAR"));
+/// ```
+pub fn is_screaming_kebab_case(test_string: &str) -> bool {
+    is_cobol_case(test_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_cobol_case;
+    use super::is_screaming_kebab_case;
+    use super::to_cobol_case;
+
+    #[test]
+    fn from_camel_case() {
+        let convertable_string: String = "fooBar".to_owned();
+        let expected: String = "FOO-BAR".to_owned();
+        assert_eq!(to_cobol_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn from_kebab_case() {
+        let convertable_string: String = "foo-bar".to_owned();
+        let expected: String = "FOO-BAR".to_owned();
+        assert_eq!(to_cobol_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn from_train_case() {
+        let convertable_string: String = "Foo-Bar".to_owned();
+        let expected: String = "FOO-BAR".to_owned();
+        assert_eq!(to_cobol_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn from_screaming_snake_case() {
+        let convertable_string: String = "FOO_BAR".to_owned();
+        let expected: String = "FOO-BAR".to_owned();
+        assert_eq!(to_cobol_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn is_correct_from_cobol_case() {
+        let convertable_string: String = "FOO-BAR".to_owned();
+        assert!(is_cobol_case(&convertable_string))
+    }
+
+    #[test]
+    fn is_correct_from_kebab_case() {
+        let convertable_string: String = "foo-bar".to_owned();
+        assert!(!is_cobol_case(&convertable_string))
+    }
+
+    #[test]
+    fn is_correct_from_screaming_snake_case() {
+        let convertable_string: String = "FOO_BAR".to_owned();
+        assert!(!is_cobol_case(&convertable_string))
+    }
+
+    #[test]
+    fn screaming_kebab_round_trip() {
+        for s in ["fooBar", "foo_bar", "Foo-Bar", "FOO-BAR"] {
+            assert!(is_screaming_kebab_case(&to_cobol_case(s)));
+        }
+    }
+}
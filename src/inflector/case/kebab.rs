@@ -33,6 +33,19 @@ pub fn to_kebab_case(non_kebab_case_string: &str) -> String {
     to_case_snake_like(non_kebab_case_string, "-", "lower")
 }
 
+/// Converts a `&str` to `kebab-case` `String` optionally starting a new word at each run of digits
+/// following a letter
+///
+/// ```
+/// use codesync::inflector::case::to_kebab_case_with_digits;
+///
+/// assert_eq!(to_kebab_case_with_digits("fooBar3", false), "foo-bar3");
+/// assert_eq!(to_kebab_case_with_digits("fooBar3", true), "foo-bar-3");
+/// ```
+pub fn to_kebab_case_with_digits(convertable_string: &str, split_digits: bool) -> String {
+    to_case_snake_like_with_digits(convertable_string, "-", "lower", split_digits)
+}
+
 #[cfg(test)]
 mod tests {
     use super::is_kebab_case;
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::{borrow::Cow, collections::HashSet};
 
 /// Provides conversion to and detection of camel case strings.
 ///
@@ -12,9 +12,9 @@ pub use camel::to_camel_case;
 /// Example string `snake_case`
 pub mod snake;
 
-use regex::Regex;
 pub use snake::is_snake_case;
 pub use snake::to_snake_case;
+pub use snake::to_snake_case_with_digits;
 
 /// Provides conversion to and detection of screaming snake case strings.
 ///
@@ -22,6 +22,7 @@ pub use snake::to_snake_case;
 pub mod screaming_snake;
 pub use screaming_snake::is_screaming_snake_case;
 pub use screaming_snake::to_screaming_snake_case;
+pub use screaming_snake::to_screaming_snake_case_with_digits;
 
 /// Provides conversion to and detection of kebab case strings.
 ///
@@ -29,6 +30,17 @@ pub use screaming_snake::to_screaming_snake_case;
 pub mod kebab;
 pub use kebab::is_kebab_case;
 pub use kebab::to_kebab_case;
+pub use kebab::to_kebab_case_with_digits;
+
+/// Provides conversion to and detection of cobol case strings.
+///
+/// Example string `COBOL-CASE`
+pub mod cobol;
+pub use cobol::is_cobol_case;
+pub use cobol::is_screaming_kebab_case;
+pub use cobol::to_cobol_case;
+pub use cobol::to_cobol_case_with_digits;
+pub use cobol::to_screaming_kebab_case;
 
 /// Provides conversion to and detection of train case strings.
 ///
@@ -36,6 +48,7 @@ pub use kebab::to_kebab_case;
 pub mod train;
 pub use train::is_train_case;
 pub use train::to_train_case;
+pub use train::to_train_case_with_acronyms;
 
 /// Provides conversion to and detection of sentence case strings.
 ///
@@ -43,6 +56,16 @@ pub use train::to_train_case;
 pub mod sentence;
 pub use sentence::is_sentence_case;
 pub use sentence::to_sentence_case;
+pub use sentence::to_sentence_case_with_acronyms;
+
+/// Provides conversion to and detection of title case strings.
+///
+/// Example string `Title Case`
+pub mod title;
+pub use title::is_title_case;
+pub use title::to_title_case;
+pub use title::to_title_case_with;
+pub use title::DEFAULT_SMALL_WORDS;
 
 /// Provides conversion to pascal case strings.
 ///
@@ -50,6 +73,7 @@ pub use sentence::to_sentence_case;
 pub mod pascal;
 pub use pascal::is_pascal_case;
 pub use pascal::to_pascal_case;
+pub use pascal::to_pascal_case_with_acronyms;
 
 #[doc(hidden)]
 pub struct CamelOptions {
@@ -63,25 +87,45 @@ pub struct CamelOptions {
 
 #[doc(hidden)]
 pub fn to_case_snake_like(convertable_string: &str, replace_with: &str, case: &str) -> String {
+    to_case_snake_like_with_digits(convertable_string, replace_with, case, false)
+}
+
+/// Like [`to_case_snake_like`] but when `split_digits` is true a run of digits following a letter
+/// starts a new word, e.g., `foo2bar` becomes `foo_2bar` instead of `foo2bar`.
+#[doc(hidden)]
+pub fn to_case_snake_like_with_digits(
+    convertable_string: &str,
+    replace_with: &str,
+    case: &str,
+    split_digits: bool,
+) -> String {
     let mut first_character: bool = true;
+    let mut last_char: char = ' ';
     let mut result: String = String::with_capacity(convertable_string.len() * 2);
-    for char_with_index in trim_right(convertable_string).char_indices() {
+    for char_with_index in trim_right(convertable_string).chars().enumerate() {
         if char_is_separator(&char_with_index.1) {
             if !first_character {
                 first_character = true;
                 result.push(replace_with.chars().next().unwrap_or('_'));
             }
-        } else if requires_separator(char_with_index, first_character, convertable_string) {
+        } else if requires_separator(char_with_index, first_character, convertable_string)
+            || (split_digits && !first_character && digit_run_starts(last_char, char_with_index.1))
+        {
             first_character = false;
             result = snake_like_with_separator(result, replace_with, &char_with_index.1, case)
         } else {
             first_character = false;
             result = snake_like_no_separator(result, &char_with_index.1, case)
         }
+        last_char = char_with_index.1;
     }
     result
 }
 
+fn digit_run_starts(last_char: char, character: char) -> bool {
+    character.is_ascii_digit() && last_char.is_alphabetic()
+}
+
 #[doc(hidden)]
 pub fn to_case_camel_like(
     convertable_string: &str,
@@ -93,7 +137,8 @@ pub fn to_case_camel_like(
     let mut last_char: char = camel_options.last_char;
     let mut found_real_char: bool = false;
     let mut result: String = String::with_capacity(convertable_string.len() * 2);
-    for character in trim_right(convertable_string).chars() {
+    let convertable_string = split_acronyms(convertable_string, acronyms);
+    for character in trim_right(&convertable_string).chars() {
         if char_is_separator(&character) && found_real_char {
             new_word = true;
         } else if !found_real_char && is_not_alphanumeric(character) {
@@ -107,32 +152,105 @@ pub fn to_case_camel_like(
         } else {
             found_real_char = true;
             last_char = character;
-            result.push(character.to_ascii_lowercase());
+            result.push(lowercase(character));
         }
     }
 
-    result = capitalize_acronym_substrings(&result, acronyms);
+    if !acronyms.is_empty() {
+        result = capitalize_acronym_words(&result, acronyms);
+    }
 
     result
 }
 
-fn capitalize_acronym_substrings(str: &str, acronyms: &HashSet<String>) -> String {
-    let mut new_string = str.to_string();
-    for acronym in acronyms {
-        // There will probably be a bug here. See test_inflected_acronyms_buggy_behavior
-        // let pattern = format!(r"(?:\b)(?i)({})(?-i)(?:\b)", acronym);
-        let pattern = format!(r"(?i)({})(?-i)", acronym);
-
-        let re = Regex::new(&pattern).unwrap();
-        let mut result = re.replace_all(&new_string, |caps: &regex::Captures| {
-            let matched = caps.get(1).unwrap().as_str();
-            matched.to_uppercase()
+/// Surround acronyms written in uppercase with separators and write them capitalized, e.g., with
+/// the acronym `HTTP` the string `HTTPServer` becomes ` Http Server`, so the acronym is read as a
+/// single word instead of merged into the next one. An acronym is only considered at the start of a
+/// word, i.e., at the start of the string, after a non-uppercase character, or right after another
+/// acronym, and only if it's not followed by a lowercase letter.
+fn split_acronyms<'a>(str: &'a str, acronyms: &HashSet<String>) -> Cow<'a, str> {
+    if acronyms.is_empty() {
+        return Cow::Borrowed(str);
+    }
+    let mut acronyms: Vec<String> = acronyms.iter().map(|a| a.to_uppercase()).collect();
+    // Prefer the longest acronym when several start at the same position.
+    acronyms.sort_by_key(|a| std::cmp::Reverse(a.len()));
+
+    let mut result = String::with_capacity(str.len() * 2);
+    let mut last_char = ' ';
+    let mut after_acronym = false;
+    let mut rest = str;
+    while let Some(character) = rest.chars().next() {
+        let at_word_start = !last_char.is_uppercase() || after_acronym;
+        let acronym = acronyms.iter().find(|a| {
+            !a.is_empty()
+                && at_word_start
+                && rest.starts_with(a.as_str())
+                && !rest[a.len()..].starts_with(char::is_lowercase)
         });
-
-        new_string = result.to_mut().to_string();
+        if let Some(acronym) = acronym {
+            let mut chars = acronym.chars();
+            result.push(' ');
+            result.extend(chars.next());
+            result.extend(chars.flat_map(char::to_lowercase));
+            result.push(' ');
+            rest = &rest[acronym.len()..];
+            after_acronym = true;
+        } else {
+            result.push(character);
+            rest = &rest[character.len_utf8()..];
+            after_acronym = false;
+        }
+        last_char = character;
     }
+    Cow::Owned(result)
+}
+
+/// Write in uppercase every word of a camel-like string that's an acronym, compared ignoring case.
+/// A word starts with an uppercase letter, so the leading lowercase word of a camel case string is
+/// left as is, e.g., with the acronym `ID` the string `idValueId` becomes `idValueID`.
+fn capitalize_acronym_words(str: &str, acronyms: &HashSet<String>) -> String {
+    let acronyms: HashSet<String> = acronyms.iter().map(|a| a.to_lowercase()).collect();
+    let mut result = String::with_capacity(str.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, result: &mut String| {
+        if word.starts_with(char::is_uppercase) && acronyms.contains(&word.to_lowercase()) {
+            result.push_str(&word.to_uppercase());
+        } else {
+            result.push_str(word);
+        }
+        word.clear();
+    };
+    for character in str.chars() {
+        if character.is_uppercase() || !character.is_alphanumeric() {
+            flush(&mut word, &mut result);
+        }
+        if character.is_alphanumeric() {
+            word.push(character);
+        } else {
+            result.push(character);
+        }
+    }
+    flush(&mut word, &mut result);
+    result
+}
 
-    new_string
+/// Adjust the words of a space-separated string: words in `acronyms` are written in uppercase and
+/// words in `small_words`, other than the first one, in lowercase. Both are compared ignoring case.
+fn adjust_words(str: &str, acronyms: &HashSet<String>, small_words: &HashSet<String>) -> String {
+    let acronyms: HashSet<String> = acronyms.iter().map(|a| a.to_lowercase()).collect();
+    let small_words: HashSet<String> = small_words.iter().map(|w| w.to_lowercase()).collect();
+    let words = str.split(' ').enumerate().map(|(i, word)| {
+        let lower = word.to_lowercase();
+        if acronyms.contains(&lower) {
+            word.to_uppercase()
+        } else if i > 0 && small_words.contains(&lower) {
+            lower
+        } else {
+            word.to_string()
+        }
+    });
+    words.collect::<Vec<_>>().join(" ")
 }
 
 #[inline]
@@ -146,9 +264,9 @@ fn append_on_new_word(
         result.push(camel_options.injectable_char);
     }
     if first_word_or_not_inverted(first_word, camel_options.inverted) {
-        result.push(character.to_ascii_uppercase());
+        result.push(uppercase(character));
     } else {
-        result.push(character.to_ascii_lowercase());
+        result.push(lowercase(character));
     }
     result
 }
@@ -166,7 +284,28 @@ fn last_char_lower_current_is_upper_or_new_word(
     last_char: char,
     character: char,
 ) -> bool {
-    new_word || ((last_char.is_lowercase() && character.is_uppercase()) && (last_char != ' '))
+    // Digits and letters without case end a word like a lowercase letter does, e.g., `Bar` is a
+    // word in `foo2Bar`
+    new_word
+        || (last_char.is_alphanumeric() && !last_char.is_uppercase() && character.is_uppercase())
+}
+
+/// `character` in lowercase, or unchanged if its lowercase form isn't a single character, e.g.,
+/// `İ` lowercases to `i` followed by a combining dot, which would be read as a separator if
+/// converted again.
+fn lowercase(character: char) -> char {
+    single_char(character.to_lowercase()).unwrap_or(character)
+}
+
+/// `character` in uppercase, or unchanged if its uppercase form isn't a single character, e.g.,
+/// `ß` uppercases to `SS`, which would be read as a word boundary if converted again.
+fn uppercase(character: char) -> char {
+    single_char(character.to_uppercase()).unwrap_or(character)
+}
+
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
 }
 
 fn char_is_separator(character: &char) -> bool {
@@ -195,10 +334,10 @@ fn requires_separator(
 #[inline]
 fn snake_like_no_separator(mut accumlator: String, current_char: &char, case: &str) -> String {
     if case == "lower" {
-        accumlator.push(current_char.to_ascii_lowercase());
+        accumlator.push(lowercase(*current_char));
         accumlator
     } else {
-        accumlator.push(current_char.to_ascii_uppercase());
+        accumlator.push(uppercase(*current_char));
         accumlator
     }
 }
@@ -212,11 +351,11 @@ fn snake_like_with_separator(
 ) -> String {
     if case == "lower" {
         accumlator.push(replace_with.chars().next().unwrap_or('_'));
-        accumlator.push(current_char.to_ascii_lowercase());
+        accumlator.push(lowercase(*current_char));
         accumlator
     } else {
         accumlator.push(replace_with.chars().next().unwrap_or('_'));
-        accumlator.push(current_char.to_ascii_uppercase());
+        accumlator.push(uppercase(*current_char));
         accumlator
     }
 }
@@ -238,6 +377,38 @@ fn next_or_previous_char_is_lowercase(convertable_string: &str, char_with_index:
 //     test_char.is_uppercase()
 // }
 
+#[test]
+fn test_digit_boundary_keep() {
+    assert_eq!(to_case_snake_like("foo2bar", "_", "lower"), "foo2bar");
+    assert_eq!(to_case_snake_like("foo22", "_", "lower"), "foo22");
+    assert_eq!(to_case_snake_like("v2Api", "_", "lower"), "v2_api");
+    assert_eq!(to_case_snake_like("fooBar3", "_", "upper"), "FOO_BAR3");
+}
+
+#[test]
+fn test_digit_boundary_split() {
+    assert_eq!(
+        to_case_snake_like_with_digits("foo2bar", "_", "lower", true),
+        "foo_2bar"
+    );
+    assert_eq!(
+        to_case_snake_like_with_digits("foo22", "_", "lower", true),
+        "foo_22"
+    );
+    assert_eq!(
+        to_case_snake_like_with_digits("v2Api", "_", "lower", true),
+        "v_2_api"
+    );
+    assert_eq!(
+        to_case_snake_like_with_digits("fooBar3", "_", "upper", true),
+        "FOO_BAR_3"
+    );
+    assert_eq!(
+        to_case_snake_like_with_digits("foo_22", "_", "lower", true),
+        "foo_22"
+    );
+}
+
 #[test]
 fn test_trim_bad_chars() {
     assert_eq!("abc", trim_right("abc----^"))
@@ -371,6 +542,13 @@ fn test_last_char_lower_current_is_upper_or_new_word_last_char_upper_current_upp
     ))
 }
 
+#[test]
+fn test_last_char_lower_current_is_upper_or_new_word_last_char_digit_current_upper() {
+    assert!(last_char_lower_current_is_upper_or_new_word(
+        false, '2', 'A'
+    ))
+}
+
 #[test]
 fn test_last_char_lower_current_is_upper_or_new_word_last_char_upper_current_lower() {
     assert!(!last_char_lower_current_is_upper_or_new_word(
@@ -407,3 +585,174 @@ fn test_not_first_word_and_has_separator_not_first_and_not_separator() {
 fn test_not_first_word_and_has_separator_not_first_and_has_separator() {
     assert!(not_first_word_and_has_separator(false, true))
 }
+
+/// Strings mixing separators, casings, and digits in every position.
+#[cfg(test)]
+const ROUND_TRIP_CORPUS: &[&str] = &[
+    "",
+    "a",
+    "A",
+    "x-",
+    "_foo_",
+    "3foo",
+    "fooBar3",
+    "foo-bar3",
+    "foo_bar_3",
+    "FOO_BAR_3",
+    "v2Api",
+    "V2API",
+    "foo2bar",
+    "Foo2Bar",
+    "FOO3bar",
+    "a1b2c3",
+    "ABC123def",
+    "abc123DEF",
+    "foo 2 bar",
+    "foo-3-bar",
+    "Foo-Bar-3",
+    "Train-Case-2",
+    "HTTPServer2",
+    "fooBAR",
+    "foo_BAR_baz",
+    "foo--bar",
+    "été2Été",
+];
+
+/// Converting a string to a case must give a string in that case, i.e., `is_x(to_x(s))` holds for
+/// every case, or `--consistent-casing` would reject its own suggestions. Conversions splitting
+/// digits are paired with the predicate of their case, which doesn't split digits, since words of
+/// digits must also be accepted by it.
+#[test]
+fn test_to_case_round_trips() {
+    type ToCase = fn(&str) -> String;
+    type IsCase = fn(&str) -> bool;
+    let cases: [(&str, ToCase, IsCase); 13] = [
+        (
+            "camel",
+            |s| to_camel_case(s, &HashSet::new()),
+            is_camel_case,
+        ),
+        ("pascal", to_pascal_case, is_pascal_case),
+        ("train", to_train_case, is_train_case),
+        ("kebab", to_kebab_case, is_kebab_case),
+        ("snake", to_snake_case, is_snake_case),
+        (
+            "screaming snake",
+            to_screaming_snake_case,
+            is_screaming_snake_case,
+        ),
+        ("cobol", to_cobol_case, is_cobol_case),
+        ("sentence", to_sentence_case, is_sentence_case),
+        ("title", to_title_case, is_title_case),
+        (
+            "kebab split digits",
+            |s| to_kebab_case_with_digits(s, true),
+            is_kebab_case,
+        ),
+        (
+            "snake split digits",
+            |s| to_snake_case_with_digits(s, true),
+            is_snake_case,
+        ),
+        (
+            "screaming snake split digits",
+            |s| to_screaming_snake_case_with_digits(s, true),
+            is_screaming_snake_case,
+        ),
+        (
+            "cobol split digits",
+            |s| to_cobol_case_with_digits(s, true),
+            is_cobol_case,
+        ),
+    ];
+    for (name, to_case, is_case) in cases {
+        for s in ROUND_TRIP_CORPUS {
+            let converted = to_case(s);
+            assert!(is_case(&converted), "{name} case of {s:?} is {converted:?}");
+        }
+    }
+}
+
+/// A pseudo-random string of words of two or more characters, each written in lowercase,
+/// uppercase (if it only has letters with case), or capitalized, and joined by separators or
+/// nothing. `state` is the state of an
+/// xorshift generator, so the same state always gives the same strings.
+#[cfg(test)]
+fn random_words(state: &mut u64) -> String {
+    const CHARS: &[char] = &['a', 'b', 'z', 'é', 'σ', '日', '0', '9'];
+    const SEPARATORS: &[&str] = &["", "", "-", "_", " ", ".", "--"];
+    let mut next = |n: usize| {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state % n as u64) as usize
+    };
+    let mut result = String::new();
+    for i in 0..next(4) + 1 {
+        if i > 0 {
+            result.push_str(SEPARATORS[next(SEPARATORS.len())]);
+        }
+        let word: String = (0..next(4) + 2).map(|_| CHARS[next(CHARS.len())]).collect();
+        match next(3) {
+            0 => result.push_str(&word),
+            // A digit or a letter without case followed by an uppercase letter starts a word, so
+            // an uppercase word could end with a single-letter word
+            1 if word.chars().all(char::is_lowercase) => result.push_str(&word.to_uppercase()),
+            1 => result.push_str(&word),
+            _ => {
+                let mut chars = word.chars();
+                result.extend(chars.next().map(uppercase));
+                result.extend(chars);
+            }
+        }
+    }
+    result
+}
+
+/// Every conversion is idempotent, i.e., `to_x(to_x(s)) == to_x(s)`, for strings made of words of
+/// two or more characters in both ASCII and other scripts. See the `case` module for the inputs
+/// where it isn't.
+#[test]
+fn test_to_case_is_idempotent() {
+    let acronyms = HashSet::from(["ID".to_string(), "HTTP".to_string()]);
+    type ToCase<'a> = &'a dyn Fn(&str) -> String;
+    let small_words = HashSet::from(["ab".to_string(), "of".to_string()]);
+    let cases: [(&str, ToCase); 16] = [
+        ("camel", &|s| to_camel_case(s, &HashSet::new())),
+        ("camel with acronyms", &|s| to_camel_case(s, &acronyms)),
+        ("pascal", &to_pascal_case),
+        ("pascal with acronyms", &|s| {
+            to_pascal_case_with_acronyms(s, &acronyms)
+        }),
+        ("train", &to_train_case),
+        ("sentence", &to_sentence_case),
+        ("sentence with acronyms", &|s| {
+            to_sentence_case_with_acronyms(s, &acronyms)
+        }),
+        ("title", &to_title_case),
+        ("title with acronyms and small words", &|s| {
+            to_title_case_with(s, &acronyms, &small_words)
+        }),
+        ("kebab", &to_kebab_case),
+        ("snake", &to_snake_case),
+        ("screaming snake", &to_screaming_snake_case),
+        ("cobol", &to_cobol_case),
+        ("kebab split digits", &|s| {
+            to_kebab_case_with_digits(s, true)
+        }),
+        ("snake split digits", &|s| {
+            to_snake_case_with_digits(s, true)
+        }),
+        ("screaming snake split digits", &|s| {
+            to_screaming_snake_case_with_digits(s, true)
+        }),
+    ];
+    let mut state = 0x2545f4914f6cdd1d;
+    for _ in 0..2000 {
+        let s = random_words(&mut state);
+        for (name, to_case) in &cases {
+            let converted = to_case(&s);
+            assert_eq!(to_case(&converted), converted, "{name} case of {s:?}");
+        }
+    }
+}
@@ -14,6 +14,23 @@ use super::*;
 /// assert_eq!(to_pascal_case("FooBar3"), "FooBar3");
 /// ```
 pub fn to_pascal_case(non_pascalized_string: &str) -> String {
+    to_pascal_case_with_acronyms(non_pascalized_string, &HashSet::new())
+}
+
+/// Like [`to_pascal_case`] but words in `acronyms` are written in uppercase.
+///
+/// ```
+/// use codesync::inflector::case::to_pascal_case_with_acronyms;
+/// use std::collections::HashSet;
+/// let acronyms = HashSet::from(["HTTP".to_string(), "ID".to_string()]);
+/// assert_eq!(to_pascal_case_with_acronyms("http_server_id", &acronyms), "HTTPServerID");
+/// assert_eq!(to_pascal_case_with_acronyms("HTTPServerID", &acronyms), "HTTPServerID");
+/// assert_eq!(to_pascal_case_with_acronyms("validate", &acronyms), "Validate");
+/// ```
+pub fn to_pascal_case_with_acronyms(
+    non_pascalized_string: &str,
+    acronyms: &HashSet<String>,
+) -> String {
     let options = CamelOptions {
         new_word: true,
         last_char: ' ',
@@ -22,7 +39,7 @@ pub fn to_pascal_case(non_pascalized_string: &str) -> String {
         has_separator: false,
         inverted: false,
     };
-    to_case_camel_like(non_pascalized_string, options, &HashSet::new())
+    to_case_camel_like(non_pascalized_string, options, acronyms)
 }
 
 /// Determines if a `&str` is pascalCase bool``
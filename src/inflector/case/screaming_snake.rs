@@ -35,6 +35,19 @@ pub fn is_screaming_snake_case(test_string: &str) -> bool {
     test_string == to_screaming_snake_case(test_string)
 }
 
+/// Converts a `&str` to `SCREAMING_SNAKE_CASE` `String` optionally starting a new word at each run
+/// of digits following a letter
+///
+/// ```
+/// use codesync::inflector::case::to_screaming_snake_case_with_digits;
+///
+/// assert_eq!(to_screaming_snake_case_with_digits("fooBar3", false), "FOO_BAR3");
+/// assert_eq!(to_screaming_snake_case_with_digits("fooBar3", true), "FOO_BAR_3");
+/// ```
+pub fn to_screaming_snake_case_with_digits(convertable_string: &str, split_digits: bool) -> String {
+    to_case_snake_like_with_digits(convertable_string, "_", "upper", split_digits)
+}
+
 #[cfg(test)]
 mod tests {
     use super::is_screaming_snake_case;
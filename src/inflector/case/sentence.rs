@@ -12,6 +12,23 @@ use super::*;
 /// assert_eq!(to_sentence_case("foo-bar"), "Foo bar");
 /// ```
 pub fn to_sentence_case(non_sentence_case_string: &str) -> String {
+    to_sentence_case_with_acronyms(non_sentence_case_string, &HashSet::new())
+}
+
+/// Like [`to_sentence_case`] but words in `acronyms` are written in uppercase.
+///
+/// ```
+/// use codesync::inflector::case::to_sentence_case_with_acronyms;
+/// use std::collections::HashSet;
+/// let acronyms = HashSet::from(["HTTP".to_string(), "ID".to_string()]);
+/// assert_eq!(to_sentence_case_with_acronyms("http_server_id", &acronyms), "HTTP server ID");
+/// assert_eq!(to_sentence_case_with_acronyms("HTTPServerID", &acronyms), "HTTP server ID");
+/// assert_eq!(to_sentence_case_with_acronyms("HTTP server ID", &acronyms), "HTTP server ID");
+/// ```
+pub fn to_sentence_case_with_acronyms(
+    non_sentence_case_string: &str,
+    acronyms: &HashSet<String>,
+) -> String {
     let options = CamelOptions {
         new_word: true,
         last_char: ' ',
@@ -20,7 +37,8 @@ pub fn to_sentence_case(non_sentence_case_string: &str) -> String {
         has_separator: true,
         inverted: true,
     };
-    to_case_camel_like(non_sentence_case_string, options, &HashSet::new())
+    let sentence = to_case_camel_like(non_sentence_case_string, options, acronyms);
+    adjust_words(&sentence, acronyms, &HashSet::new())
 }
 /// Determines of a `&str` is `Sentence case`
 ///
@@ -38,6 +38,19 @@ pub fn is_snake_case(test_string: &str) -> bool {
     test_string == to_snake_case(test_string)
 }
 
+/// Converts a `&str` to `snake_case` `String` optionally starting a new word at each run of digits
+/// following a letter
+///
+/// ```
+/// use codesync::inflector::case::to_snake_case_with_digits;
+///
+/// assert_eq!(to_snake_case_with_digits("fooBar3", false), "foo_bar3");
+/// assert_eq!(to_snake_case_with_digits("fooBar3", true), "foo_bar_3");
+/// ```
+pub fn to_snake_case_with_digits(convertable_string: &str, split_digits: bool) -> String {
+    to_case_snake_like_with_digits(convertable_string, "_", "lower", split_digits)
+}
+
 #[cfg(test)]
 mod tests {
     use super::is_snake_case;
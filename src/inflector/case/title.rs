@@ -0,0 +1,122 @@
+use super::*;
+/// Words written in lowercase by [`to_title_case`] unless they're the first word.
+pub const DEFAULT_SMALL_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "in", "nor", "of", "on", "or", "the", "to",
+];
+
+/// Converts a `&str` to `Title Case` `String`. Small words in [`DEFAULT_SMALL_WORDS`] are written
+/// in lowercase unless they're the first word.
+///
+/// ```
+/// use codesync::inflector::case::to_title_case;
+///
+/// assert_eq!(to_title_case("the lord of the rings"), "The Lord of the Rings");
+/// assert_eq!(to_title_case("Foo bar"), "Foo Bar");
+/// assert_eq!(to_title_case("FooBar"), "Foo Bar");
+/// assert_eq!(to_title_case("fooBar"), "Foo Bar");
+/// assert_eq!(to_title_case("FOO_BAR"), "Foo Bar");
+/// assert_eq!(to_title_case("foo-bar"), "Foo Bar");
+/// ```
+pub fn to_title_case(non_title_case_string: &str) -> String {
+    let small_words = DEFAULT_SMALL_WORDS.iter().map(|w| w.to_string()).collect();
+    to_title_case_with(non_title_case_string, &HashSet::new(), &small_words)
+}
+
+/// Like [`to_title_case`] but words in `acronyms` are written in uppercase and words in
+/// `small_words` replace [`DEFAULT_SMALL_WORDS`]. Both are compared ignoring case.
+///
+/// ```
+/// use codesync::inflector::case::to_title_case_with;
+/// use std::collections::HashSet;
+/// let acronyms = HashSet::from(["HTTP".to_string()]);
+/// let small_words = HashSet::from(["of".to_string(), "over".to_string()]);
+/// assert_eq!(
+///     to_title_case_with("http_over_the_wire", &acronyms, &small_words),
+///     "HTTP over The Wire"
+/// );
+/// assert_eq!(
+///     to_title_case_with("HTTPOverTheWire", &acronyms, &small_words),
+///     "HTTP over The Wire"
+/// );
+/// ```
+pub fn to_title_case_with(
+    non_title_case_string: &str,
+    acronyms: &HashSet<String>,
+    small_words: &HashSet<String>,
+) -> String {
+    let options = CamelOptions {
+        new_word: true,
+        last_char: ' ',
+        first_word: true,
+        injectable_char: ' ',
+        has_separator: true,
+        inverted: false,
+    };
+    let title = to_case_camel_like(non_title_case_string, options, acronyms);
+    adjust_words(&title, acronyms, small_words)
+}
+
+/// Determines if a `&str` is `Title Case`
+///
+/// ```
+/// use codesync::inflector::case::is_title_case;
+///
+/// assert!(is_title_case("Foo"));
+/// assert!(is_title_case("Foo Bar Is a Really Really Long String"));
+///
+/// assert!(!is_title_case("Foo Bar Is A Really Really Long String"));
+/// assert!(!is_title_case("foo-bar-string-that-is-really-really-long"));
+/// assert!(!is_title_case("FooBarIsAReallyReallyLongString"));
+/// assert!(!is_title_case("Foo bar string that is really really long"));
+/// assert!(!is_title_case("FOO_BAR_STRING_THAT_IS_REALLY_REALLY_LONG"));
+/// assert!(!is_title_case("foo"));
+/// ```
+pub fn is_title_case(test_string: &str) -> bool {
+    test_string == to_title_case(test_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_title_case;
+    use super::to_title_case;
+
+    #[test]
+    fn from_sentence_case() {
+        let convertable_string: String = "Foo bar".to_owned();
+        let expected: String = "Foo Bar".to_owned();
+        assert_eq!(to_title_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn from_train_case() {
+        let convertable_string: String = "Foo-Bar".to_owned();
+        let expected: String = "Foo Bar".to_owned();
+        assert_eq!(to_title_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn from_snake_case() {
+        let convertable_string: String = "foo_bar".to_owned();
+        let expected: String = "Foo Bar".to_owned();
+        assert_eq!(to_title_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn small_word_first() {
+        let convertable_string: String = "of mice and men".to_owned();
+        let expected: String = "Of Mice and Men".to_owned();
+        assert_eq!(to_title_case(&convertable_string), expected)
+    }
+
+    #[test]
+    fn is_correct_from_title_case() {
+        let convertable_string: String = "The Lord of the Rings".to_owned();
+        assert!(is_title_case(&convertable_string))
+    }
+
+    #[test]
+    fn is_correct_from_capitalized_small_words() {
+        let convertable_string: String = "The Lord Of The Rings".to_owned();
+        assert!(!is_title_case(&convertable_string))
+    }
+}
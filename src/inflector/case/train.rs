@@ -28,8 +28,28 @@ pub fn is_train_case(test_string: &str) -> bool {
 /// assert!(to_train_case("Foo-Bar") == "Foo-Bar");
 /// assert!(to_train_case("FooBar") == "Foo-Bar");
 /// assert!(to_train_case("fooBar") == "Foo-Bar");
+/// assert!(to_train_case("fooBar3") == "Foo-Bar3");
+/// assert!(to_train_case("foo3bar") == "Foo3bar");
 /// ```
 pub fn to_train_case(non_train_case_string: &str) -> String {
+    to_train_case_with_acronyms(non_train_case_string, &HashSet::new())
+}
+
+/// Like [`to_train_case`] but words in `acronyms` are written in uppercase.
+///
+/// ```
+/// use codesync::inflector::case::to_train_case_with_acronyms;
+/// use std::collections::HashSet;
+/// let acronyms = HashSet::from(["HTTP".to_string(), "ID".to_string()]);
+/// assert_eq!(to_train_case_with_acronyms("http server", &acronyms), "HTTP-Server");
+/// assert_eq!(to_train_case_with_acronyms("HTTPServerID", &acronyms), "HTTP-Server-ID");
+/// assert_eq!(to_train_case_with_acronyms("Http-Server-Id", &acronyms), "HTTP-Server-ID");
+/// assert_eq!(to_train_case_with_acronyms("httpsServer", &acronyms), "Https-Server");
+/// ```
+pub fn to_train_case_with_acronyms(
+    non_train_case_string: &str,
+    acronyms: &HashSet<String>,
+) -> String {
     let options = CamelOptions {
         new_word: true,
         last_char: ' ',
@@ -38,7 +58,7 @@ pub fn to_train_case(non_train_case_string: &str) -> String {
         has_separator: true,
         inverted: false,
     };
-    to_case_camel_like(non_train_case_string, options, &HashSet::new())
+    to_case_camel_like(non_train_case_string, options, acronyms)
 }
 
 #[cfg(test)]
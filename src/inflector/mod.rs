@@ -9,11 +9,22 @@
 /// - Class case
 /// - Kebab case
 /// - Train case
+/// - Cobol case
 /// - Screaming snake case
 /// - Table case
 /// - Sentence case
+/// - Title case
 /// - Snake case
 /// - Pascal case
+///
+/// Converting a string that's already converted leaves it unchanged, except in two cases where the
+/// result of the first conversion is ambiguous:
+/// - In camel and pascal case, leading single-letter words are merged, e.g., `a_b` becomes `AB`,
+///   which is then read as a single word and becomes `Ab`. Without separators they can't be told
+///   apart from an acronym.
+/// - Letters whose other case isn't a single letter, e.g., `İ` or `ß`, are left as is and letters
+///   with a title case form, e.g., `ǅ`, may be read as starting a word after the first conversion,
+///   e.g., `zÉİ` becomes `z_éİ` and then `z_é_İ` in snake case. Labels are rarely written with them.
 pub mod case;
 
 pub use case::camel::is_camel_case;
@@ -21,21 +32,37 @@ pub use case::camel::to_camel_case;
 
 pub use case::pascal::is_pascal_case;
 pub use case::pascal::to_pascal_case;
+pub use case::pascal::to_pascal_case_with_acronyms;
 
 pub use case::snake::is_snake_case;
 pub use case::snake::to_snake_case;
+pub use case::snake::to_snake_case_with_digits;
 
 pub use case::screaming_snake::is_screaming_snake_case;
 pub use case::screaming_snake::to_screaming_snake_case;
+pub use case::screaming_snake::to_screaming_snake_case_with_digits;
 
 pub use case::kebab::is_kebab_case;
 pub use case::kebab::to_kebab_case;
+pub use case::kebab::to_kebab_case_with_digits;
 
 pub use case::train::is_train_case;
 pub use case::train::to_train_case;
+pub use case::train::to_train_case_with_acronyms;
+
+pub use case::cobol::is_cobol_case;
+pub use case::cobol::is_screaming_kebab_case;
+pub use case::cobol::to_cobol_case;
+pub use case::cobol::to_cobol_case_with_digits;
+pub use case::cobol::to_screaming_kebab_case;
 
 pub use case::sentence::is_sentence_case;
 pub use case::sentence::to_sentence_case;
+pub use case::sentence::to_sentence_case_with_acronyms;
+
+pub use case::title::is_title_case;
+pub use case::title::to_title_case;
+pub use case::title::to_title_case_with;
 
 #[allow(missing_docs)]
 pub trait Inflector {
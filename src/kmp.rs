@@ -5,26 +5,19 @@ pub const fn search<const N: usize>(
 ) -> Option<usize> {
     let mut t_i = 0;
     let mut p_i = 0;
-    let mut result_idx = 0;
 
     while t_i < haystack.len() && p_i < needle.len() {
         if haystack[t_i] == needle[p_i] {
-            if result_idx == 0 {
-                result_idx = t_i;
-            }
             t_i = t_i + 1;
             p_i = p_i + 1;
             if p_i >= needle.len() {
-                return Some(result_idx);
-            }
-        } else {
-            if p_i == 0 {
-                p_i = 0;
-            } else {
-                p_i = table[p_i - 1];
+                return Some(t_i - needle.len());
             }
+        } else if p_i == 0 {
             t_i = t_i + 1;
-            result_idx = 0;
+        } else {
+            // Retry the current byte against the longest proper prefix that's also a suffix
+            p_i = table[p_i - 1];
         }
     }
     None
@@ -1,104 +1,901 @@
 use std::{
-    collections::HashMap,
-    io,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, io,
     ops::Range,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     str,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
+mod check;
+pub mod config;
 pub mod inflector;
 mod kmp;
+pub mod vfs;
+
+pub use check::{check_count_override, Violation, ViolationKind};
+use vfs::{FileSystem, OsFileSystem};
 
 const PATTERN: [u8; 8] = [b'C', b'O', b'D', b'E', b'S', b'Y', b'N', b'C'];
 const PATTERN_KMP_TABLE: [usize; PATTERN.len()] = kmp::table(PATTERN);
 
+/// Comment delimiters recognized around a match as `(opening, closing)`. Line comments don't have
+/// a closing delimiter and extend until the end of the line.
+const COMMENT_DELIMITERS: &[(&str, Option<&str>)] = &[
+    ("//", None),
+    ("#", None),
+    ("--", None),
+    (";", None),
+    ("/*", Some("*/")),
+    ("<!--", Some("-->")),
+    ("{-", Some("-}")),
+    ("(*", Some("*)")),
+];
+
+/// The keyword marking a match unless configured otherwise with [`CollectOptions::keyword`].
+pub const DEFAULT_KEYWORD: &str = "CODESYNC";
+
+/// The count assumed for comments that don't specify one explicitly.
+pub const DEFAULT_COUNT: u16 = 2;
+
+/// The number of matches collected from a single file unless configured otherwise with
+/// [`CollectOptions::max_matches_per_file`].
+pub const DEFAULT_MAX_MATCHES_PER_FILE: usize = 100_000;
+
+/// Language names by file extension. Extensions are matched case-insensitively.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("go", "Go"),
+    ("hs", "Haskell"),
+    ("java", "Java"),
+    ("js", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("kt", "Kotlin"),
+    ("md", "Markdown"),
+    ("ml", "OCaml"),
+    ("py", "Python"),
+    ("rb", "Ruby"),
+    ("rs", "Rust"),
+    ("sh", "Shell"),
+    ("swift", "Swift"),
+    ("toml", "TOML"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("yaml", "YAML"),
+    ("yml", "YAML"),
+];
+
+#[derive(Clone)]
 pub struct Matches {
     files: Vec<FileMatches>,
+    profile: CollectProfile,
+    /// See [`Matches::binary_files`]
+    binary_files: Vec<(PathBuf, u64)>,
+    /// See [`Matches::truncated_files`]
+    truncated_files: Vec<PathBuf>,
+}
+
+/// Where time went while collecting [`Matches`] and how much was searched, see
+/// [`Matches::profile`].
+#[derive(Clone, Debug, Default)]
+pub struct CollectProfile {
+    /// Time spent listing the files to search.
+    pub walk: Duration,
+    /// Time spent reading and searching files, including parsing matches.
+    pub search: Duration,
+    /// Time spent parsing matches, summed across threads, so it can exceed `search` when searching
+    /// with more than one thread.
+    pub parse: Duration,
+    /// Number of files searched, with or without matches.
+    pub files: usize,
+    /// Number of bytes read from searched files.
+    pub bytes: u64,
 }
 
 /// A collection of [matches] in a file.
 ///
 /// [matches]: Match
+#[derive(Clone)]
 struct FileMatches {
     path: PathBuf,
+    /// The contents of the file as seen when searching it. Kept so diagnostics are rendered against
+    /// the same contents even if the file changes afterwards. Invalid UTF-8 is replaced byte by
+    /// byte, see [`decode_lossy`], so spans into it are spans into the file.
+    contents: Arc<str>,
     matches: Vec<Match>,
+    /// The directory [`Comment::fingerprint`] makes the path relative to: the root the file was
+    /// collected from, or its directory if the root is a file, or `.` for in-memory buffers.
+    root: PathBuf,
 }
 
 impl FileMatches {
-    fn new(path: &Path) -> Self {
+    fn new(path: &Path, contents: Arc<str>) -> Self {
         Self {
             path: path.to_path_buf(),
+            contents,
             matches: vec![],
+            root: PathBuf::from("."),
         }
     }
 
-    fn push(&mut self, m: Match) {
-        self.matches.push(m)
+    fn comment<'a>(&'a self, m: &'a Match) -> Option<Comment<'a>> {
+        let comment = m.to_comment(&self.path)?;
+        Some(Comment {
+            root: &self.root,
+            ..comment
+        })
+    }
+
+    fn invalid<'a>(&'a self, m: &'a Match) -> Option<InvalidMatch<'a>> {
+        let invalid = m.to_invalid(&self.path, &self.contents)?;
+        Some(InvalidMatch {
+            root: &self.root,
+            ..invalid
+        })
     }
 }
 
 impl Matches {
+    /// Collect all matches in the current directory.
     pub fn collect() -> Result<Self, ignore::Error> {
-        let matcher = Matcher::new();
-        let mut files = vec![];
-        for result in ignore::Walk::new("./") {
-            let dir = result?;
+        Self::collect_with_options(&CollectOptions::default())
+    }
 
-            let Some(file_type) = dir.file_type() else {
-                continue;
-            };
+    /// Collect all matches in files under `root`, respecting ignore files (e.g., `.gitignore`). If
+    /// `root` is a file, only that file is searched.
+    pub fn collect_in(root: impl AsRef<Path>) -> Result<Self, ignore::Error> {
+        Self::collect_with_options(&CollectOptions::new().root(root))
+    }
 
-            if file_type.is_file() {
-                let path = dir.path();
-                let mut file = FileMatches::new(path);
-                grep_searcher::Searcher::new().search_path(
-                    &matcher,
-                    path,
-                    Sink(|byte_offset, line| {
-                        file.push(matcher.parse_line(byte_offset as usize, &line));
-                    }),
-                )?;
-                if !file.matches.is_empty() {
-                    files.push(file);
+    /// Collect all matches in the roots configured in `opts`. Files are sorted by path and matches
+    /// within a file by byte offset, so the result is the same regardless of the number of threads
+    /// used to search them or the order in which directories are visited.
+    pub fn collect_with_options(opts: &CollectOptions) -> Result<Self, ignore::Error> {
+        Self::collect_from(&OsFileSystem, opts)
+    }
+
+    /// Collect all matches in files of `fs` as configured by `opts`. See [`vfs`] for the file
+    /// systems available. Paths are only canonicalized with [`FileSystem::canonicalize`] when the
+    /// same file may be reached more than once, i.e., when following symbolic links or when roots
+    /// overlap, or to compare them with [`CollectOptions::only_files`].
+    pub fn collect_from(fs: &dyn FileSystem, opts: &CollectOptions) -> Result<Self, ignore::Error> {
+        // The keyword of each file is an index into `keywords`, with the default one first
+        let keywords: Vec<&str> = [&opts.keyword]
+            .into_iter()
+            .chain(opts.keyword_globs.iter().map(|(_, keyword)| keyword))
+            .map(String::as_str)
+            .collect();
+        for keyword in &keywords {
+            if keyword.is_empty() || keyword.contains(['\n', '\r']) {
+                let msg = format!("invalid keyword `{}`", keyword.escape_debug());
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+            }
+        }
+        let comment_prefix = match &opts.comment_prefix {
+            // Validate the regex as given so errors don't point at the added anchor
+            Some(prefix) => match regex::bytes::Regex::new(prefix)
+                .and_then(|_| regex::bytes::Regex::new(&format!("(?:{prefix})$")))
+            {
+                Ok(re) => Some(re),
+                Err(err) => {
+                    let msg = format!("invalid comment prefix `{prefix}`: {err}");
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+                }
+            },
+            None => None,
+        };
+
+        let started = Instant::now();
+        // Paths are tagged with the index of their root so overlapping roots, e.g., `.` and `src`,
+        // keep a file under the path of the first root reaching it
+        let mut paths = vec![];
+        let mut bases = vec![];
+        for (i, root) in opts.roots.iter().enumerate() {
+            let mut walked = fs.walk(root, opts)?;
+            walked.retain(|path| path == root || opts.has_extension(path));
+            // Globs are relative to the root, or to its directory if the root is a file
+            let base = match &walked[..] {
+                [path] if path == root => root.parent().unwrap_or(Path::new("")),
+                _ => root,
+            };
+            let globs = opts.keyword_overrides(base)?;
+            bases.push(base.to_path_buf());
+            paths.extend(walked.into_iter().map(|path| {
+                // Later globs take precedence
+                let keyword = globs
+                    .iter()
+                    .rposition(|glob| glob.matched(&path, false).is_whitelist())
+                    .map_or(0, |i| i + 1);
+                (i, path, keyword)
+            }));
+        }
+        paths.sort();
+        let only_files: Option<HashSet<_>> = opts.only_files.as_ref().map(|files| {
+            files
+                .iter()
+                .filter_map(|f| fs.canonicalize(f).ok())
+                .collect()
+        });
+        // A file reached through symbolic links or more than one root is searched once, under the
+        // first of its paths. Otherwise every path is a different file and canonicalizing them is
+        // only needed to compare against `only_files`, which may be spelled differently.
+        let canonicalize =
+            opts.follow_symlinks || only_files.is_some() || roots_overlap(fs, &opts.roots)?;
+        let mut seen = HashSet::new();
+        let mut unique = Vec::with_capacity(paths.len());
+        for (root, path, keyword) in paths {
+            if canonicalize {
+                let canonical = fs.canonicalize(&path)?;
+                if only_files
+                    .as_ref()
+                    .is_some_and(|only| !only.contains(&canonical))
+                {
+                    continue;
+                }
+                if !seen.insert(canonical) {
+                    continue;
                 }
             }
+            unique.push((path, keyword, root));
+        }
+        unique.sort();
+        let paths = unique;
+        let walk = started.elapsed();
+        let started = Instant::now();
+
+        let threads = match opts.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let search =
+            |paths: &[(PathBuf, usize, usize)]| -> io::Result<(Vec<FileMatches>, SearchTotals)> {
+                let mut searchers: Vec<_> = keywords
+                    .iter()
+                    .enumerate()
+                    .map(|(i, keyword)| {
+                        let mut matcher =
+                            Matcher::with_keyword(keyword, opts.strict_keyword_boundary);
+                        matcher.bare_labels = opts.bare_labels;
+                        matcher.strict_trailing_comma = opts.strict_trailing_comma;
+                        matcher.multiline = opts.multiline;
+                        matcher.prefix = comment_prefix.clone();
+                        if opts.tag_spelling {
+                            matcher.tag = Some(format!("@{}", keyword.to_lowercase()).into());
+                        }
+                        // Labels found with a keyword other than the default one get their own
+                        // namespace
+                        if i > 0 && *keyword != opts.keyword {
+                            matcher.namespace = Some((*keyword).into());
+                        }
+                        let mut searcher =
+                            FileSearcher::with_binary_detection(matcher, opts.binary_detection);
+                        searcher.max_matches = opts.max_matches_per_file;
+                        searcher
+                    })
+                    .collect();
+                let mut files = vec![];
+                for (path, keyword, root) in paths {
+                    if let Some(mut file) = searchers[*keyword].search_file(fs, path)? {
+                        file.root = bases[*root].clone();
+                        files.push(file);
+                    }
+                }
+                let mut totals = SearchTotals::default();
+                for searcher in searchers {
+                    totals.add(searcher.totals);
+                }
+                Ok((files, totals))
+            };
+        let (mut files, mut totals) = if threads <= 1 || paths.len() <= 1 {
+            search(&paths)?
+        } else {
+            let chunk_size = paths.len().div_ceil(threads);
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = paths
+                    .chunks(chunk_size)
+                    .map(|chunk| scope.spawn(move || search(chunk)))
+                    .collect();
+                let (mut files, mut totals) = (vec![], SearchTotals::default());
+                for handle in handles {
+                    let (chunk, chunk_totals) = handle.join().expect("search thread panicked")?;
+                    files.extend(chunk);
+                    totals.add(chunk_totals);
+                }
+                Ok::<_, io::Error>((files, totals))
+            })?
+        };
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        totals.binary_files.sort();
+        totals.truncated_files.sort();
+        let profile = CollectProfile {
+            walk,
+            search: started.elapsed(),
+            parse: totals.parse_time,
+            files: paths.len(),
+            bytes: totals.bytes_read,
+        };
+        Ok(Self {
+            files,
+            profile,
+            binary_files: totals.binary_files,
+            truncated_files: totals.truncated_files,
+        })
+    }
+
+    /// Files in which more than [`CollectOptions::max_matches_per_file`] matches were found,
+    /// sorted by path. Only the first matches up to the limit are kept for these files.
+    pub fn truncated_files(&self) -> &[PathBuf] {
+        &self.truncated_files
+    }
+
+    /// Files in which binary data was detected, sorted by path, with the offset of the first
+    /// binary byte. Always empty unless [`CollectOptions::binary_detection`] is enabled. These files
+    /// are skipped with [`BinaryDetection::Quit`] and searched with [`BinaryDetection::Convert`].
+    pub fn binary_files(&self) -> &[(PathBuf, u64)] {
+        &self.binary_files
+    }
+
+    /// Where time went while collecting the matches. Matches not collected from a file system,
+    /// e.g., with [`Matches::from_buffer`], have an empty profile.
+    pub fn profile(&self) -> &CollectProfile {
+        &self.profile
+    }
+
+    /// Collect all matches in an in-memory buffer as if it were the contents of the file at `path`.
+    pub fn from_buffer(path: &Path, contents: &str) -> Self {
+        let file = FileSearcher::new(Matcher::new())
+            .search_buffer(path, contents)
+            .expect("searching an in-memory buffer shouldn't fail");
+        Self {
+            files: file.into_iter().collect(),
+            profile: CollectProfile::default(),
+            binary_files: vec![],
+            truncated_files: vec![],
+        }
+    }
+
+    /// Collect all matches in in-memory buffers, each given with the path of the file it's the
+    /// contents of. Files are sorted by path as in [`Matches::collect_with_options`]. Meant for
+    /// tests and only available with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn from_parsed(files: Vec<(PathBuf, String)>) -> Self {
+        let mut searcher = FileSearcher::new(Matcher::new());
+        let mut files = files
+            .iter()
+            .filter_map(|(path, contents)| {
+                searcher
+                    .search_buffer(path, contents)
+                    .expect("searching an in-memory buffer shouldn't fail")
+            })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Self {
+            files,
+            profile: CollectProfile::default(),
+            binary_files: vec![],
+            truncated_files: vec![],
+        }
+    }
+
+    /// Combine the matches of two scans, e.g., of subtrees searched separately, so they can be
+    /// checked together. A file found by both scans keeps only its matches from `other`, i.e., the
+    /// later scan wins, and files stay sorted by path. [`Matches::binary_files`] are combined the
+    /// same way and profiles are added up.
+    pub fn merge(self, other: Matches) -> Matches {
+        let in_other: HashSet<_> = other.files.iter().map(|f| f.path.clone()).collect();
+        let mut files: Vec<_> = self
+            .files
+            .into_iter()
+            .filter(|f| !in_other.contains(&f.path))
+            .chain(other.files)
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let binary_in_other: HashSet<_> = other.binary_files.iter().map(|(p, _)| p).collect();
+        let mut binary_files: Vec<_> = self
+            .binary_files
+            .iter()
+            .filter(|(path, _)| !binary_in_other.contains(path))
+            .chain(&other.binary_files)
+            .cloned()
+            .collect();
+        binary_files.sort();
+
+        let mut truncated_files: Vec<_> = self
+            .truncated_files
+            .into_iter()
+            .filter(|path| !in_other.contains(path))
+            .chain(other.truncated_files)
+            .collect();
+        truncated_files.sort();
+
+        let (a, b) = (self.profile, other.profile);
+        let profile = CollectProfile {
+            walk: a.walk + b.walk,
+            search: a.search + b.search,
+            parse: a.parse + b.parse,
+            files: a.files + b.files,
+            bytes: a.bytes + b.bytes,
+        };
+        Matches {
+            files,
+            profile,
+            binary_files,
+            truncated_files,
         }
-        Ok(Self { files })
     }
 
     /// Return valid comments grouped by label. This ignores invalid matches.
-    pub fn group_by_label(&self) -> HashMap<&str, Vec<Comment>> {
-        let mut groups = HashMap::new();
+    ///
+    /// Groups are sorted by label and comments within a group are sorted by file and byte offset.
+    /// Comments found with a keyword set by [`CollectOptions::keyword_for`] are grouped apart from
+    /// the ones with the same label and the default keyword, under `KEYWORD:label`.
+    pub fn group_by_label(&self) -> BTreeMap<&str, Vec<Comment>> {
+        let mut groups = BTreeMap::new();
         for comment in self.comments() {
             groups
-                .entry(&*comment.args.label())
+                .entry(comment.args.group())
                 .or_insert(vec![])
                 .push(comment)
         }
+        for comments in groups.values_mut() {
+            sort_by_location(comments);
+        }
         groups
     }
 
+    /// Iterator over the contents of every file with at least one match, as seen when searching.
+    /// Each byte of invalid UTF-8 is replaced by `?`, so the contents have the same length as the
+    /// file and spans of matches index both.
+    pub fn sources(&self) -> impl Iterator<Item = (&Path, Arc<str>)> + '_ {
+        self.files
+            .iter()
+            .map(|file| (&*file.path, file.contents.clone()))
+    }
+
+    /// The path of each file relative to the root it was collected from as given by
+    /// [`normalized_path`], i.e., the path [`Comment::fingerprint`] identifies the file by. Unlike
+    /// the paths in [`Matches::sources`], it doesn't depend on the directory codesync runs in.
+    pub fn fingerprint_paths(&self) -> impl Iterator<Item = (&Path, String)> + '_ {
+        self.files.iter().map(|file| {
+            (
+                &*file.path,
+                normalized_path(&relative_to(&file.path, &file.root)),
+            )
+        })
+    }
+
     /// Iterator over all valid comments
     pub fn comments(&self) -> impl Iterator<Item = Comment> + '_ {
         self.files
             .iter()
-            .flat_map(|file| file.matches.iter().filter_map(|m| m.to_comment(&file.path)))
+            .flat_map(|file| file.matches.iter().filter_map(|m| file.comment(m)))
+    }
+
+    /// Iterator over all matches, valid or not, ordered by file path and then byte offset.
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_>> + '_ {
+        self.files.iter().flat_map(|file| {
+            file.matches.iter().map(|m| match file.comment(m) {
+                Some(comment) => Entry::Valid(comment),
+                None => Entry::Invalid(file.invalid(m).unwrap()),
+            })
+        })
     }
 
-    /// Iterator over all invalid matches
+    /// Remove valid comments whose label doesn't satisfy `keep`. Invalid matches are kept.
+    pub fn retain_labels(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        for file in &mut self.files {
+            file.matches
+                .retain(|m| m.args.as_ref().map_or(true, |args| keep(args.label())));
+        }
+    }
+
+    /// Remove every file whose path doesn't satisfy `keep`, e.g., before merging in a scan of the
+    /// same files that may have no matches left.
+    pub fn retain_files(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.files.retain(|f| keep(&f.path));
+        self.binary_files.retain(|(path, _)| keep(path));
+        self.truncated_files.retain(|path| keep(path));
+    }
+
+    /// Iterator over all invalid matches in `(path, byte offset)` order, regardless of how the
+    /// matches were collected, e.g., the number of threads, or merged.
     pub fn invalid_matches(&self) -> impl Iterator<Item = InvalidMatch> + '_ {
         self.files
             .iter()
-            .flat_map(|file| file.matches.iter().filter_map(|m| m.to_invalid(&file.path)))
+            .flat_map(|file| file.matches.iter().filter_map(|m| file.invalid(m)))
+    }
+
+    /// The number of invalid matches, i.e., the length of [`Matches::invalid_matches`].
+    pub fn invalid_count(&self) -> usize {
+        self.files
+            .iter()
+            .flat_map(|file| &file.matches)
+            .filter(|m| m.args.is_err())
+            .count()
+    }
+}
+
+/// Options configuring how [`Matches`] are collected. Options are set with chained methods
+/// starting from [`CollectOptions::new`], e.g.,
+///
+/// ```
+/// use codesync::CollectOptions;
+///
+/// let opts = CollectOptions::new().root("src").types(["rust"]).threads(4);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CollectOptions {
+    roots: Vec<PathBuf>,
+    keyword: String,
+    strict_keyword_boundary: bool,
+    strict_trailing_comma: bool,
+    multiline: bool,
+    include_hidden: bool,
+    no_ignore: bool,
+    types: Vec<String>,
+    /// Lowercase and without the leading dot, see [`CollectOptions::extensions`]
+    extensions: Vec<String>,
+    max_filesize: Option<u64>,
+    threads: usize,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    bare_labels: bool,
+    tag_spelling: bool,
+    comment_prefix: Option<String>,
+    binary_detection: BinaryDetection,
+    /// See [`CollectOptions::only_files`]
+    only_files: Option<Vec<PathBuf>>,
+    max_matches_per_file: Option<usize>,
+    /// `(glob, keyword)` pairs, see [`CollectOptions::keyword_for`]
+    keyword_globs: Vec<(String, String)>,
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self {
+        Self {
+            roots: vec![PathBuf::from("./")],
+            keyword: DEFAULT_KEYWORD.to_string(),
+            strict_keyword_boundary: true,
+            strict_trailing_comma: false,
+            multiline: false,
+            include_hidden: false,
+            no_ignore: false,
+            types: vec![],
+            extensions: vec![],
+            max_filesize: None,
+            threads: 1,
+            include: vec![],
+            exclude: vec![],
+            max_depth: None,
+            follow_symlinks: false,
+            bare_labels: false,
+            tag_spelling: false,
+            comment_prefix: None,
+            binary_detection: BinaryDetection::None,
+            only_files: None,
+            max_matches_per_file: Some(DEFAULT_MAX_MATCHES_PER_FILE),
+            keyword_globs: vec![],
+        }
+    }
+}
+
+impl CollectOptions {
+    /// Options searching the current directory with the defaults described in each method.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Search `root` instead of the configured roots. Directories are walked recursively and files
+    /// are searched directly. Defaults to the current directory.
+    pub fn root(mut self, root: impl AsRef<Path>) -> Self {
+        self.roots = vec![root.as_ref().to_path_buf()];
+        self
+    }
+
+    /// Search exactly `roots` instead of the configured roots, e.g., a list of files given by a
+    /// build system. Files reached from more than one root, e.g., with `.` and `src`, are searched
+    /// once, under the path of the first root reaching them.
+    pub fn roots<P: AsRef<Path>>(mut self, roots: impl IntoIterator<Item = P>) -> Self {
+        self.roots = roots
+            .into_iter()
+            .map(|root| root.as_ref().to_path_buf())
+            .collect();
+        self
+    }
+
+    /// Search `root` in addition to the configured roots.
+    pub fn add_root(mut self, root: impl AsRef<Path>) -> Self {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// The keyword marking a match. Defaults to `CODESYNC`. The keyword must be non-empty and fit
+    /// in a single line.
+    pub fn keyword(mut self, keyword: impl Into<String>) -> Self {
+        self.keyword = keyword.into();
+        self
+    }
+
+    /// Search files matching `glob` with `keyword` instead of the one set with
+    /// [`CollectOptions::keyword`], e.g., `DOCSYNC` in `**/*.md`. Globs use gitignore syntax
+    /// relative to the root being searched, or to the directory of a root that is a file. If more
+    /// than one glob matches a file, the one added last wins.
+    ///
+    /// Labels of comments found with a different keyword are a separate namespace, see
+    /// [`Matches::group_by_label`].
+    pub fn keyword_for(mut self, glob: impl Into<String>, keyword: impl Into<String>) -> Self {
+        self.keyword_globs.push((glob.into(), keyword.into()));
+        self
+    }
+
+    /// Whether to discard occurrences of the keyword immediately preceded or followed by an
+    /// identifier character (ASCII alphanumeric or `_`), e.g., `CODESYNCHRONIZED` or
+    /// `XCODESYNC(a)`, instead of reporting them as matches. Defaults to `true`.
+    pub fn strict_keyword_boundary(mut self, yes: bool) -> Self {
+        self.strict_keyword_boundary = yes;
+        self
+    }
+
+    /// Whether a comma followed by an empty count, e.g., `CODESYNC(my-label,)`, makes a match
+    /// invalid with [`ArgsError::EmptyCount`] instead of being read as a comment without a count.
+    /// Defaults to `false`.
+    pub fn strict_trailing_comma(mut self, yes: bool) -> Self {
+        self.strict_trailing_comma = yes;
+        self
+    }
+
+    /// Whether arguments missing their closing parenthesis or bracket may continue on the next
+    /// line, e.g., `// CODESYNC(very-long-` followed by `// label)`. A trailing backslash is
+    /// removed from the first line, and the indentation and a line comment delimiter from the
+    /// second, before joining them without whitespace, so the example has label
+    /// `very-long-label`. Matches are still reported as missing their closing delimiter if the
+    /// joined arguments aren't valid. Defaults to `false`.
+    pub fn multiline(mut self, yes: bool) -> Self {
+        self.multiline = yes;
+        self
+    }
+
+    /// Whether to search hidden files and directories, i.e., the ones whose name starts with `.`.
+    /// The `.git` directory is skipped regardless. Defaults to `false`.
+    pub fn include_hidden(mut self, yes: bool) -> Self {
+        self.include_hidden = yes;
+        self
+    }
+
+    /// Whether to search files excluded by ignore files (`.gitignore`, `.ignore`, global git
+    /// excludes, and ignore files in parent directories). Defaults to `false`.
+    pub fn no_ignore(mut self, yes: bool) -> Self {
+        self.no_ignore = yes;
+        self
+    }
+
+    /// Only search files matching one of the given file types when walking directories, e.g.,
+    /// `rust` or `py`. Types are the ones known by ripgrep (see `rg --type-list`). Roots that are
+    /// files are always searched. Defaults to all files.
+    pub fn types<S: Into<String>>(mut self, types: impl IntoIterator<Item = S>) -> Self {
+        self.types = types.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only search files with one of the given extensions when walking directories, e.g., `rs` or
+    /// `.ts`. Extensions are compared ignoring case and a leading dot is optional. This is a simpler
+    /// alternative to [`CollectOptions::types`] and files must pass both filters, as well as ignore
+    /// files and globs. Roots that are files are always searched. Defaults to all files.
+    pub fn extensions<S: AsRef<str>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.extensions = extensions
+            .into_iter()
+            .map(|ext| ext.as_ref().trim_start_matches('.').to_lowercase())
+            .collect();
+        self
+    }
+
+    /// Skip files larger than the given number of bytes when walking directories. Defaults to no
+    /// limit.
+    pub fn max_filesize(mut self, bytes: Option<u64>) -> Self {
+        self.max_filesize = bytes;
+        self
+    }
+
+    /// Number of threads used to search files, with `0` meaning one per available CPU. The result
+    /// doesn't depend on the number of threads, see [`Matches::collect_with_options`]. Defaults to
+    /// `1`.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Only search files matching one of the given globs when walking directories, e.g.,
+    /// `src/**`. Globs use gitignore syntax and are matched against paths relative to the root
+    /// being walked. They take precedence over ignore files, so a file matching a glob is searched
+    /// even if it's ignored. Roots that are files are always searched. Defaults to all files.
+    pub fn include<S: Into<String>>(mut self, globs: impl IntoIterator<Item = S>) -> Self {
+        self.include = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skip files and directories matching one of the given globs when walking directories, e.g.,
+    /// `tests/fixtures/**` or `*.min.js`. Globs are interpreted as in [`CollectOptions::include`]
+    /// and excluding takes precedence over including. Defaults to none.
+    pub fn exclude<S: Into<String>>(mut self, globs: impl IntoIterator<Item = S>) -> Self {
+        self.exclude = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Don't descend more than this many directories below each root when walking, e.g., with `1`
+    /// only files directly inside a root are searched. Defaults to no limit.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Whether to accept a label without parentheses, e.g., `// CODESYNC my-label`, taking the
+    /// whitespace-delimited word after the keyword as the label of a comment without a count.
+    /// Parenthesized arguments are parsed as usual. Defaults to `false`, reporting such comments
+    /// as malformed.
+    pub fn bare_labels(mut self, yes: bool) -> Self {
+        self.bare_labels = yes;
+        self
+    }
+
+    /// Whether to also accept the keyword in lowercase prefixed with `@`, e.g.,
+    /// `@codesync(my-label, 2)`, for documentation tools that treat `@` tags specially. Both
+    /// spellings share labels and the `@` counts as the boundary before the keyword. Keywords set
+    /// by [`CollectOptions::keyword_for`] get their own tag, e.g., `@docsync`. Defaults to `false`.
+    pub fn tag_spelling(mut self, yes: bool) -> Self {
+        self.tag_spelling = yes;
+        self
+    }
+
+    /// Only accept occurrences of the keyword immediately preceded by a match of the given regex
+    /// on the same line, e.g., `^\s*//\s*` for line comments at the start of a line. The regex is
+    /// matched against the text from the start of the line up to the keyword and must match up to
+    /// the keyword, so `^` anchors it at the start of the line. Other occurrences are ignored as if
+    /// they weren't there. Defaults to accepting the keyword anywhere.
+    pub fn comment_prefix(mut self, regex: Option<impl Into<String>>) -> Self {
+        self.comment_prefix = regex.map(Into::into);
+        self
+    }
+
+    /// Whether to follow symbolic links when walking directories. Loops are detected and reported
+    /// as errors, and a file reached through more than one path is only searched once. Defaults to
+    /// `false`.
+    pub fn follow_symlinks(mut self, yes: bool) -> Self {
+        self.follow_symlinks = yes;
+        self
+    }
+
+    /// How files with binary data, i.e., NUL bytes, are handled. Files where it's detected are
+    /// listed by [`Matches::binary_files`]. Defaults to [`BinaryDetection::None`].
+    pub fn binary_detection(mut self, detection: BinaryDetection) -> Self {
+        self.binary_detection = detection;
+        self
+    }
+
+    /// Stop collecting matches in a file after the given number, so a degenerate file with the
+    /// keyword repeated many times doesn't exhaust memory. Files where this happens are listed by
+    /// [`Matches::truncated_files`]. Defaults to [`DEFAULT_MAX_MATCHES_PER_FILE`], with `None`
+    /// meaning no limit.
+    pub fn max_matches_per_file(mut self, max: Option<usize>) -> Self {
+        self.max_matches_per_file = max;
+        self
+    }
+
+    /// Only search files found walking the roots that are also in `files`, e.g., the files
+    /// tracked by a version control system. Paths are compared after resolving symbolic links, so
+    /// they don't need to be spelled as the walk finds them, and paths that don't exist are
+    /// ignored. Defaults to all files found.
+    pub fn only_files<P: AsRef<Path>>(mut self, files: impl IntoIterator<Item = P>) -> Self {
+        let files = files.into_iter().map(|f| f.as_ref().to_path_buf());
+        self.only_files = Some(files.collect());
+        self
+    }
+
+    /// Whether `path` passes the filter set by [`CollectOptions::extensions`].
+    fn has_extension(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
+            return false;
+        };
+        let ext = ext.to_lowercase();
+        self.extensions.contains(&ext)
+    }
+
+    /// A matcher for each glob in [`CollectOptions::keyword_for`] relative to `base`.
+    fn keyword_overrides(
+        &self,
+        base: &Path,
+    ) -> Result<Vec<ignore::overrides::Override>, ignore::Error> {
+        self.keyword_globs
+            .iter()
+            .map(|(glob, _)| {
+                let mut builder = ignore::overrides::OverrideBuilder::new(base);
+                builder.add(glob)?;
+                builder.build()
+            })
+            .collect()
+    }
+
+    fn walker(&self, root: &Path) -> Result<ignore::Walk, ignore::Error> {
+        let mut builder = ignore::WalkBuilder::new(root);
+        builder
+            .hidden(!self.include_hidden)
+            .parents(!self.no_ignore)
+            .ignore(!self.no_ignore)
+            .git_ignore(!self.no_ignore)
+            .git_global(!self.no_ignore)
+            .git_exclude(!self.no_ignore)
+            .max_filesize(self.max_filesize)
+            .max_depth(self.max_depth)
+            .follow_links(self.follow_symlinks)
+            .filter_entry(|entry| entry.file_name() != ".git");
+        if !self.types.is_empty() {
+            let mut types = ignore::types::TypesBuilder::new();
+            types.add_defaults();
+            for name in &self.types {
+                types.select(name);
+            }
+            builder.types(types.build()?);
+        }
+        if !self.include.is_empty() || !self.exclude.is_empty() {
+            let mut overrides = ignore::overrides::OverrideBuilder::new(root);
+            for glob in &self.include {
+                overrides.add(glob)?;
+            }
+            // Later globs take precedence, so exclusions are added last.
+            for glob in &self.exclude {
+                overrides.add(&format!("!{glob}"))?;
+            }
+            builder.overrides(overrides.build()?);
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Whether a root is the same as or under another one, comparing their canonical paths.
+fn roots_overlap(fs: &dyn FileSystem, roots: &[PathBuf]) -> io::Result<bool> {
+    if roots.len() < 2 {
+        return Ok(false);
     }
+    let roots = roots
+        .iter()
+        .map(|root| fs.canonicalize(root))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(roots.iter().enumerate().any(|(i, a)| {
+        roots[i + 1..]
+            .iter()
+            .any(|b| a.starts_with(b) || b.starts_with(a))
+    }))
 }
 
 /// A *match* is an occurrence of the `CODESYNC` pattern which may or may not be valid. A match
 /// is identified by the offset in bytes from the beginning of the file where the pattern was found.
+#[derive(Clone)]
 pub struct Match {
     args: Result<Args, ArgsError>,
     /// The offset in bytes from the beginning of the file to the start of the match
     byte_offset: usize,
+    /// The offset in bytes from the beginning of the match's line to the start of the match
+    column_byte_offset: usize,
+    /// The length of the keyword that was matched
+    keyword_len: usize,
+    /// The extent of the source comment containing the match, see [`Comment::full_span`]
+    comment: Range<usize>,
+    /// Whether the source comment is a documentation comment, see [`Comment::is_doc_comment`]
+    doc_comment: bool,
+    /// Suppression directives applying to the match, see [`Comment::suppressions`]
+    suppressions: Vec<Suppression>,
 }
 
 impl Match {
@@ -108,18 +905,23 @@ impl Match {
                 args: opts,
                 file,
                 m: self,
+                root: Path::new("."),
             })
         } else {
             None
         }
     }
 
-    pub fn to_invalid<'a>(&'a self, file: &'a Path) -> Option<InvalidMatch> {
+    /// The match as an [`InvalidMatch`] if its arguments are invalid. `contents` are the contents of
+    /// the file the match was found in.
+    pub fn to_invalid<'a>(&'a self, file: &'a Path, contents: &'a str) -> Option<InvalidMatch<'a>> {
         if let Err(error) = self.args {
             Some(InvalidMatch {
                 error,
                 m: self,
                 file,
+                contents,
+                root: Path::new("."),
             })
         } else {
             None
@@ -128,7 +930,7 @@ impl Match {
 
     fn span(&self) -> Range<usize> {
         let start = self.byte_offset;
-        let mut end = start + PATTERN.len();
+        let mut end = start + self.keyword_len;
         if let Ok(args) = &self.args {
             end += args.len;
         }
@@ -142,14 +944,60 @@ pub struct Comment<'a> {
     args: &'a Args,
     file: &'a Path,
     m: &'a Match,
+    /// See [`FileMatches::root`]
+    root: &'a Path,
 }
 
-impl Comment<'_> {
+impl<'a> Comment<'a> {
+    /// The span of the comment from the start of the `CODESYNC` keyword through the closing `)`
+    /// of its arguments. The spans of the individual arguments are given by [`Arg::span`].
     pub fn span(&self) -> Range<usize> {
         self.m.span()
     }
 
-    pub fn file(&self) -> &Path {
+    /// The span of the source comment containing the codesync comment, including the comment
+    /// delimiters, e.g., all of `/* CODESYNC(my-label) */` or `// CODESYNC(my-label) text`. Line
+    /// comments extend until the end of the line (excluding the line terminator). This always
+    /// contains [`Comment::span`] and is equal to it if the comment syntax isn't recognized. It's
+    /// meant for tools that need to remove or replace the whole comment.
+    pub fn full_span(&self) -> Range<usize> {
+        self.m.comment.clone()
+    }
+
+    /// Whether the comment is inside a documentation comment, e.g., `/// CODESYNC(my-label)` in
+    /// Rust. See [`Comment::full_span`] for how the surrounding comment is detected.
+    pub fn is_doc_comment(&self) -> bool {
+        self.m.doc_comment
+    }
+
+    /// The span of the note's text between its quotes, e.g., `keep in sync with bar` in
+    /// `CODESYNC(foo, 2, "keep in sync with bar")`, or `None` if the comment has no note.
+    pub fn note_span(&self) -> Option<Range<usize>> {
+        let note = self.args.note.as_ref()?;
+        let start = note.span.start + note.match_.find('"')? + 1;
+        Some(start..start + note.val.len())
+    }
+
+    /// The offset in bytes from the beginning of the file to the start of the comment.
+    pub fn byte_offset(&self) -> usize {
+        self.m.byte_offset
+    }
+
+    /// The length in bytes of the arguments including the delimiting parentheses (or brackets)
+    /// and any whitespace between the keyword and the opening one, e.g., 11 for ` (my-label)` in
+    /// `CODESYNC (my-label)`. The arguments end at [`Comment::span`]'s end, which is
+    /// [`Comment::byte_offset`] plus the keyword's length plus this.
+    pub fn args_len(&self) -> usize {
+        self.args.len
+    }
+
+    /// The offset in bytes from the beginning of the comment's line to the start of the comment,
+    /// i.e., [`Comment::byte_offset`] minus the offset of the line.
+    pub fn column_byte_offset(&self) -> usize {
+        self.m.column_byte_offset
+    }
+
+    pub fn file(&self) -> &'a Path {
         self.file
     }
 
@@ -157,8 +1005,78 @@ impl Comment<'_> {
         self.args.label()
     }
 
+    /// The label as given by [`normalize_label`].
+    pub fn normalized_label(&self) -> String {
+        normalize_label(self.label())
+    }
+
+    /// Whether the comment's label is the same as `label` ignoring casing and word separators,
+    /// see [`normalize_label`].
+    pub fn eq_label(&self, label: &str) -> bool {
+        self.normalized_label() == normalize_label(label)
+    }
+
+    /// Whether the label only has ASCII digits, e.g., `CODESYNC(42)`, which most likely was meant
+    /// as a count with a missing label.
+    pub fn has_numeric_label(&self) -> bool {
+        let label = self.label();
+        !label.is_empty() && label.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    /// The path of the file containing the comment relative to `base`, see [`relative_to`].
+    pub fn relative_path(&self, base: &Path) -> PathBuf {
+        relative_to(self.file, base)
+    }
+
+    /// A stable identity for the comment that survives unrelated edits to its file. It's the
+    /// [`stable_hash`] of `comment`, the file path relative to the root it was collected from as
+    /// given by [`normalized_path`], and the label, in that order. The path is relative to the
+    /// root's directory if the root is a file, and to the current directory for comments in
+    /// in-memory buffers. Comments with the same label in the same file share a fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        let path = normalized_path(&self.relative_path(self.root));
+        stable_hash(["comment", &path, self.label()])
+    }
+
+    /// The name of the language of the file containing the comment based on its extension, or
+    /// `None` if the extension is unknown.
+    pub fn language(&self) -> Option<&'static str> {
+        let ext = self.file.extension()?.to_str()?;
+        LANGUAGES
+            .iter()
+            .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+            .map(|(_, name)| *name)
+    }
+
+    /// The count of the comment or [`DEFAULT_COUNT`] if it doesn't have one. Variables can't be
+    /// resolved without the configuration defining them, so a count referencing one is also
+    /// [`DEFAULT_COUNT`], see [`Comment::count_with_vars`].
     pub fn count(&self) -> u16 {
-        self.args.count.as_ref().map(|c| c.val).unwrap_or(2)
+        self.count_with_vars(&HashMap::new())
+            .unwrap_or(DEFAULT_COUNT)
+    }
+
+    /// The count of the comment or [`DEFAULT_COUNT`] if it doesn't have one, resolving variables
+    /// with `vars`. Returns `None` if the count is a variable not defined in `vars`.
+    pub fn count_with_vars(&self, vars: &HashMap<String, u16>) -> Option<u16> {
+        match &self.args.count {
+            Some(arg) => arg.val.resolve(vars),
+            None => Some(DEFAULT_COUNT),
+        }
+    }
+
+    /// Whether the comment doesn't have an explicit count, in which case [`Comment::count`] is
+    /// [`DEFAULT_COUNT`].
+    pub fn count_is_default(&self) -> bool {
+        self.args.count.is_none()
+    }
+
+    /// The name of the variable used as count, if any.
+    pub fn count_var(&self) -> Option<&str> {
+        match &self.args.count.as_ref()?.val {
+            Count::Var(name) => Some(name),
+            Count::Literal(_) => None,
+        }
     }
 
     pub fn count_arg(&self) -> Option<&CountArg> {
@@ -168,175 +1086,2807 @@ impl Comment<'_> {
     pub fn label_arg(&self) -> &LabelArg {
         &self.args.label
     }
-}
-
-/// An [match] that's not correctly formatted or is missing some arguments.
-///
-/// [match]: Match
-pub struct InvalidMatch<'a> {
-    pub error: ArgsError,
-    file: &'a Path,
-    m: &'a Match,
-}
 
-impl InvalidMatch<'_> {
-    pub fn span(&self) -> Range<usize> {
-        self.m.span()
+    /// The note explaining the comment, written as the last argument between double quotes, e.g.,
+    /// `CODESYNC(my-label, 2, "keep in sync with the parser")` or
+    /// `CODESYNC(my-label, "keep in sync with the parser")`. Its value is the text between the
+    /// quotes.
+    pub fn note_arg(&self) -> Option<&NoteArg> {
+        self.args.note.as_ref()
     }
 
-    pub fn file(&self) -> &Path {
-        self.file
+    /// The bytes to replace to change the label, including any whitespace around it but never the
+    /// keyword, the parentheses, or the delimiters of the surrounding source comment, e.g., ` foo `
+    /// in `/* CODESYNC( foo ) */`. This is [`Arg::span`] of [`Comment::label_arg`] and is the
+    /// contract `codesync fix` follows when changing a label, for external tools to rely on too.
+    pub fn label_byte_range(&self) -> Range<usize> {
+        self.args.label.span()
     }
-}
 
-struct Args {
-    label: LabelArg,
-    count: Option<CountArg>,
-    /// The length of the parsed string including delimiting parentheses
-    len: usize,
-}
+    /// Like [`Comment::label_byte_range`] but for the count, or `None` if the comment has no
+    /// explicit count.
+    pub fn count_byte_range(&self) -> Option<Range<usize>> {
+        self.args.count.as_ref().map(Arg::span)
+    }
 
-impl Args {
-    pub fn label(&self) -> &str {
-        &self.label.val
+    /// The span of just the count as written, without surrounding whitespace, e.g., `$N` in
+    /// `CODESYNC(foo, $N )`, or `None` if the comment has no explicit count. Diagnostics about the
+    /// count underline this span, like they underline [`Arg::span`] of [`Comment::label_arg`] for
+    /// the label.
+    pub fn count_span(&self) -> Option<Range<usize>> {
+        let count = self.args.count.as_ref()?;
+        let (leading, trailing) = surrounding_whitespace(count.raw());
+        let span = count.span();
+        Some(span.start + leading.len()..span.end - trailing.len())
     }
-}
 
-pub struct Arg<T> {
-    /// Processed value, i.e., trimmed and parsed.
-    val: T,
-    /// The original string that was matched.
-    match_: String,
-    /// The span (in bytes) of the match whitin the file.
-    span: Range<usize>,
-}
+    /// Suppression directives written on the comment's line or on a line of their own right before
+    /// it, see [`Suppression`].
+    pub fn suppressions(&self) -> &'a [Suppression] {
+        &self.m.suppressions
+    }
 
-impl<T> Arg<T> {
-    pub fn span(&self) -> Range<usize> {
-        self.span.clone()
+    /// The span of the whitespace between the keyword and the opening parenthesis, e.g., in
+    /// `CODESYNC (my-label)`, or `None` if there's none.
+    pub fn keyword_gap(&self) -> Option<Range<usize>> {
+        let start = self.m.byte_offset + self.m.keyword_len;
+        (self.args.gap > 0).then_some(start..start + self.args.gap)
     }
 
-    pub fn has_extra_whitespace(&self) -> bool {
-        self.match_.trim() != &self.match_
+    /// Edits giving the comment canonical spacing, as `(span, replacement)` pairs sorted by
+    /// position, or none if it already has it. The canonical spacing has no whitespace between the
+    /// keyword and the opening parenthesis (or bracket), after the opening parenthesis, before the
+    /// comma, or before the closing parenthesis, and exactly one space after each comma, e.g.,
+    /// `CODESYNC(my-label)` or `CODESYNC(my-label, 2, "note")`.
+    pub fn spacing_edits(&self) -> Vec<(Range<usize>, &'static str)> {
+        let mut edits: Vec<_> = self
+            .keyword_gap()
+            .map(|gap| (gap, ""))
+            .into_iter()
+            .collect();
+        let label = self.label_arg();
+        let (leading, trailing) = surrounding_whitespace(label.raw());
+        let span = label.span();
+        if !leading.is_empty() {
+            edits.push((span.start..span.start + leading.len(), ""));
+        }
+        if !trailing.is_empty() {
+            edits.push((span.end - trailing.len()..span.end, ""));
+        }
+        let count = self.count_arg().map(|count| (count.raw(), count.span()));
+        let note = self.note_arg().map(|note| (note.raw(), note.span()));
+        for (raw, span) in count.into_iter().chain(note) {
+            let (leading, trailing) = surrounding_whitespace(raw);
+            if leading != " " {
+                edits.push((span.start..span.start + leading.len(), " "));
+            }
+            if !trailing.is_empty() {
+                edits.push((span.end - trailing.len()..span.end, ""));
+            }
+        }
+        edits
     }
 }
 
-type LabelArg = Arg<String>;
-type CountArg = Arg<u16>;
-
-#[derive(Debug, Copy, Clone)]
-pub enum ArgsError {
-    Malformed,
-    InvalidCount { start: usize, end: usize },
+/// The leading and trailing whitespace of `s`. If `s` only has whitespace, all of it is leading.
+fn surrounding_whitespace(s: &str) -> (&str, &str) {
+    let start = s.len() - s.trim_start().len();
+    let end = s.trim_end().len().max(start);
+    (&s[..start], &s[end..])
 }
 
-struct Matcher {
-    re: regex::Regex,
+/// A match yielded by [`Matches::entries`], either a valid comment or an invalid match.
+pub enum Entry<'a> {
+    Valid(Comment<'a>),
+    Invalid(InvalidMatch<'a>),
 }
 
-impl Matcher {
-    fn new() -> Matcher {
-        const OPTS_REGEX: &str = r"^\(([^,\)]+)(?:,([^\)]*))?\)";
-        Matcher {
-            re: regex::Regex::new(OPTS_REGEX).unwrap(),
+impl<'a> Entry<'a> {
+    pub fn file(&self) -> &'a Path {
+        match self {
+            Entry::Valid(comment) => comment.file(),
+            Entry::Invalid(m) => m.file(),
         }
     }
 
-    fn parse_line(&self, byte_offset: usize, line: &str) -> Match {
-        let idx = find_codesync_pattern(line.as_bytes()).expect("line should be a match");
-        let opts = self.parse_args(
-            byte_offset + idx + PATTERN.len(),
-            &line[idx + PATTERN.len()..],
-        );
+    /// The span of the match, see [`Comment::span`] and [`InvalidMatch::span`].
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Entry::Valid(comment) => comment.span(),
+            Entry::Invalid(m) => m.span(),
+        }
+    }
 
-        Match {
-            args: opts,
-            byte_offset: byte_offset + idx,
+    /// Suppression directives applying to the match, see [`Comment::suppressions`].
+    pub fn suppressions(&self) -> &'a [Suppression] {
+        match self {
+            Entry::Valid(comment) => comment.suppressions(),
+            Entry::Invalid(m) => m.suppressions(),
         }
     }
+}
 
-    fn parse_args(&self, byte_offset: usize, haystack: &str) -> Result<Args, ArgsError> {
-        let Some(captures) = self.re.captures(haystack) else {
-            return Err(ArgsError::Malformed);
-        };
+/// An inline directive like `codesync:allow(extra-whitespace, missing-note)` suppressing the named
+/// diagnostics, or all of them with `all`, for the match on the same line. A directive on a line
+/// without a match applies to the match on the next line instead, e.g.,
+///
+/// ```text
+/// // codesync:allow(missing-note)
+/// // CODESYNC(my-label)
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suppression {
+    span: Range<usize>,
+    lints: Vec<String>,
+}
 
-        let m = captures.get(1).unwrap();
-        let label = LabelArg {
-            val: m.as_str().trim().to_string(),
-            match_: m.as_str().to_string(),
-            span: (byte_offset + m.start()..byte_offset + m.end()),
+impl Suppression {
+    /// The span of the directive from `codesync:allow` through the closing `)`.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// The suppressed diagnostic codes as written, e.g., `["extra-whitespace", "missing-note"]`.
+    pub fn lints(&self) -> &[String] {
+        &self.lints
+    }
+
+    /// Whether the directive suppresses diagnostics with the given code.
+    pub fn allows(&self, code: &str) -> bool {
+        self.lints.iter().any(|lint| lint == code || lint == "all")
+    }
+}
+
+const SUPPRESSION_DIRECTIVE: &str = "codesync:allow(";
+
+/// Parse the suppression directives in `line`, which starts at byte `offset` of its file. Directives
+/// without a closing `)` are ignored.
+fn parse_suppressions(line: &str, offset: usize) -> Vec<Suppression> {
+    let mut suppressions = vec![];
+    for (start, _) in line.match_indices(SUPPRESSION_DIRECTIVE) {
+        let args_start = start + SUPPRESSION_DIRECTIVE.len();
+        let Some(len) = line[args_start..].find(')') else {
+            continue;
         };
+        let lints = line[args_start..args_start + len]
+            .split(',')
+            .map(str::trim)
+            .filter(|lint| !lint.is_empty())
+            .map(String::from)
+            .collect();
+        suppressions.push(Suppression {
+            span: offset + start..offset + args_start + len + 1,
+            lints,
+        });
+    }
+    suppressions
+}
+
+/// Attach to each match in `contents` the suppression directives on its line and, if the previous
+/// line has no match, on the previous line. `matches` must be sorted by byte offset.
+fn attach_suppressions(contents: &str, matches: &mut [Match]) {
+    let line_start = |offset: usize| contents[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = |offset: usize| {
+        contents[offset..]
+            .find('\n')
+            .map_or(contents.len(), |i| offset + i)
+    };
+    let lines: Vec<_> = matches.iter().map(|m| line_start(m.byte_offset)).collect();
+    for (m, &start) in matches.iter_mut().zip(&lines) {
+        let mut suppressions = vec![];
+        if start > 0 {
+            let previous = line_start(start - 1);
+            if lines.binary_search(&previous).is_err() {
+                suppressions = parse_suppressions(&contents[previous..start - 1], previous);
+            }
+        }
+        suppressions.extend(parse_suppressions(&contents[start..line_end(start)], start));
+        m.suppressions = suppressions;
+    }
+}
+
+/// Sort comments by file and then by byte offset within the file.
+pub fn sort_by_location(comments: &mut [Comment]) {
+    comments.sort_by_key(|c| (c.file(), c.byte_offset()));
+}
+
+/// Whether `label` is `prefix` or is nested under it when labels are split into segments by
+/// `separator`, e.g., `payments/refunds/state` is under `payments` but `paymentsv2` is not. A
+/// trailing separator in `prefix` is ignored and the empty prefix matches every label.
+pub fn has_label_prefix(label: &str, prefix: &str, separator: &str) -> bool {
+    let prefix = prefix.strip_suffix(separator).unwrap_or(prefix);
+    if prefix.is_empty() {
+        return true;
+    }
+    match label.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with(separator),
+        None => false,
+    }
+}
+
+/// The outcome of checking the number of comments sharing a label against their counts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CountCheck {
+    /// The number of comments matches the expected count.
+    Ok,
+    /// The number of comments doesn't match the expected count.
+    Mismatch { expected: u16, found: usize },
+    /// Comments specify more than one distinct count explicitly.
+    Conflicting,
+}
+
+/// How an explicit count relates to the number of comments sharing a label.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CountSemantics {
+    /// The count is the total number of comments with the label, including the one with the count.
+    #[default]
+    Total,
+    /// The count is the number of *other* comments with the label, i.e., `CODESYNC(my-label, 2)`
+    /// expects 3 comments in total.
+    Others,
+}
+
+impl CountSemantics {
+    /// The expected total number of comments for an explicit `count`.
+    fn total(self, count: u16) -> u16 {
+        match self {
+            CountSemantics::Total => count,
+            CountSemantics::Others => count.saturating_add(1),
+        }
+    }
+}
+
+/// What is counted when comparing comments sharing a label against their count.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CountUnit {
+    /// Every comment, including several in the same file.
+    #[default]
+    Occurrence,
+    /// Every file with at least one comment, so comments repeated within a file count once.
+    File,
+}
+
+/// How [`check_count_with`] and [`expected_count`] read counts. The default reads counts as the
+/// total number of comments, counts every comment, and expects [`DEFAULT_COUNT`] comments when
+/// none has an explicit count.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CountOptions {
+    pub semantics: CountSemantics,
+    /// What is counted. [`CountCheck::Mismatch`] reports the number of units found.
+    pub unit: CountUnit,
+    /// The total number of comments expected when none of them has an explicit count, e.g., a
+    /// count computed by another tool.
+    pub default: u16,
+}
+
+impl Default for CountOptions {
+    fn default() -> Self {
+        Self {
+            semantics: CountSemantics::default(),
+            unit: CountUnit::default(),
+            default: DEFAULT_COUNT,
+        }
+    }
+}
+
+/// Check the number of comments sharing a label against their counts with the default
+/// [`CountOptions`].
+///
+/// Comments without an explicit count don't constrain the expected count. If there's a single
+/// explicit count among the comments it's authoritative and the number of comments is compared
+/// against it. If there are no explicit counts, [`DEFAULT_COUNT`] is expected. Multiple distinct
+/// explicit counts are a conflict. Counts referencing variables are resolved using `vars` and
+/// ignored if the variable is undefined.
+pub fn check_count(comments: &[Comment], vars: &HashMap<String, u16>) -> CountCheck {
+    check_count_with(comments, vars, CountOptions::default())
+}
+
+/// Like [`check_count`] but reading counts according to `opts`. [`CountCheck::Mismatch`] always
+/// reports the expected total number of units.
+pub fn check_count_with(
+    comments: &[Comment],
+    vars: &HashMap<String, u16>,
+    opts: CountOptions,
+) -> CountCheck {
+    let Some(ExpectedCount {
+        total: expected, ..
+    }) = expected_count(comments, vars, opts)
+    else {
+        return CountCheck::Conflicting;
+    };
+    let found = count_units(comments, opts.unit);
+    if found == expected as usize {
+        CountCheck::Ok
+    } else {
+        CountCheck::Mismatch { expected, found }
+    }
+}
 
-        let count = if let Some(m) = captures.get(2) {
-            let (start, end) = (byte_offset + m.start(), byte_offset + m.end());
-            let val = m
-                .as_str()
-                .trim()
-                .parse::<u16>()
-                .map_err(|_| ArgsError::InvalidCount { start, end })?;
-            Some(CountArg {
-                val,
-                match_: m.as_str().to_string(),
-                span: (start..end),
+/// The total number of comments expected for a label. See [`expected_count`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExpectedCount {
+    pub total: u16,
+    /// Whether the count was written explicitly in one of the comments or the default one, usually
+    /// [`DEFAULT_COUNT`], is used.
+    pub explicit: bool,
+}
+
+/// The total number of comments expected among `comments` sharing a label, following the same
+/// rules as [`check_count_with`]. Returns `None` if comments specify more than one distinct count
+/// explicitly.
+pub fn expected_count(
+    comments: &[Comment],
+    vars: &HashMap<String, u16>,
+    opts: CountOptions,
+) -> Option<ExpectedCount> {
+    let explicit: HashSet<u16> = comments
+        .iter()
+        .filter_map(|c| c.count_arg()?.val.resolve(vars))
+        .collect();
+
+    match explicit.len() {
+        0 => Some(ExpectedCount {
+            total: opts.default,
+            explicit: false,
+        }),
+        1 => {
+            let count = explicit.into_iter().next().unwrap();
+            Some(ExpectedCount {
+                total: opts.semantics.total(count),
+                explicit: true,
             })
+        }
+        _ => None,
+    }
+}
+
+/// The number of `unit`s among `comments`, i.e., what is compared against their count.
+fn count_units(comments: &[Comment], unit: CountUnit) -> usize {
+    match unit {
+        CountUnit::Occurrence => comments.len(),
+        CountUnit::File => comments
+            .iter()
+            .map(|c| c.file())
+            .collect::<HashSet<_>>()
+            .len(),
+    }
+}
+
+/// Formats the comment as `label @ path (bytes start..end)`, e.g., `foo @ src/a.rs (bytes 100..112)`.
+impl fmt::Display for Comment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{} @ {} (bytes {}..{})",
+            self.label(),
+            self.file.display(),
+            span.start,
+            span.end
+        )
+    }
+}
+
+/// An [match] that's not correctly formatted or is missing some arguments.
+///
+/// [match]: Match
+pub struct InvalidMatch<'a> {
+    pub error: ArgsError,
+    file: &'a Path,
+    contents: &'a str,
+    m: &'a Match,
+    /// See [`FileMatches::root`]
+    root: &'a Path,
+}
+
+impl<'a> InvalidMatch<'a> {
+    pub fn span(&self) -> Range<usize> {
+        self.m.span()
+    }
+
+    pub fn file(&self) -> &'a Path {
+        self.file
+    }
+
+    /// The offset in bytes from the beginning of the match's line to the start of the match, see
+    /// [`Comment::column_byte_offset`].
+    pub fn column_byte_offset(&self) -> usize {
+        self.m.column_byte_offset
+    }
+
+    /// Suppression directives applying to the match, see [`Comment::suppressions`].
+    pub fn suppressions(&self) -> &'a [Suppression] {
+        &self.m.suppressions
+    }
+
+    /// The code of the diagnostic reported for the match, either `malformed` or `invalid-count`.
+    /// See `codesync --explain`.
+    pub fn error_kind(&self) -> &'static str {
+        match self.error {
+            ArgsError::InvalidCount { .. }
+            | ArgsError::CountOutOfRange { .. }
+            | ArgsError::EmptyCount { .. } => "invalid-count",
+            ArgsError::Malformed
+            | ArgsError::MissingClosingParen { .. }
+            | ArgsError::MissingClosingBracket { .. }
+            | ArgsError::MismatchedBrackets { .. }
+            | ArgsError::NestedKeyword { .. } => "malformed",
+        }
+    }
+
+    /// The text of the match as found in the file, from the start of [`InvalidMatch::span`] to the
+    /// end of its line without trailing whitespace, e.g., `CODESYNC(my-label, x)`. Since the
+    /// arguments couldn't be parsed, this may include text after them like a comment's closing
+    /// delimiter.
+    pub fn snippet(&self) -> &'a str {
+        let rest = &self.contents[self.span().start..];
+        rest.lines().next().unwrap_or("").trim_end()
+    }
+
+    /// The path of the file containing the match relative to `base`, see [`relative_to`].
+    pub fn relative_path(&self, base: &Path) -> PathBuf {
+        relative_to(self.file, base)
+    }
+
+    /// A stable identity for the match that survives unrelated edits to its file. It's the
+    /// [`stable_hash`] of [`ArgsError::kind`] and the file path relative to the root it was
+    /// collected from as given by [`normalized_path`], in that order, see
+    /// [`Comment::fingerprint`]. Matches with the same kind of error in the same file share a
+    /// fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        let path = normalized_path(&self.relative_path(self.root));
+        stable_hash([self.error.kind(), &path])
+    }
+}
+
+/// A 64-bit FNV-1a hash of `parts` joined with NUL bytes. Unlike [`std::hash::DefaultHasher`],
+/// the result is guaranteed to be the same across runs, platforms, and versions of codesync.
+pub fn stable_hash<'a>(parts: impl IntoIterator<Item = &'a str>) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for (i, part) in parts.into_iter().enumerate() {
+        let sep = if i == 0 { &[][..] } else { &[0u8][..] };
+        for &b in sep.iter().chain(part.as_bytes()) {
+            hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// The key under which labels that only differ in casing or word separators are considered the
+/// same, e.g., `MyLabel`, `my-label`, `my_label`, and `MY LABEL` all become `mylabel`. Every
+/// character that isn't alphanumeric is removed, which includes the separators recognized by
+/// [`inflector`], and the rest are lowercased.
+pub fn normalize_label(label: &str) -> String {
+    label
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// `label` with every run of word separators, i.e., `-`, `_`, `.`, and whitespace, replaced by a
+/// single `-`, e.g., `my_label`, `my.label`, and `my--label` all become `my-label`. Unlike
+/// [`normalize_label`], casing and the position of separators are kept, so `MyLabel` and `mylabel`
+/// stay distinct.
+pub fn normalize_separators(label: &str) -> String {
+    let mut normalized = String::with_capacity(label.len());
+    let mut in_separator = false;
+    for c in label.chars() {
+        if matches!(c, '-' | '_' | '.') || c.is_whitespace() {
+            if !in_separator {
+                normalized.push('-');
+            }
+            in_separator = true;
         } else {
-            None
-        };
-        Ok(Args {
-            label,
-            count,
-            len: captures[0].len(),
-        })
+            normalized.push(c);
+            in_separator = false;
+        }
     }
+    normalized
 }
 
-/// A sink that provides byte offset from the beggining of the file and matches as (lossily converted)
-/// strings while ignoring everything else.
+/// `path` without `.` components and with components separated by `/` on every platform, e.g.,
+/// `./src/lib.rs` becomes `src/lib.rs`.
+pub fn normalized_path(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    components.join("/")
+}
+
+/// `path` relative to `base`, ignoring `.` components, e.g., `./src/lib.rs` relative to `.` is
+/// `src/lib.rs`. If one of them is relative and the other absolute, the relative one is resolved
+/// against the current directory first. Falls back to the absolute path if `path` isn't under
+/// `base`.
+pub fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let without_cur_dir =
+        |p: &Path| -> PathBuf { p.components().filter(|c| *c != Component::CurDir).collect() };
+    let (path, base) = (without_cur_dir(path), without_cur_dir(base));
+    if path.is_absolute() == base.is_absolute() {
+        if let Ok(relative) = path.strip_prefix(&base) {
+            return relative.to_path_buf();
+        }
+    }
+    let Ok(cwd) = std::env::current_dir() else {
+        return path;
+    };
+    let (path, base) = (cwd.join(path), cwd.join(base));
+    match path.strip_prefix(&base) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+/// Formats the match as `kind @ path (bytes start..end)`, e.g.,
+/// `invalid count @ src/a.rs (bytes 100..112)`.
+impl fmt::Display for InvalidMatch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{} @ {} (bytes {}..{})",
+            self.error.kind(),
+            self.file.display(),
+            span.start,
+            span.end
+        )
+    }
+}
+
+#[derive(Clone)]
+struct Args {
+    label: LabelArg,
+    count: Option<CountArg>,
+    note: Option<NoteArg>,
+    /// The length of the parsed string including delimiting parentheses and the whitespace
+    /// between the keyword and the opening parenthesis
+    len: usize,
+    /// The length of the whitespace between the keyword and the opening parenthesis
+    gap: usize,
+    /// The label qualified with the keyword if the comment was found with a keyword other than
+    /// the default one, see [`Matches::group_by_label`]
+    qualified_label: Option<String>,
+}
+
+impl Args {
+    pub fn label(&self) -> &str {
+        &self.label.val
+    }
+
+    /// The key of the comment's group in [`Matches::group_by_label`].
+    fn group(&self) -> &str {
+        self.qualified_label.as_deref().unwrap_or(&self.label.val)
+    }
+}
+
+#[derive(Clone)]
+pub struct Arg<T> {
+    /// Processed value, i.e., trimmed and parsed.
+    val: T,
+    /// The original string that was matched.
+    match_: String,
+    /// The span (in bytes) of the match whitin the file.
+    span: Range<usize>,
+}
+
+impl<T> Arg<T> {
+    /// The processed value of the argument, e.g., the label without surrounding whitespace.
+    pub fn value(&self) -> &T {
+        &self.val
+    }
+
+    /// The argument as written in the file, including any surrounding whitespace.
+    pub fn raw(&self) -> &str {
+        &self.match_
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn has_extra_whitespace(&self) -> bool {
+        self.match_.trim() != &self.match_
+    }
+}
+
+impl<T: fmt::Display> Arg<T> {
+    /// The normalized rendering of the argument as written in a comment with canonical spacing,
+    /// e.g., `my-label` for ` my-label ` or `3` for ` 03`.
+    pub fn canonical(&self) -> String {
+        self.val.to_string()
+    }
+}
+
+type LabelArg = Arg<String>;
+type CountArg = Arg<Count>;
+type NoteArg = Arg<String>;
+
+/// An explicit count, either written literally or as a `$NAME` variable defined in the
+/// [configuration].
 ///
-/// This is like [`grep_searcher::sinks::Lossy`] but provides the byte offset instead of the line number.
-struct Sink<F>(pub F)
-where
-    F: FnMut(u64, String);
+/// [configuration]: config::Config::vars
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Count {
+    Literal(u16),
+    Var(String),
+}
 
-impl<F> grep_searcher::Sink for Sink<F>
-where
-    F: FnMut(u64, String),
-{
-    type Error = io::Error;
+impl Count {
+    /// Resolve the count returning `None` if it's a variable not defined in `vars`.
+    pub fn resolve(&self, vars: &HashMap<String, u16>) -> Option<u16> {
+        match self {
+            Count::Literal(n) => Some(*n),
+            Count::Var(name) => vars.get(name).copied(),
+        }
+    }
 
-    fn matched(
-        &mut self,
-        _searcher: &grep_searcher::Searcher,
-        mat: &grep_searcher::SinkMatch<'_>,
-    ) -> Result<bool, Self::Error> {
-        let matched = match str::from_utf8(mat.bytes()) {
-            Ok(s) => s.to_string(),
-            Err(_) => String::from_utf8_lossy(mat.bytes()).into_owned(),
-        };
-        (self.0)(mat.absolute_byte_offset(), matched);
-        Ok(true)
+    fn parse(s: &str) -> Option<Count> {
+        if let Some(name) = s.strip_prefix('$') {
+            let mut chars = name.chars();
+            let starts_ok = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+            if starts_ok && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Some(Count::Var(name.to_string()));
+            }
+            return None;
+        }
+        s.parse().ok().map(Count::Literal)
     }
 }
 
-impl grep_matcher::Matcher for &Matcher {
-    type Captures = grep_matcher::NoCaptures;
+/// Formats the count as written in a comment, e.g., `3` or `$N`.
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Count::Literal(n) => write!(f, "{n}"),
+            Count::Var(name) => write!(f, "${name}"),
+        }
+    }
+}
 
-    type Error = grep_matcher::NoError;
+#[derive(Debug, Copy, Clone)]
+pub enum ArgsError {
+    Malformed,
+    InvalidCount {
+        start: usize,
+        end: usize,
+    },
+    /// The count is an integer but doesn't fit in a `u16`.
+    CountOutOfRange {
+        start: usize,
+        end: usize,
+    },
+    /// There's an opening parenthesis after the pattern but no closing one on the same line.
+    MissingClosingParen {
+        start: usize,
+        end: usize,
+    },
+    /// Like [`ArgsError::MissingClosingParen`] but for the `CODESYNC[my-label]` form.
+    MissingClosingBracket {
+        start: usize,
+        end: usize,
+    },
+    /// The arguments are opened with a parenthesis and closed with a bracket or vice versa, e.g.,
+    /// `CODESYNC(my-label]`.
+    MismatchedBrackets {
+        start: usize,
+        end: usize,
+    },
+    /// The count after the comma is empty, e.g., `CODESYNC(my-label,)`. The span covers the comma
+    /// and the empty count. Only reported with [`CollectOptions::strict_trailing_comma`],
+    /// otherwise the comment is read as having no count.
+    EmptyCount {
+        start: usize,
+        end: usize,
+    },
+    /// The keyword appears again inside the arguments, e.g., `CODESYNC(CODESYNC(my-label))`. The
+    /// span is that of the nested keyword.
+    NestedKeyword {
+        start: usize,
+        end: usize,
+    },
+}
 
-    fn find_at(
-        &self,
-        haystack: &[u8],
-        at: usize,
-    ) -> Result<Option<grep_matcher::Match>, Self::Error> {
-        Ok(find_codesync_pattern(&haystack[at..])
-            .map(|idx| grep_matcher::Match::new(at + idx, at + idx + PATTERN.len())))
+impl ArgsError {
+    /// The span of the offending argument within the file, or `None` if the error isn't specific
+    /// to an argument, in which case the span of the whole match ([`InvalidMatch::span`]) should be
+    /// used.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match *self {
+            ArgsError::Malformed => None,
+            ArgsError::InvalidCount { start, end }
+            | ArgsError::CountOutOfRange { start, end }
+            | ArgsError::MissingClosingParen { start, end }
+            | ArgsError::MissingClosingBracket { start, end }
+            | ArgsError::MismatchedBrackets { start, end }
+            | ArgsError::EmptyCount { start, end }
+            | ArgsError::NestedKeyword { start, end } => Some(start..end),
+        }
     }
 
-    fn new_captures(&self) -> Result<Self::Captures, Self::Error> {
-        Ok(grep_matcher::NoCaptures::new())
+    /// A short name for the kind of error, e.g., `invalid count`.
+    fn kind(&self) -> &'static str {
+        match self {
+            ArgsError::Malformed => "malformed",
+            ArgsError::InvalidCount { .. } => "invalid count",
+            ArgsError::CountOutOfRange { .. } => "count out of range",
+            ArgsError::MissingClosingParen { .. } => "missing closing paren",
+            ArgsError::MissingClosingBracket { .. } => "missing closing bracket",
+            ArgsError::MismatchedBrackets { .. } => "mismatched brackets",
+            ArgsError::EmptyCount { .. } => "empty count",
+            ArgsError::NestedKeyword { .. } => "nested keyword",
+        }
     }
 }
 
-fn find_codesync_pattern(haystack: &[u8]) -> Option<usize> {
-    kmp::search(&haystack, PATTERN, PATTERN_KMP_TABLE)
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::Malformed => write!(f, "malformed codesync comment"),
+            ArgsError::InvalidCount { .. } => {
+                write!(f, "invalid count: second argument must be an integer")
+            }
+            ArgsError::CountOutOfRange { .. } => {
+                write!(f, "invalid count: count must be between 0 and {}", u16::MAX)
+            }
+            ArgsError::MissingClosingParen { .. } => {
+                write!(f, "malformed codesync comment: missing closing `)`")
+            }
+            ArgsError::MissingClosingBracket { .. } => {
+                write!(f, "malformed codesync comment: missing closing `]`")
+            }
+            ArgsError::MismatchedBrackets { .. } => {
+                write!(f, "malformed codesync comment: mismatched brackets")
+            }
+            ArgsError::EmptyCount { .. } => {
+                write!(f, "invalid count: empty count after trailing comma")
+            }
+            ArgsError::NestedKeyword { .. } => {
+                write!(f, "malformed codesync comment: nested keyword")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+/// How files with binary data are handled, see [`CollectOptions::binary_detection`]. These map to
+/// grep's binary detection settings with NUL as the binary byte.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryDetection {
+    /// Skip files containing a NUL byte.
+    Quit,
+    /// Search files containing NUL bytes treating each one as a line terminator.
+    Convert,
+    /// Search all files as text.
+    #[default]
+    None,
+}
+
+impl BinaryDetection {
+    fn to_grep(self) -> grep_searcher::BinaryDetection {
+        match self {
+            BinaryDetection::Quit => grep_searcher::BinaryDetection::quit(b'\0'),
+            BinaryDetection::Convert => grep_searcher::BinaryDetection::convert(b'\0'),
+            BinaryDetection::None => grep_searcher::BinaryDetection::none(),
+        }
+    }
+}
+
+/// Totals accumulated by a [`FileSearcher`] across the files it searched.
+#[derive(Default)]
+struct SearchTotals {
+    /// Time spent parsing matches, see [`CollectProfile::parse`]
+    parse_time: Duration,
+    /// Bytes read by [`FileSearcher::search_file`]
+    bytes_read: u64,
+    /// See [`Matches::binary_files`]
+    binary_files: Vec<(PathBuf, u64)>,
+    /// See [`Matches::truncated_files`]
+    truncated_files: Vec<PathBuf>,
+}
+
+impl SearchTotals {
+    fn add(&mut self, other: SearchTotals) {
+        self.parse_time += other.parse_time;
+        self.bytes_read += other.bytes_read;
+        self.binary_files.extend(other.binary_files);
+        self.truncated_files.extend(other.truncated_files);
+    }
+}
+
+/// `bytes` as UTF-8 with every byte of an invalid sequence replaced by [`INVALID_UTF8_BYTE`].
+/// Unlike [`String::from_utf8_lossy`], which inserts a three-byte U+FFFD per invalid sequence, the
+/// result has the same length as `bytes`, so byte offsets into it are offsets into `bytes`.
+fn decode_lossy(bytes: &[u8]) -> Cow<'_, str> {
+    let mut rest = match str::from_utf8(bytes) {
+        Ok(s) => return Cow::Borrowed(s),
+        Err(_) => bytes,
+    };
+    let mut decoded = String::with_capacity(bytes.len());
+    loop {
+        match str::from_utf8(rest) {
+            Ok(s) => {
+                decoded.push_str(s);
+                break;
+            }
+            Err(err) => {
+                let (valid, after) = rest.split_at(err.valid_up_to());
+                // Everything up to `valid_up_to` is valid UTF-8
+                decoded.push_str(str::from_utf8(valid).unwrap());
+                // An incomplete sequence at the end has no error length
+                let invalid = err.error_len().unwrap_or(after.len());
+                for _ in 0..invalid {
+                    decoded.push(INVALID_UTF8_BYTE);
+                }
+                rest = &after[invalid..];
+            }
+        }
+    }
+    Cow::Owned(decoded)
+}
+
+/// Replacement for each byte of invalid UTF-8 in the contents of searched files, see
+/// [`decode_lossy`]. A single ASCII byte, so offsets are preserved.
+const INVALID_UTF8_BYTE: char = '?';
+
+/// A [`Matcher`] paired with a searcher. Creating a searcher allocates buffers, so a single
+/// `FileSearcher` should be reused across files (one per thread).
+struct FileSearcher {
+    matcher: Matcher,
+    searcher: grep_searcher::Searcher,
+    /// See [`CollectOptions::max_matches_per_file`]
+    max_matches: Option<usize>,
+    totals: SearchTotals,
+}
+
+impl FileSearcher {
+    fn new(matcher: Matcher) -> Self {
+        Self::with_binary_detection(matcher, BinaryDetection::None)
+    }
+
+    fn with_binary_detection(matcher: Matcher, detection: BinaryDetection) -> Self {
+        Self {
+            matcher,
+            searcher: grep_searcher::SearcherBuilder::new()
+                .binary_detection(detection.to_grep())
+                .build(),
+            max_matches: None,
+            totals: SearchTotals::default(),
+        }
+    }
+
+    /// Search a file for matches returning `None` if there are none.
+    fn search_file(&mut self, fs: &dyn FileSystem, path: &Path) -> io::Result<Option<FileMatches>> {
+        let bytes = fs.read(path)?;
+        self.totals.bytes_read += bytes.len() as u64;
+        self.search_bytes(path, &bytes)
+    }
+
+    /// Search an in-memory buffer for matches returning `None` if there are none.
+    fn search_buffer(&mut self, path: &Path, contents: &str) -> io::Result<Option<FileMatches>> {
+        self.search_bytes(path, contents.as_bytes())
+    }
+
+    /// Search the raw bytes of a file, so byte offsets are offsets into the file as stored.
+    /// Matching lines and, if there are matches, the whole file are converted with
+    /// [`decode_lossy`], which keeps offsets unchanged.
+    fn search_bytes(&mut self, path: &Path, bytes: &[u8]) -> io::Result<Option<FileMatches>> {
+        let matcher = &self.matcher;
+        let max_matches = self.max_matches;
+        let parse_time = &mut self.totals.parse_time;
+        let mut matches = vec![];
+        let mut truncated = false;
+        let mut sink = Sink {
+            on_match: |byte_offset, line: String| {
+                let started = Instant::now();
+                let byte_offset = byte_offset as usize;
+                let next = matcher.multiline.then(|| {
+                    let after = &bytes[(byte_offset + line.len()).min(bytes.len())..];
+                    let end = after
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .map_or(after.len(), |i| i + 1);
+                    decode_lossy(&after[..end])
+                });
+                if let Some(m) = matcher.parse_lines(byte_offset, &line, next.as_deref()) {
+                    if max_matches.is_some_and(|max| matches.len() >= max) {
+                        truncated = true;
+                    } else {
+                        matches.push(m);
+                    }
+                }
+                *parse_time += started.elapsed();
+                !truncated
+            },
+            binary_byte_offset: None,
+        };
+        self.searcher.search_slice(matcher, bytes, &mut sink)?;
+        if let Some(offset) = sink.binary_byte_offset {
+            self.totals.binary_files.push((path.to_path_buf(), offset));
+        }
+        if truncated {
+            self.totals.truncated_files.push(path.to_path_buf());
+        }
+        if matches.is_empty() {
+            return Ok(None);
+        }
+        let mut file = FileMatches::new(path, decode_lossy(bytes).into());
+        file.matches = matches;
+        attach_suppressions(&file.contents, &mut file.matches);
+        Ok(Some(file))
+    }
+}
+
+struct Matcher {
+    re: regex::Regex,
+    /// Like `re` for arguments between brackets, e.g., `CODESYNC[my-label, 2]`.
+    bracket_re: regex::Regex,
+    keyword: Box<str>,
+    /// See [`CollectOptions::strict_keyword_boundary`]
+    strict_boundary: bool,
+    /// See [`CollectOptions::bare_labels`]
+    bare_labels: bool,
+    /// See [`CollectOptions::strict_trailing_comma`]
+    strict_trailing_comma: bool,
+    /// See [`CollectOptions::multiline`]
+    multiline: bool,
+    /// Alternate spelling of the keyword, see [`CollectOptions::tag_spelling`]
+    tag: Option<Box<str>>,
+    /// The keyword qualifying labels if it isn't the default one, see [`Matches::group_by_label`]
+    namespace: Option<Box<str>>,
+    /// Regex that must match the text before the keyword on its line, anchored at the end, see
+    /// [`CollectOptions::comment_prefix`]
+    prefix: Option<regex::bytes::Regex>,
+}
+
+impl Matcher {
+    fn new() -> Matcher {
+        Matcher::with_keyword(DEFAULT_KEYWORD, true)
+    }
+
+    fn with_keyword(keyword: &str, strict_boundary: bool) -> Matcher {
+        const OPTS_REGEX: &str = r"^\(([^,\)]*)(?:,([^\)]*))?\)";
+        const BRACKET_OPTS_REGEX: &str = r"^\[([^,\]]*)(?:,([^\]]*))?\]";
+        Matcher {
+            re: regex::Regex::new(OPTS_REGEX).unwrap(),
+            bracket_re: regex::Regex::new(BRACKET_OPTS_REGEX).unwrap(),
+            keyword: keyword.into(),
+            strict_boundary,
+            bare_labels: false,
+            strict_trailing_comma: false,
+            multiline: false,
+            tag: None,
+            namespace: None,
+            prefix: None,
+        }
+    }
+
+    /// Parse a line without looking at the following one, see [`Matcher::parse_lines`].
+    #[cfg(test)]
+    fn parse_line(&self, byte_offset: usize, line: &str) -> Option<Match> {
+        self.parse_lines(byte_offset, line, None)
+    }
+
+    /// Parse the match in a line reported by the searcher. Returns `None` if the keyword can't be
+    /// found in the line, which shouldn't happen, e.g., if converting the line to UTF-8 altered the
+    /// bytes around the keyword, so such a line is skipped instead of aborting the search. With
+    /// [`CollectOptions::multiline`], arguments missing their closing delimiter may continue on
+    /// `next`, the line following `line`.
+    fn parse_lines(&self, byte_offset: usize, line: &str, next: Option<&str>) -> Option<Match> {
+        let mut line = Cow::Borrowed(line);
+        let found = self.find_keyword(line.as_bytes())?;
+        let (idx, keyword_len) = (found.start, found.len());
+        let rest = &line[idx + keyword_len..];
+        let after_gap = rest.trim_start_matches([' ', '\t']);
+        let gap = if after_gap.starts_with(['(', '[']) {
+            rest.len() - after_gap.len()
+        } else {
+            0
+        };
+        let bare = (self.bare_labels && gap == 0 && after_gap.len() < rest.len())
+            .then(|| self.parse_bare_label(byte_offset + idx + keyword_len, rest))
+            .flatten();
+        let mut opts = match bare {
+            Some(args) => Ok(args),
+            None => self
+                .parse_args(byte_offset + idx + keyword_len + gap, &rest[gap..])
+                .map(|args| Args {
+                    len: args.len + gap,
+                    gap,
+                    ..args
+                }),
+        };
+        let continued = match (&opts, next) {
+            (
+                Err(
+                    ArgsError::MissingClosingParen { start, .. }
+                    | ArgsError::MissingClosingBracket { start, .. },
+                ),
+                Some(next),
+            ) if self.multiline => {
+                let next_offset = byte_offset + line.len();
+                self.parse_continued_args(*start, &rest[gap..], next_offset, next)
+                    .map(|args| (args, next))
+            }
+            _ => None,
+        };
+        if let Some((args, next)) = continued {
+            opts = Ok(Args {
+                len: args.len + gap,
+                gap,
+                ..args
+            });
+            line = Cow::Owned(format!("{line}{next}"));
+        }
+        if let (Some(namespace), Ok(args)) = (&self.namespace, &mut opts) {
+            args.qualified_label = Some(format!("{namespace}:{}", args.label()));
+        }
+
+        let mut m = Match {
+            args: opts,
+            byte_offset: byte_offset + idx,
+            column_byte_offset: idx,
+            keyword_len,
+            comment: 0..0,
+            doc_comment: false,
+            suppressions: vec![],
+        };
+        let span = m.span();
+        let (start, end) = comment_extent(&line, idx..span.end - byte_offset);
+        m.comment = byte_offset + start..byte_offset + end;
+        m.doc_comment = is_doc_comment(&line[start..]);
+        Some(m)
+    }
+
+    /// Parse arguments at `byte_offset` missing their closing delimiter in `rest`, the rest of
+    /// their line, as continuing on `next`, the following line at `next_offset`. The line
+    /// terminator and a trailing backslash are removed from `rest`, and the indentation and a line
+    /// comment delimiter followed by whitespace are removed from `next` before joining them, e.g.,
+    /// `CODESYNC(very-long-` and `// label)` have label `very-long-label`. Returns `None` unless
+    /// the joined arguments are valid.
+    fn parse_continued_args(
+        &self,
+        byte_offset: usize,
+        rest: &str,
+        next_offset: usize,
+        next: &str,
+    ) -> Option<Args> {
+        let first = rest.trim_end_matches(['\n', '\r']);
+        let first = first.strip_suffix('\\').unwrap_or(first);
+        let second = next.trim_end_matches(['\n', '\r']);
+        let mut body = second.trim_start();
+        if let Some((open, _)) = COMMENT_DELIMITERS
+            .iter()
+            .find(|(open, close)| close.is_none() && body.starts_with(open))
+        {
+            body = body[open.len()..].trim_start();
+        }
+        let next_offset = next_offset + second.len() - body.len();
+        let joined = format!("{first}{body}");
+        let mut args = self.parse_args(0, &joined).ok()?;
+        // Offsets in `joined` before the end of `first` are on the first line
+        let start = |i: usize| match i.checked_sub(first.len()) {
+            Some(i) => next_offset + i,
+            None => byte_offset + i,
+        };
+        let end = |i: usize| match i.checked_sub(first.len()) {
+            Some(i) if i > 0 => next_offset + i,
+            _ => byte_offset + i,
+        };
+        let span = |span: &Range<usize>| start(span.start)..end(span.end);
+        args.label.span = span(&args.label.span);
+        if let Some(count) = &mut args.count {
+            count.span = span(&count.span);
+        }
+        if let Some(note) = &mut args.note {
+            note.span = span(&note.span);
+        }
+        args.len = end(args.len) - byte_offset;
+        Some(args)
+    }
+
+    fn find_keyword(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        self.find_keyword_at(haystack, 0)
+    }
+
+    /// Find the first occurrence of the keyword, or of its tag spelling if any, starting at or
+    /// after `at` and return its span. Bytes before `at` are only looked at to check the boundary
+    /// before the keyword and the comment prefix.
+    fn find_keyword_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        self.find_keyword_where(haystack, at, self.prefix.as_ref())
+    }
+
+    /// Like [`Matcher::find_keyword_at`] but requiring the given `prefix` instead of the
+    /// configured one.
+    fn find_keyword_where(
+        &self,
+        haystack: &[u8],
+        at: usize,
+        prefix: Option<&regex::bytes::Regex>,
+    ) -> Option<Range<usize>> {
+        let has_prefix = |idx: usize| {
+            let Some(re) = prefix else {
+                return true;
+            };
+            let line_start = haystack[..idx]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |i| i + 1);
+            re.is_match(&haystack[line_start..idx])
+        };
+        let is_ident = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+        let spellings = std::iter::once(&self.keyword).chain(&self.tag);
+        spellings
+            .filter_map(|spelling| {
+                let len = spelling.len();
+                find_keywords(&haystack[at..], spelling.as_bytes())
+                    .map(|idx| at + idx)
+                    .find(|&idx| {
+                        (!self.strict_boundary
+                            || !(idx > 0 && is_ident(&haystack[idx - 1])
+                                || haystack.get(idx + len).is_some_and(is_ident)))
+                            && has_prefix(idx)
+                    })
+                    .map(|idx| idx..idx + len)
+            })
+            .min_by_key(|span| span.start)
+    }
+
+    /// Parse the word following the whitespace at the start of `haystack` as a label, see
+    /// [`CollectOptions::bare_labels`]. Returns `None` if there's no such word.
+    fn parse_bare_label(&self, byte_offset: usize, haystack: &str) -> Option<Args> {
+        let word = haystack.trim_start_matches([' ', '\t']);
+        let start = haystack.len() - word.len();
+        let len = word.find(char::is_whitespace).unwrap_or(word.len());
+        if len == 0 {
+            return None;
+        }
+        let label = &word[..len];
+        Some(Args {
+            label: LabelArg {
+                val: label.to_string(),
+                match_: label.to_string(),
+                span: byte_offset + start..byte_offset + start + len,
+            },
+            count: None,
+            note: None,
+            len: start + len,
+            gap: 0,
+            qualified_label: None,
+        })
+    }
+
+    fn parse_args(&self, byte_offset: usize, haystack: &str) -> Result<Args, ArgsError> {
+        // Arguments are written between parentheses or, alternatively, between brackets
+        let (re, close, other_close) = if haystack.starts_with('[') {
+            (&self.bracket_re, ']', ')')
+        } else {
+            (&self.re, ')', ']')
+        };
+        let Some(captures) = re.captures(haystack) else {
+            let line = haystack.trim_end();
+            if line.starts_with(['(', '[']) && !line.contains(close) {
+                if let Some(i) = line.find(other_close) {
+                    return Err(ArgsError::MismatchedBrackets {
+                        start: byte_offset,
+                        end: byte_offset + i + 1,
+                    });
+                }
+                let (start, end) = (byte_offset, byte_offset + line.len());
+                return Err(if close == ')' {
+                    ArgsError::MissingClosingParen { start, end }
+                } else {
+                    ArgsError::MissingClosingBracket { start, end }
+                });
+            }
+            return Err(ArgsError::Malformed);
+        };
+
+        // A keyword inside the arguments is almost certainly a mistake, e.g., a comment pasted
+        // into another one. Without this check, `CODESYNC(CODESYNC(a))` would have label
+        // `CODESYNC(a`.
+        // The keyword is nested regardless of the comment prefix
+        let args = &haystack.as_bytes()[..captures[0].len()];
+        if let Some(nested) = self.find_keyword_where(args, 1, None) {
+            return Err(ArgsError::NestedKeyword {
+                start: byte_offset + nested.start,
+                end: byte_offset + nested.end,
+            });
+        }
+
+        let m = captures.get(1).unwrap();
+        let label = LabelArg {
+            val: m.as_str().trim().to_string(),
+            match_: m.as_str().to_string(),
+            span: (byte_offset + m.start()..byte_offset + m.end()),
+        };
+
+        // The arguments after the label are the count and the note, in that order, but either may
+        // be missing, e.g., `CODESYNC(foo, "note")`
+        let (count, note) = match captures.get(2) {
+            Some(m) => {
+                let (count, note) = split_note(m.as_str());
+                let arg = |r: Range<usize>| (m.start() + r.start, &m.as_str()[r]);
+                (count.map(arg), note.map(arg))
+            }
+            None => (None, None),
+        };
+        let note = note.map(|(start, raw)| {
+            let quoted = raw.trim();
+            NoteArg {
+                val: quoted[1..quoted.len() - 1].to_string(),
+                match_: raw.to_string(),
+                span: byte_offset + start..byte_offset + start + raw.len(),
+            }
+        });
+        let count = match count {
+            // A trailing comma, e.g., `CODESYNC(foo,)`
+            Some((start, raw)) if raw.trim().is_empty() => {
+                if self.strict_trailing_comma {
+                    return Err(ArgsError::EmptyCount {
+                        start: byte_offset + start - 1,
+                        end: byte_offset + start + raw.len(),
+                    });
+                }
+                None
+            }
+            Some((start, raw)) => {
+                let (start, end) = (byte_offset + start, byte_offset + start + raw.len());
+                let s = raw.trim();
+                let is_integer = !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+                let val = Count::parse(s).ok_or(if is_integer {
+                    ArgsError::CountOutOfRange { start, end }
+                } else {
+                    ArgsError::InvalidCount { start, end }
+                })?;
+                Some(CountArg {
+                    val,
+                    match_: raw.to_string(),
+                    span: (start..end),
+                })
+            }
+            None => None,
+        };
+        Ok(Args {
+            label,
+            count,
+            note,
+            len: captures[0].len(),
+            gap: 0,
+            qualified_label: None,
+        })
+    }
+}
+
+/// Extend `span` in `line` to cover the comment surrounding it. If more than one delimiter appears
+/// before the span, the closest one is chosen. A delimiter preceded by more of its first character,
+/// e.g., `///`, is extended to include them.
+fn comment_extent(line: &str, span: Range<usize>) -> (usize, usize) {
+    let before = &line[..span.start];
+    let delimiter = COMMENT_DELIMITERS
+        .iter()
+        .filter_map(|&(open, close)| Some((before.rfind(open)?, open, close)))
+        // Prefer the delimiter ending closest to the span and then the longest one, so `<!--`
+        // wins over `--`
+        .max_by_key(|(start, open, _)| (start + open.len(), open.len()));
+    let Some((mut start, open, close)) = delimiter else {
+        return (span.start, span.end);
+    };
+    let first = open.as_bytes()[0];
+    while start > 0 && before.as_bytes()[start - 1] == first {
+        start -= 1;
+    }
+    let rest = &line[span.end..];
+    let end = match close {
+        Some(close) => rest.find(close).map_or(0, |i| i + close.len()),
+        None => rest.trim_end_matches(['\n', '\r']).len(),
+    };
+    (start, span.end + end)
+}
+
+/// Split `rest`, the arguments following the comma after the label, into the count and the note
+/// as ranges in `rest`. The note is the last argument if it's written between double quotes, e.g.,
+/// ` 2, "keep in sync"`, and may be the only one, e.g., ` "keep in sync"`, in which case there's
+/// no count.
+fn split_note(rest: &str) -> (Option<Range<usize>>, Option<Range<usize>>) {
+    let quoted = rest.trim_end().strip_suffix('"').and_then(|s| s.rfind('"'));
+    let Some(open) = quoted else {
+        return (Some(0..rest.len()), None);
+    };
+    let before = &rest[..open];
+    if before.trim().is_empty() {
+        return (None, Some(0..rest.len()));
+    }
+    match before.trim_end().strip_suffix(',') {
+        Some(count) => (Some(0..count.len()), Some(count.len() + 1..rest.len())),
+        None => (Some(0..rest.len()), None),
+    }
+}
+
+/// Whether `comment`, starting at its opening delimiter, is a documentation comment. Only Rust's
+/// `///`, `//!`, `/** */`, and `/*! */` are currently recognized.
+fn is_doc_comment(comment: &str) -> bool {
+    let outer_line = comment.starts_with("///") && !comment.starts_with("////");
+    let outer_block =
+        comment.starts_with("/**") && !comment.starts_with("/***") && !comment.starts_with("/**/");
+    outer_line || outer_block || comment.starts_with("//!") || comment.starts_with("/*!")
+}
+
+/// A sink that provides byte offset from the beggining of the file and matches as (lossily converted)
+/// strings while ignoring everything else.
+///
+/// This is like [`grep_searcher::sinks::Lossy`] but provides the byte offset instead of the line number.
+struct Sink<F>
+where
+    F: FnMut(u64, String) -> bool,
+{
+    /// Called with the byte offset and contents of each matching line, returning whether to keep
+    /// searching
+    on_match: F,
+    /// The offset of the first binary byte, if binary data was detected
+    binary_byte_offset: Option<u64>,
+}
+
+impl<F> grep_searcher::Sink for Sink<F>
+where
+    F: FnMut(u64, String) -> bool,
+{
+    type Error = io::Error;
+
+    fn matched(
+        &mut self,
+        _searcher: &grep_searcher::Searcher,
+        mat: &grep_searcher::SinkMatch<'_>,
+    ) -> Result<bool, Self::Error> {
+        let matched = decode_lossy(mat.bytes()).into_owned();
+        Ok((self.on_match)(mat.absolute_byte_offset(), matched))
+    }
+
+    fn binary_data(
+        &mut self,
+        _searcher: &grep_searcher::Searcher,
+        binary_byte_offset: u64,
+    ) -> Result<bool, Self::Error> {
+        self.binary_byte_offset.get_or_insert(binary_byte_offset);
+        Ok(true)
+    }
+}
+
+impl grep_matcher::Matcher for &Matcher {
+    type Captures = grep_matcher::NoCaptures;
+
+    type Error = grep_matcher::NoError;
+
+    fn find_at(
+        &self,
+        haystack: &[u8],
+        at: usize,
+    ) -> Result<Option<grep_matcher::Match>, Self::Error> {
+        Ok(self
+            .find_keyword_at(haystack, at)
+            .map(|span| grep_matcher::Match::new(span.start, span.end)))
+    }
+
+    fn new_captures(&self) -> Result<Self::Captures, Self::Error> {
+        Ok(grep_matcher::NoCaptures::new())
+    }
+}
+
+/// Internal entry points exposed for benchmarks. Not part of the public API.
+#[doc(hidden)]
+pub mod bench {
+    pub fn find_codesync_pattern(haystack: &[u8]) -> Option<usize> {
+        super::find_codesync_pattern(haystack)
+    }
+
+    pub fn find_codesync_pattern_kmp(haystack: &[u8]) -> Option<usize> {
+        super::find_codesync_pattern_kmp(haystack)
+    }
+
+    pub struct Matcher(super::Matcher);
+
+    impl Default for Matcher {
+        fn default() -> Self {
+            Self(super::Matcher::new())
+        }
+    }
+
+    impl Matcher {
+        /// Parse the arguments following the pattern returning whether they're valid.
+        pub fn parse_args(&self, haystack: &str) -> bool {
+            self.0.parse_args(0, haystack).is_ok()
+        }
+    }
+}
+
+/// Find the first occurrence of the pattern. Since the first byte of the pattern is relatively
+/// rare in source code, this uses `memchr` to jump between occurrences of it and only then checks
+/// the rest of the pattern.
+fn find_codesync_pattern(haystack: &[u8]) -> Option<usize> {
+    find_keywords(haystack, &PATTERN).next()
+}
+
+/// Iterator over the offsets of all occurrences of a (non-empty) keyword.
+fn find_keywords<'a>(haystack: &'a [u8], keyword: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    memchr::memchr_iter(keyword[0], haystack)
+        .filter(move |&idx| haystack[idx..].starts_with(keyword))
+}
+
+/// Reference implementation of [`find_codesync_pattern`] using KMP.
+fn find_codesync_pattern_kmp(haystack: &[u8]) -> Option<usize> {
+    kmp::search(haystack, PATTERN, PATTERN_KMP_TABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn parse(lines: &[&str]) -> Vec<Match> {
+        let matcher = Matcher::new();
+        lines
+            .iter()
+            .map(|line| matcher.parse_line(0, line).unwrap())
+            .collect()
+    }
+
+    fn search(contents: &str) -> Vec<Match> {
+        Matches::from_buffer(Path::new("file.rs"), contents)
+            .files
+            .into_iter()
+            .flat_map(|file| file.matches)
+            .collect()
+    }
+
+    #[test]
+    fn collect_from_memory() {
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert("src/b.rs", "// CODESYNC(foo)\n");
+        fs.insert("src/a.rs", "// CODESYNC(foo)\n// CODESYNC(\n");
+        fs.insert("docs/c.md", "<!-- CODESYNC(bar) -->\n");
+
+        let opts = CollectOptions::new().root("src");
+        let matches = Matches::collect_from(&fs, &opts).unwrap();
+        let sources: Vec<_> = matches.sources().map(|(path, _)| path).collect();
+        assert_eq!(sources, [Path::new("src/a.rs"), Path::new("src/b.rs")]);
+        assert_eq!(matches.comments().count(), 2);
+        assert_eq!(matches.invalid_matches().count(), 1);
+    }
+
+    #[test]
+    fn spans_in_non_utf8_files() {
+        let bytes = b"\xe9t\xe9 \xff\xfe // CODESYNC(foo, 2)\n\xe9// CODESYNC(bar\n";
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert("a.rs", &bytes[..]);
+        let matches = Matches::collect_from(&fs, &CollectOptions::new().root("a.rs")).unwrap();
+
+        let comment = matches.comments().next().unwrap();
+        assert_eq!(&bytes[comment.span()], b"CODESYNC(foo, 2)");
+        assert_eq!(&bytes[comment.label_arg().span()], b"foo");
+        let invalid = matches.invalid_matches().next().unwrap();
+        assert_eq!(&bytes[invalid.span()], b"CODESYNC");
+        let (_, contents) = matches.sources().next().unwrap();
+        assert_eq!(contents.len(), bytes.len());
+        assert!(contents.starts_with("?t? ?? // CODESYNC(foo, 2)\n?//"));
+
+        assert_eq!(decode_lossy(b"ok"), Cow::Borrowed("ok"));
+        assert_eq!(decode_lossy(b"a\xf0\x9f\x98"), "a???");
+    }
+
+    #[test]
+    fn collect_from_overlapping_roots() {
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert("a.rs", "// CODESYNC(foo)\n");
+        fs.insert("src/b.rs", "// CODESYNC(foo)\n");
+
+        let sources = |roots: &[&str]| {
+            let opts = CollectOptions::new().roots(roots);
+            let matches = Matches::collect_from(&fs, &opts).unwrap();
+            let sources: Vec<_> = matches
+                .sources()
+                .map(|(path, _)| path.to_path_buf())
+                .collect();
+            sources
+        };
+        assert_eq!(
+            sources(&[".", "./src"]),
+            [Path::new("./a.rs"), Path::new("./src/b.rs")]
+        );
+        assert_eq!(
+            sources(&["src", "."]),
+            [Path::new("./a.rs"), Path::new("src/b.rs")]
+        );
+    }
+
+    #[test]
+    fn canonicalizes_only_when_needed() {
+        /// A [`vfs::MemoryFileSystem`] counting calls to `canonicalize`
+        struct Counting(vfs::MemoryFileSystem, std::sync::atomic::AtomicUsize);
+
+        impl FileSystem for Counting {
+            fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+                self.0.read(path)
+            }
+
+            fn walk(
+                &self,
+                root: &Path,
+                opts: &CollectOptions,
+            ) -> Result<Vec<PathBuf>, ignore::Error> {
+                self.0.walk(root, opts)
+            }
+
+            fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+                self.1.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                self.0.canonicalize(path)
+            }
+        }
+
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert("src/a.rs", "// CODESYNC(foo)\n");
+        fs.insert("docs/b.md", "CODESYNC(foo)\n");
+        let calls = |opts: CollectOptions| {
+            let fs = Counting(fs.clone(), Default::default());
+            let matches = Matches::collect_from(&fs, &opts).unwrap();
+            (matches.comments().count(), fs.1.into_inner())
+        };
+        assert_eq!(calls(CollectOptions::new()), (2, 0));
+        assert_eq!(calls(CollectOptions::new().roots(["src", "docs"])), (2, 2));
+        assert_eq!(calls(CollectOptions::new().roots([".", "src"])), (2, 5));
+        assert_eq!(calls(CollectOptions::new().follow_symlinks(true)), (2, 2));
+    }
+
+    #[test]
+    fn invalid_match_kinds_and_snippets() {
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert("a.rs", "// CODESYNC(foo, x)\n// CODESYNC(bar\n");
+        let matches = Matches::collect_from(&fs, &CollectOptions::new().root("a.rs")).unwrap();
+        let invalid: Vec<_> = matches
+            .invalid_matches()
+            .map(|m| (m.error_kind(), m.snippet()))
+            .collect();
+        assert_eq!(
+            invalid,
+            [
+                ("invalid-count", "CODESYNC(foo, x)"),
+                ("malformed", "CODESYNC(bar")
+            ]
+        );
+    }
+
+    #[test]
+    fn column_byte_offsets() {
+        let contents = "// CODESYNC(a)\n  /* CODESYNC(a) */\n// CODESYNC(b, x)\n\tCODESYNC(\n";
+        let matches = Matches::from_buffer(Path::new("a.rs"), contents);
+        let line_start = |offset: usize| contents[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let columns: Vec<_> = matches
+            .entries()
+            .map(|entry| {
+                let (offset, column) = match entry {
+                    Entry::Valid(c) => (c.byte_offset(), c.column_byte_offset()),
+                    Entry::Invalid(m) => (m.span().start, m.column_byte_offset()),
+                };
+                assert_eq!(column, offset - line_start(offset));
+                column
+            })
+            .collect();
+        assert_eq!(columns, [3, 5, 3, 1]);
+    }
+
+    #[test]
+    fn args_len() {
+        let snippets = [
+            ("CODESYNC", "(a)"),
+            ("CODESYNC", "[a, 2]"),
+            ("CODESYNC", " ( a , 2 )"),
+            ("CODESYNC", "\t(a)"),
+            ("CODESYNC", "(a, 2, \"see b\")"),
+        ];
+        let contents: String = snippets
+            .iter()
+            .map(|(keyword, args)| format!("// {keyword}{args} note\n"))
+            .collect();
+        let matches = Matches::from_buffer(Path::new("a.rs"), &contents);
+        let comments: Vec<_> = matches.comments().collect();
+        assert_eq!(comments.len(), snippets.len());
+        for (comment, (keyword, args)) in comments.iter().zip(snippets) {
+            assert_eq!(comment.args_len(), args.len());
+            let span = comment.span();
+            assert_eq!(
+                span.end,
+                comment.byte_offset() + keyword.len() + comment.args_len()
+            );
+            assert_eq!(&contents[span], format!("{keyword}{args}"));
+        }
+    }
+
+    #[test]
+    fn invalid_matches_order() {
+        let file = |path: &str| {
+            let contents = "// CODESYNC\n// CODESYNC(a)\n// CODESYNC(b\n";
+            (PathBuf::from(path), contents.to_string())
+        };
+        let locations = |matches: &Matches| -> Vec<_> {
+            matches
+                .invalid_matches()
+                .map(|m| (m.file().to_path_buf(), m.span().start))
+                .collect()
+        };
+        let matches = Matches::from_parsed(vec![file("c.rs"), file("a.rs"), file("b/a.rs")]);
+        assert_eq!(matches.invalid_count(), 6);
+        let expected: Vec<_> = ["a.rs", "b/a.rs", "c.rs"]
+            .into_iter()
+            .flat_map(|path| [(PathBuf::from(path), 3), (PathBuf::from(path), 30)])
+            .collect();
+        assert_eq!(locations(&matches), expected);
+
+        let merged = Matches::from_parsed(vec![file("c.rs")])
+            .merge(Matches::from_parsed(vec![file("b/a.rs"), file("a.rs")]));
+        assert_eq!(merged.invalid_count(), 6);
+        assert_eq!(locations(&merged), expected);
+    }
+
+    #[test]
+    fn merge() {
+        let scan = |files: &[(&str, &str)]| {
+            Matches::from_parsed(
+                files
+                    .iter()
+                    .map(|(path, contents)| (path.into(), contents.to_string()))
+                    .collect(),
+            )
+        };
+        let labels = |matches: &Matches| -> Vec<_> {
+            matches
+                .comments()
+                .map(|c| format!("{}@{}", c.label(), c.file().display()))
+                .collect()
+        };
+
+        let merged = scan(&[("b.rs", "// CODESYNC(foo)\n")]).merge(scan(&[
+            ("a.rs", "// CODESYNC(foo)\n"),
+            ("c.rs", "// CODESYNC(bar)\n"),
+        ]));
+        assert_eq!(labels(&merged), ["foo@a.rs", "foo@b.rs", "bar@c.rs"]);
+        assert!(matches!(
+            check_count(&merged.group_by_label()["foo"], &HashMap::new()),
+            CountCheck::Ok
+        ));
+
+        let merged = scan(&[
+            ("a.rs", "// CODESYNC(old)\n"),
+            ("b.rs", "// CODESYNC(foo)\n"),
+        ])
+        .merge(scan(&[("a.rs", "// CODESYNC(new)\n")]));
+        assert_eq!(labels(&merged), ["new@a.rs", "foo@b.rs"]);
+    }
+
+    #[test]
+    fn group_by_label_across_files() {
+        let matches = Matches::from_parsed(vec![
+            (
+                "b.rs".into(),
+                "// CODESYNC(foo)\n// CODESYNC(bar, 2)\n".to_string(),
+            ),
+            (
+                "a.rs".into(),
+                "// CODESYNC(bar)\n\n// CODESYNC(foo, 2)\n".to_string(),
+            ),
+            ("c.rs".into(), "no comments here\n".to_string()),
+        ]);
+        let groups: Vec<_> = matches
+            .group_by_label()
+            .into_iter()
+            .map(|(label, comments)| {
+                let locations: Vec<_> = comments
+                    .iter()
+                    .map(|c| (c.file().to_str().unwrap(), c.byte_offset()))
+                    .collect();
+                (label, locations)
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("bar", vec![("a.rs", 3), ("b.rs", 20)]),
+                ("foo", vec![("a.rs", 21), ("b.rs", 3)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_labels() {
+        for label in ["MyLabel", "my-label", "my_label", "MY LABEL", "my.label"] {
+            assert_eq!(normalize_label(label), "mylabel");
+        }
+        assert_eq!(normalize_label("Étiquette-2"), "étiquette2");
+        assert_ne!(normalize_label("my-label"), normalize_label("my-label-2"));
+
+        let matches = Matches::from_buffer(Path::new("a.rs"), "// CODESYNC(MyLabel)\n");
+        let comment = matches.comments().next().unwrap();
+        assert_eq!(comment.normalized_label(), "mylabel");
+        assert!(comment.eq_label("my_label"));
+        assert!(!comment.eq_label("my_label_2"));
+    }
+
+    #[test]
+    fn normalize_label_separators() {
+        for label in [
+            "my-label",
+            "my_label",
+            "my.label",
+            "my label",
+            "my--label",
+            "my-_label",
+        ] {
+            assert_eq!(normalize_separators(label), "my-label");
+        }
+        assert_eq!(normalize_separators("_my_label_"), "-my-label-");
+        assert_ne!(
+            normalize_separators("MyLabel"),
+            normalize_separators("my-label")
+        );
+        assert_ne!(
+            normalize_separators("mylabel"),
+            normalize_separators("my-label")
+        );
+    }
+
+    #[test]
+    fn fingerprints() {
+        assert_eq!(stable_hash(["a"]), 0xaf63dc4c8601ec8c);
+        assert_eq!(stable_hash(["a", "b"]), stable_hash(["a\0b"]));
+        assert_eq!(normalized_path(Path::new("./src/lib.rs")), "src/lib.rs");
+
+        let fingerprints = |path: &str, contents: &str| {
+            let matches = Matches::from_buffer(Path::new(path), contents);
+            let comments: Vec<_> = matches.comments().map(|c| c.fingerprint()).collect();
+            let invalid: Vec<_> = matches.invalid_matches().map(|m| m.fingerprint()).collect();
+            (comments, invalid)
+        };
+        let (before, invalid_before) = fingerprints(
+            "./a.rs",
+            "// CODESYNC(foo)\n// CODESYNC(bar)\n// CODESYNC\n",
+        );
+        let (after, invalid_after) = fingerprints(
+            "a.rs",
+            "fn f() {}\n// CODESYNC(foo)\n\n// CODESYNC(bar)\n// CODESYNC\n",
+        );
+        assert_eq!(before, after);
+        assert_eq!(invalid_before, invalid_after);
+        assert_ne!(before[0], before[1]);
+        assert_ne!(fingerprints("b.rs", "// CODESYNC(foo)\n").0[0], before[0]);
+    }
+
+    #[test]
+    fn fingerprints_relative_to_root() {
+        // The same project collected from its own directory, from its parent, and by absolute path
+        let fingerprints = |file: &str, root: &str| {
+            let mut fs = vfs::MemoryFileSystem::new();
+            fs.insert(file, "// CODESYNC(foo)\n// CODESYNC\n");
+            let matches = Matches::collect_from(&fs, &CollectOptions::new().root(root)).unwrap();
+            let comment = matches.comments().next().unwrap().fingerprint();
+            let invalid = matches.invalid_matches().next().unwrap().fingerprint();
+            (comment, invalid)
+        };
+        let in_project = fingerprints("src/a.rs", ".");
+        assert_eq!(fingerprints("proj/src/a.rs", "proj"), in_project);
+        assert_eq!(
+            fingerprints("/work/proj/src/a.rs", "/work/proj"),
+            in_project
+        );
+        assert_eq!(
+            fingerprints("proj/src/a.rs", "proj/src"),
+            fingerprints("a.rs", ".")
+        );
+        assert_eq!(
+            fingerprints("src/a.rs", "src/a.rs"),
+            fingerprints("a.rs", ".")
+        );
+    }
+
+    #[test]
+    fn long_line_with_many_markers() {
+        // Would take minutes if finding the keyword or its boundary rescanned the line from each
+        // rejected occurrence.
+        let n = 100_000;
+        let line = format!(
+            "// {}CODESYNC(foo) {}\n",
+            "CODESYNCX ".repeat(n),
+            "CODESYNC(bar) ".repeat(n)
+        );
+        let matches = search(&line);
+        assert_eq!(matches.len(), 1);
+        let label = matches[0].args.as_ref().ok().map(|args| args.label());
+        assert_eq!(label, Some("foo"));
+        assert_eq!(matches[0].byte_offset, 3 + "CODESYNCX ".len() * n);
+    }
+
+    #[test]
+    fn relative_paths() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            relative_to(Path::new("./src/a.rs"), Path::new(".")),
+            Path::new("src/a.rs")
+        );
+        assert_eq!(
+            relative_to(Path::new("src/a.rs"), Path::new("./src")),
+            Path::new("a.rs")
+        );
+        assert_eq!(
+            relative_to(&cwd.join("src/a.rs"), Path::new(".")),
+            Path::new("src/a.rs")
+        );
+        assert_eq!(
+            relative_to(Path::new("src/a.rs"), &cwd),
+            Path::new("src/a.rs")
+        );
+        assert_eq!(
+            relative_to(Path::new("src/a.rs"), Path::new("tests")),
+            cwd.join("src/a.rs")
+        );
+        assert_eq!(
+            relative_to(Path::new("/x/a.rs"), Path::new("/y")),
+            Path::new("/x/a.rs")
+        );
+
+        let matches =
+            Matches::from_buffer(&cwd.join("src/a.rs"), "// CODESYNC(foo)\n// CODESYNC\n");
+        let comment = matches.comments().next().unwrap();
+        assert_eq!(comment.relative_path(&cwd.join("src")), Path::new("a.rs"));
+        let m = matches.invalid_matches().next().unwrap();
+        assert_eq!(m.relative_path(&cwd), Path::new("src/a.rs"));
+
+        let relative = Matches::from_buffer(Path::new("./src/a.rs"), "// CODESYNC(foo)\n");
+        assert_eq!(
+            relative.comments().next().unwrap().fingerprint(),
+            comment.fingerprint()
+        );
+    }
+
+    #[test]
+    fn spacing_edits() {
+        let edits = |line: &str| {
+            let matches = Matches::from_buffer(Path::new("file.rs"), line);
+            let comment = matches.comments().next().unwrap();
+            comment.spacing_edits()
+        };
+        assert_eq!(edits("CODESYNC(foo, 2)"), vec![]);
+        assert_eq!(edits("CODESYNC[foo]"), vec![]);
+        assert_eq!(edits("CODESYNC(foo,2)"), vec![(13..13, " ")]);
+        assert_eq!(edits("CODESYNC(foo,  2)"), vec![(13..15, " ")]);
+        assert_eq!(edits("CODESYNC(foo,\t2)"), vec![(13..14, " ")]);
+        assert_eq!(edits("CODESYNC( foo, 2 )"), vec![(9..10, ""), (16..17, "")]);
+        assert_eq!(edits("CODESYNC (foo , 2)"), vec![(8..9, ""), (13..14, "")]);
+        assert_eq!(edits("CODESYNC(  )"), vec![(9..11, "")]);
+        assert_eq!(edits("CODESYNC(foo, \"a\")"), vec![]);
+        assert_eq!(
+            edits("CODESYNC(foo, 2,\"a\" )"),
+            vec![(16..16, " "), (19..20, "")]
+        );
+    }
+
+    #[test]
+    fn entries_in_source_order() {
+        let matches = Matches::from_parsed(vec![
+            ("b.rs".into(), "// CODESYNC(foo)\n".to_string()),
+            (
+                "a.rs".into(),
+                "// CODESYNC(bar)\n// CODESYNC\n// CODESYNC(baz)\n".to_string(),
+            ),
+        ]);
+        let entries: Vec<_> = matches
+            .entries()
+            .map(|entry| {
+                let file = entry.file().to_str().unwrap();
+                match entry {
+                    Entry::Valid(comment) => (file, comment.label().to_string()),
+                    Entry::Invalid(m) => (file, m.error.kind().to_string()),
+                }
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("a.rs", "bar".to_string()),
+                ("a.rs", "malformed".to_string()),
+                ("a.rs", "baz".to_string()),
+                ("b.rs", "foo".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn retain_labels() {
+        let mut matches = Matches::from_buffer(
+            Path::new("file.rs"),
+            "// CODESYNC(foo)\n// CODESYNC(gen/bar)\n// CODESYNC(baz, x)\n",
+        );
+        matches.retain_labels(|label| !label.starts_with("gen/"));
+        let labels: Vec<_> = matches.comments().map(|c| c.label().to_string()).collect();
+        assert_eq!(labels, ["foo"]);
+        assert_eq!(matches.invalid_matches().count(), 1);
+    }
+
+    fn check(lines: &[&str]) -> CountCheck {
+        let matches = parse(lines);
+        let comments: Vec<_> = matches
+            .iter()
+            .filter_map(|m| m.to_comment(Path::new("file.rs")))
+            .collect();
+        let vars = HashMap::from([("N".to_string(), 3)]);
+        check_count(&comments, &vars)
+    }
+
+    #[test]
+    fn memchr_search_agrees_with_kmp() {
+        let haystacks: [&[u8]; 7] = [
+            b"CODESYNC",
+            b"// CODESYNC(a)",
+            b"CCODESYNC",
+            b"CODECODESYNC",
+            b"CODESYN",
+            b"no pattern here",
+            b"",
+        ];
+        for haystack in haystacks {
+            assert_eq!(
+                find_codesync_pattern(haystack),
+                find_codesync_pattern_kmp(haystack),
+                "{}",
+                String::from_utf8_lossy(haystack)
+            );
+        }
+        assert_eq!(find_codesync_pattern(b"CCODESYNC"), Some(1));
+        assert_eq!(
+            find_keywords(b"CODESYNC CODESYNC", &PATTERN).collect::<Vec<_>>(),
+            vec![0, 9]
+        );
+    }
+
+    #[test]
+    fn nested_keyword() {
+        let matches = parse(&[
+            "// CODESYNC(CODESYNC(a))\n",
+            "// CODESYNC(foo CODESYNC(a))\n",
+            "// CODESYNC(a, CODESYNC)\n",
+            "// CODESYNC(MY_CODESYNC_LABEL)\n",
+        ]);
+        assert!(matches!(
+            matches[0].args,
+            Err(ArgsError::NestedKeyword { start: 12, end: 20 })
+        ));
+        assert!(matches!(
+            matches[1].args,
+            Err(ArgsError::NestedKeyword { start: 16, end: 24 })
+        ));
+        assert!(matches!(
+            matches[2].args,
+            Err(ArgsError::NestedKeyword { start: 15, end: 23 })
+        ));
+        assert_eq!(
+            matches[3].args.as_ref().unwrap().label(),
+            "MY_CODESYNC_LABEL"
+        );
+    }
+
+    #[test]
+    fn missing_closing_paren() {
+        let matches = parse(&["// CODESYNC(foo, 3\n", "// CODESYNC(foo, 3 // )\n"]);
+        assert!(matches!(
+            matches[0].args,
+            Err(ArgsError::MissingClosingParen { start: 11, end: 18 })
+        ));
+        assert!(matches!(
+            matches[1].args,
+            Err(ArgsError::InvalidCount { .. })
+        ));
+    }
+
+    #[test]
+    fn count_limit() {
+        let matches = parse(&[
+            "// CODESYNC(foo, 65535)\n",
+            "// CODESYNC(foo, 65536)\n",
+            "// CODESYNC(foo, 70000)\n",
+            "// CODESYNC(foo, -1)\n",
+        ]);
+        assert!(matches!(
+            matches[0]
+                .args
+                .as_ref()
+                .unwrap()
+                .count
+                .as_ref()
+                .unwrap()
+                .val,
+            Count::Literal(65535)
+        ));
+        assert!(matches!(
+            matches[1].args,
+            Err(ArgsError::CountOutOfRange { start: 16, end: 22 })
+        ));
+        let Err(err) = &matches[2].args else {
+            panic!("expected an error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "invalid count: count must be between 0 and 65535"
+        );
+        assert!(matches!(
+            matches[3].args,
+            Err(ArgsError::InvalidCount { .. })
+        ));
+    }
+
+    #[test]
+    fn multiline() {
+        let contents = "\
+// CODESYNC(very-long-
+// label, 3, \"note\")
+x = 1 # CODESYNC[other,
+      #   2]
+s = \"CODESYNC(split-\\
+label)\"
+// CODESYNC(unclosed
+// more
+";
+        let path = Path::new("a.rs");
+        let mut matcher = Matcher::new();
+        matcher.multiline = true;
+        let file = FileSearcher::new(matcher)
+            .search_buffer(path, contents)
+            .unwrap()
+            .unwrap();
+        let comments: Vec<_> = file
+            .matches
+            .iter()
+            .filter_map(|m| m.to_comment(path))
+            .collect();
+        let labels: Vec<_> = comments.iter().map(|c| c.label()).collect();
+        assert_eq!(labels, ["very-long-label", "other", "split-label"]);
+        let counts: Vec<_> = comments
+            .iter()
+            .map(|c| c.count_arg().map(|c| c.value().clone()))
+            .collect();
+        assert_eq!(
+            counts,
+            [Some(Count::Literal(3)), Some(Count::Literal(2)), None]
+        );
+
+        let comment = &comments[0];
+        assert_eq!(
+            &contents[comment.span()],
+            "CODESYNC(very-long-\n// label, 3, \"note\")"
+        );
+        assert_eq!(
+            &contents[comment.label_arg().span()],
+            "very-long-\n// label"
+        );
+        assert_eq!(&contents[comment.count_arg().unwrap().span()], " 3");
+        assert_eq!(&contents[comment.note_span().unwrap()], "note");
+        assert_eq!(
+            &contents[comments[1].full_span()],
+            "# CODESYNC[other,\n      #   2]"
+        );
+
+        assert!(matches!(
+            file.matches[3].args,
+            Err(ArgsError::MissingClosingParen { .. })
+        ));
+        // Without the option, the first line is missing its closing paren
+        assert!(matches!(
+            search(contents)[0].args,
+            Err(ArgsError::MissingClosingParen { .. })
+        ));
+    }
+
+    #[test]
+    fn trailing_comma() {
+        let lines = [
+            "// CODESYNC(foo,)\n",
+            "// CODESYNC(foo, )\n",
+            "// CODESYNC[foo,\t]\n",
+        ];
+        for (line, m) in lines.iter().zip(parse(&lines)) {
+            let comment = m.to_comment(Path::new("a.rs")).unwrap();
+            assert_eq!(comment.label(), "foo");
+            assert!(comment.count_arg().is_none());
+            assert_eq!(&line[comment.span()], line[3..].trim_end());
+        }
+
+        let mut matcher = Matcher::new();
+        matcher.strict_trailing_comma = true;
+        let strict: Vec<_> = lines
+            .iter()
+            .map(|line| matcher.parse_line(0, line).unwrap())
+            .collect();
+        assert!(matches!(
+            strict[0].args,
+            Err(ArgsError::EmptyCount { start: 15, end: 16 })
+        ));
+        assert!(matches!(
+            strict[1].args,
+            Err(ArgsError::EmptyCount { start: 15, end: 17 })
+        ));
+        let Err(err) = &strict[1].args else {
+            panic!("expected an error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "invalid count: empty count after trailing comma"
+        );
+        // A count that isn't empty is still parsed
+        assert!(matcher
+            .parse_line(0, "// CODESYNC(foo, 2)\n")
+            .unwrap()
+            .args
+            .is_ok());
+    }
+
+    #[test]
+    fn count_is_default() {
+        let matches = parse(&["// CODESYNC(a)", "// CODESYNC(a, 2)", "// CODESYNC(a, $N)"]);
+        let defaults: Vec<_> = matches
+            .iter()
+            .map(|m| {
+                m.to_comment(Path::new("file.rs"))
+                    .unwrap()
+                    .count_is_default()
+            })
+            .collect();
+        assert_eq!(defaults, [true, false, false]);
+    }
+
+    #[test]
+    fn defaulted_counts() {
+        assert_eq!(check(&["// CODESYNC(a)", "// CODESYNC(a)"]), CountCheck::Ok);
+        assert_eq!(
+            check(&["// CODESYNC(a)"]),
+            CountCheck::Mismatch {
+                expected: 2,
+                found: 1
+            }
+        );
+    }
+
+    #[test]
+    fn single_explicit_count_is_authoritative() {
+        assert_eq!(
+            check(&["// CODESYNC(a, 3)", "// CODESYNC(a)", "// CODESYNC(a)"]),
+            CountCheck::Ok
+        );
+        assert_eq!(
+            check(&["// CODESYNC(a)", "// CODESYNC(a, 3)"]),
+            CountCheck::Mismatch {
+                expected: 3,
+                found: 2
+            }
+        );
+    }
+
+    #[test]
+    fn repeated_explicit_count() {
+        assert_eq!(
+            check(&["// CODESYNC(a, 3)", "// CODESYNC(a, 3)", "// CODESYNC(a)"]),
+            CountCheck::Ok
+        );
+    }
+
+    #[test]
+    fn variable_counts() {
+        assert_eq!(
+            check(&["// CODESYNC(a, $N)", "// CODESYNC(a)", "// CODESYNC(a, 3)"]),
+            CountCheck::Ok
+        );
+        assert_eq!(
+            check(&["// CODESYNC(a, $N)", "// CODESYNC(a)"]),
+            CountCheck::Mismatch {
+                expected: 3,
+                found: 2
+            }
+        );
+        assert!(matches!(
+            parse(&["// CODESYNC(a, $1N)"])[0].args,
+            Err(ArgsError::InvalidCount { .. })
+        ));
+    }
+
+    #[test]
+    fn strict_keyword_boundary() {
+        assert!(search("let CODESYNCHRONIZED = 1;\nCODESYNC_X\n").is_empty());
+        assert!(matches!(
+            &search("// CODESYNC(\n")[..],
+            [Match {
+                args: Err(ArgsError::MissingClosingParen { .. }),
+                ..
+            }]
+        ));
+        // Whitespace is a boundary, and the arguments may follow it
+        assert!(matches!(
+            &search("// CODESYNC (x)\n")[..],
+            [Match {
+                byte_offset: 3,
+                args: Ok(_),
+                ..
+            }]
+        ));
+        let m = &search("CODESYNCHRONIZED CODESYNC(a)\n")[..];
+        assert!(matches!(
+            m,
+            [Match {
+                byte_offset: 17,
+                args: Ok(_),
+                ..
+            }]
+        ));
+
+        assert!(search("XCODESYNC(a)\n_CODESYNC(a)\n").is_empty());
+        for (contents, byte_offset) in [("/*CODESYNC(a)*/\n", 2), (" CODESYNC(a)\n", 1)] {
+            let m = &search(contents)[..];
+            assert!(
+                matches!(m, [Match { byte_offset: b, args: Ok(_), .. }] if *b == byte_offset),
+                "{contents:?}"
+            );
+        }
+
+        let lenient = Matcher::with_keyword(DEFAULT_KEYWORD, false);
+        assert!(lenient
+            .parse_line(0, "CODESYNCHRONIZED")
+            .unwrap()
+            .args
+            .is_err());
+        assert_eq!(lenient.find_keyword(b"CODESYNCHRONIZED"), Some(0..8));
+        assert_eq!(lenient.find_keyword(b"XCODESYNC(a)"), Some(1..9));
+        // A line the keyword can't be found in is skipped instead of panicking
+        assert!(Matcher::new().parse_line(0, "XCODESYNC(a)\n").is_none());
+    }
+
+    #[test]
+    fn canonical_args() {
+        for (line, label, count) in [
+            ("// CODESYNC(foo, 2)", "foo", Some("2")),
+            ("// CODESYNC(  foo bar\t,   002  )", "foo bar", Some("2")),
+            ("// CODESYNC[ foo ,$N ]", "foo", Some("$N")),
+            ("// CODESYNC( foo )", "foo", None),
+        ] {
+            let matches = parse(&[line]);
+            let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+            assert_eq!(comment.label_arg().canonical(), label);
+            assert_eq!(
+                comment.count_arg().map(Arg::canonical).as_deref(),
+                count,
+                "{line:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn suppressions() {
+        let contents = "\
+// codesync:allow(missing-note)
+// CODESYNC(a) codesync:allow( extra-whitespace, all )
+// CODESYNC(b)
+// codesync:allow(label-length
+// CODESYNC(c)
+";
+        let matches = Matches::from_buffer(Path::new("file.rs"), contents);
+        let suppressions: Vec<Vec<_>> = matches
+            .comments()
+            .map(|c| {
+                c.suppressions()
+                    .iter()
+                    .map(|s| (&contents[s.span()], s.lints().to_vec()))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            suppressions,
+            [
+                vec![
+                    ("codesync:allow(missing-note)", vec!["missing-note".into()]),
+                    (
+                        "codesync:allow( extra-whitespace, all )",
+                        vec!["extra-whitespace".into(), "all".into()]
+                    ),
+                ],
+                // The previous line has a match, so its directive applies to that match only
+                vec![],
+                vec![],
+            ]
+        );
+        let a = matches.comments().next().unwrap();
+        assert!(a.suppressions()[0].allows("missing-note"));
+        assert!(!a.suppressions()[0].allows("label-length"));
+        assert!(a.suppressions()[1].allows("label-length"));
+    }
+
+    #[test]
+    fn tag_spelling() {
+        let mut matcher = Matcher::new();
+        matcher.tag = Some("@codesync".into());
+        let lines = [
+            "/** @codesync(a) */\n",
+            "// CODESYNC(a) and @codesync(b)\n",
+            "// user@codesync(a)\n",
+            "// @codesync_a(b)\n",
+        ];
+        let matches: Vec<_> = lines[..2]
+            .iter()
+            .map(|line| matcher.parse_line(0, line).unwrap())
+            .collect();
+        let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+        assert_eq!(comment.label(), "a");
+        assert_eq!(&lines[0][comment.span()], "@codesync(a)");
+        // The first spelling in the line wins
+        let comment = matches[1].to_comment(Path::new("file.rs")).unwrap();
+        assert_eq!(comment.label(), "a");
+        assert_eq!(matcher.find_keyword(lines[2].as_bytes()), None);
+        assert_eq!(matcher.find_keyword(lines[3].as_bytes()), None);
+
+        // Both spellings in the same file share labels
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert(
+            "a.rs",
+            "// CODESYNC(a)\n/// @codesync(a)\n// @codesync(b, 1)\n",
+        );
+        let opts = CollectOptions::new().root("a.rs").tag_spelling(true);
+        let matches = Matches::collect_from(&fs, &opts).unwrap();
+        let groups = matches.group_by_label();
+        assert_eq!(groups["a"].len(), 2);
+        assert_eq!(groups["b"].len(), 1);
+        let opts = CollectOptions::new().root("a.rs");
+        assert_eq!(
+            Matches::collect_from(&fs, &opts)
+                .unwrap()
+                .comments()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn comment_prefix() {
+        let mut fs = vfs::MemoryFileSystem::new();
+        fs.insert(
+            "a.rs",
+            "// CODESYNC(a)\n    //CODESYNC(b)\nlet s = \"CODESYNC(c)\"; // CODESYNC(d)\n\
+             /* CODESYNC(e) */\n// see CODESYNC(f)\n",
+        );
+        let labels = |prefix: Option<&str>| -> Vec<_> {
+            let opts = CollectOptions::new().root("a.rs").comment_prefix(prefix);
+            let matches = Matches::collect_from(&fs, &opts).unwrap();
+            let labels = matches.comments().map(|c| c.label().to_string()).collect();
+            labels
+        };
+        assert_eq!(labels(None), ["a", "b", "c", "e", "f"]);
+        assert_eq!(labels(Some(r"^\s*//\s*")), ["a", "b"]);
+        // Without `^` the prefix can start anywhere but must end at the keyword, and a later
+        // occurrence on the line is found if the first one doesn't have the prefix
+        assert_eq!(labels(Some(r"// ")), ["a", "d"]);
+        assert_eq!(labels(Some(r"^(//|/\*) ")), ["a", "e"]);
+
+        let opts = CollectOptions::new().root("a.rs").comment_prefix(Some("("));
+        assert!(Matches::collect_from(&fs, &opts).is_err());
+    }
+
+    #[test]
+    fn bare_labels() {
+        let mut matcher = Matcher::new();
+        matcher.bare_labels = true;
+        let lines = [
+            "// CODESYNC my-label keep in sync\n",
+            "/* CODESYNC\tfoo */\n",
+            "// CODESYNC (foo, 3)\n",
+            "// CODESYNC\n",
+            "// CODESYNC:foo\n",
+        ];
+        let matches: Vec<_> = lines
+            .map(|line| matcher.parse_line(0, line).unwrap())
+            .into();
+        let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+        assert_eq!(comment.label(), "my-label");
+        assert_eq!(comment.label_arg().span(), 12..20);
+        assert_eq!(comment.span(), 3..20);
+        assert_eq!(comment.keyword_gap(), None);
+        assert!(comment.spacing_edits().is_empty());
+        let comment = matches[1].to_comment(Path::new("file.rs")).unwrap();
+        assert_eq!(&lines[1][comment.label_arg().span()], "foo");
+        let comment = matches[2].to_comment(Path::new("file.rs")).unwrap();
+        assert_eq!(comment.count_arg().unwrap().raw(), " 3");
+        assert!(matches!(matches[3].args, Err(ArgsError::Malformed)));
+        assert!(matches!(matches[4].args, Err(ArgsError::Malformed)));
+
+        assert!(parse(&[lines[0]])[0].args.is_err());
+    }
+
+    #[test]
+    fn whitespace_before_paren() {
+        for (line, gap) in [
+            ("// CODESYNC(a, 3)", None),
+            ("// CODESYNC (a, 3)", Some(11..12)),
+            ("// CODESYNC \t  (a, 3)", Some(11..15)),
+        ] {
+            let matches = parse(&[line]);
+            let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+            assert_eq!(comment.keyword_gap(), gap);
+            assert_eq!(&line[comment.span()], &line[3..]);
+            assert_eq!(&line[comment.label_arg().span()], "a");
+            assert_eq!(&line[comment.count_arg().unwrap().span()], " 3");
+        }
+        assert!(parse(&["// CODESYNC a"])[0].args.is_err());
+    }
+
+    #[test]
+    fn bracket_arguments() {
+        for line in [
+            "// CODESYNC[a, 3]",
+            "// CODESYNC (a, 3)",
+            "// CODESYNC [ a,3]",
+        ] {
+            let matches = parse(&[line]);
+            let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+            assert_eq!(comment.label(), "a");
+            assert_eq!(&line[comment.span()], &line[3..]);
+            assert_eq!(comment.count_arg().unwrap().value(), &Count::Literal(3));
+        }
+        assert!(matches!(
+            parse(&["// CODESYNC(a, 3]"])[0].args,
+            Err(ArgsError::MismatchedBrackets { start: 11, end: 17 })
+        ));
+        assert!(matches!(
+            parse(&["// CODESYNC[a)"])[0].args,
+            Err(ArgsError::MismatchedBrackets { start: 11, end: 14 })
+        ));
+        assert!(matches!(
+            parse(&["// CODESYNC[a, 3"])[0].args,
+            Err(ArgsError::MissingClosingBracket { start: 11, end: 16 })
+        ));
+    }
+
+    #[test]
+    fn notes() {
+        for (line, count, note) in [
+            (
+                r#"CODESYNC(a, 2, "keep in sync")"#,
+                Some(Count::Literal(2)),
+                Some("keep in sync"),
+            ),
+            (
+                r#"CODESYNC(a, "keep, in sync")"#,
+                None,
+                Some("keep, in sync"),
+            ),
+            (
+                r#"CODESYNC(a , $N ,"" )"#,
+                Some(Count::Var("N".into())),
+                Some(""),
+            ),
+            (
+                r#"CODESYNC(a, 2) "not a note""#,
+                Some(Count::Literal(2)),
+                None,
+            ),
+            ("CODESYNC(a, 2)", Some(Count::Literal(2)), None),
+        ] {
+            let m = &parse(&[line])[0];
+            let comment = m.to_comment(Path::new("file.rs")).unwrap();
+            assert_eq!(
+                comment.count_arg().map(|c| c.value().clone()),
+                count,
+                "{line}"
+            );
+            let span = comment.note_span();
+            assert_eq!(span.map(|span| &line[span]), note, "{line}");
+            assert_eq!(comment.note_arg().map(|n| n.value().as_str()), note);
+        }
+        assert!(matches!(
+            parse(&[r#"CODESYNC(a, 2 "note")"#])[0].args,
+            Err(ArgsError::InvalidCount { start: 11, end: 20 })
+        ));
+    }
+
+    #[test]
+    fn doc_comments() {
+        let is_doc = |line: &str| {
+            parse(&[line])[0]
+                .to_comment(Path::new("file.rs"))
+                .unwrap()
+                .is_doc_comment()
+        };
+        assert!(is_doc("/// CODESYNC(a)"));
+        assert!(is_doc("//! CODESYNC(a)"));
+        assert!(is_doc("/** CODESYNC(a) */"));
+        assert!(!is_doc("// CODESYNC(a)"));
+        assert!(!is_doc("//// CODESYNC(a)"));
+        assert!(!is_doc("/* CODESYNC(a) */"));
+        assert!(!is_doc("# CODESYNC(a)"));
+    }
+
+    #[test]
+    fn comment_full_span() {
+        fn full_span(line: &str) -> &str {
+            let matches = parse(&[line]);
+            let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+            &line[comment.full_span()]
+        }
+        assert_eq!(full_span("x; // CODESYNC(a) text\n"), "// CODESYNC(a) text");
+        assert_eq!(
+            full_span("x /* CODESYNC(a, 2) */ y"),
+            "/* CODESYNC(a, 2) */"
+        );
+        assert_eq!(full_span("<!-- CODESYNC(a) -->"), "<!-- CODESYNC(a) -->");
+        assert_eq!(full_span("  /// CODESYNC(a)"), "/// CODESYNC(a)");
+        assert_eq!(full_span("\"CODESYNC(a)\""), "CODESYNC(a)");
+    }
+
+    #[test]
+    fn arg_byte_ranges() {
+        let lines = ["/* CODESYNC( foo , 2) */", "# CODESYNC[foo]\n"];
+        let matches = parse(&lines);
+        let comments: Vec<_> = matches
+            .iter()
+            .map(|m| m.to_comment(Path::new("file.rs")).unwrap())
+            .collect();
+        assert_eq!(&lines[0][comments[0].label_byte_range()], " foo ");
+        assert_eq!(&lines[0][comments[0].count_byte_range().unwrap()], " 2");
+        assert_eq!(&lines[1][comments[1].label_byte_range()], "foo");
+        assert_eq!(comments[1].count_byte_range(), None);
+    }
+
+    #[test]
+    fn count_spans() {
+        let lines = [
+            "// CODESYNC(foo, $N )",
+            "// CODESYNC(foo,3)",
+            "// CODESYNC(foo)",
+        ];
+        let matches = parse(&lines);
+        let spans: Vec<_> = matches
+            .iter()
+            .zip(lines)
+            .map(|(m, line)| {
+                let comment = m.to_comment(Path::new("file.rs")).unwrap();
+                comment.count_span().map(|span| &line[span])
+            })
+            .collect();
+        assert_eq!(spans, [Some("$N"), Some("3"), None]);
+    }
+
+    #[test]
+    fn language_from_extension() {
+        let matches = parse(&["// CODESYNC(a)"]);
+        let language = |file: &str| matches[0].to_comment(Path::new(file)).unwrap().language();
+        assert_eq!(language("src/lib.rs"), Some("Rust"));
+        assert_eq!(language("App.TSX"), Some("TypeScript"));
+        assert_eq!(language("Makefile"), None);
+        assert_eq!(language("notes.unknown"), None);
+    }
+
+    #[test]
+    fn display() {
+        let matches = Matches::from_buffer(
+            Path::new("src/a.rs"),
+            "// CODESYNC(foo)\n// CODESYNC(bar, x)\n",
+        );
+        let comments: Vec<_> = matches.comments().map(|c| c.to_string()).collect();
+        assert_eq!(comments, ["foo @ src/a.rs (bytes 3..16)"]);
+        let invalid: Vec<_> = matches.invalid_matches().map(|m| m.to_string()).collect();
+        assert_eq!(invalid, ["invalid count @ src/a.rs (bytes 20..28)"]);
+    }
+
+    #[test]
+    fn args_error_spans() {
+        let matches = Matches::from_buffer(
+            Path::new("file.rs"),
+            "// CODESYNC(a, x)\n// CODESYNC(b\n// CODESYNC\n",
+        );
+        let errors: Vec<_> = matches
+            .invalid_matches()
+            .map(|m| (m.error.span(), m.error.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    Some(14..16),
+                    "invalid count: second argument must be an integer".to_string()
+                ),
+                (
+                    Some(29..31),
+                    "malformed codesync comment: missing closing `)`".to_string()
+                ),
+                (None, "malformed codesync comment".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_labels() {
+        let matches = parse(&["CODESYNC()", "CODESYNC( )", "CODESYNC(,2)"]);
+        let comments: Vec<_> = matches
+            .iter()
+            .map(|m| m.to_comment(Path::new("file.rs")).unwrap())
+            .collect();
+        assert!(comments.iter().all(|c| c.label().is_empty()));
+        assert_eq!(comments[0].label_arg().span(), 9..9);
+        assert_eq!(comments[1].label_arg().span(), 9..10);
+        assert_eq!(comments[2].label_arg().span(), 9..9);
+        assert_eq!(comments[2].count(), 2);
+        assert_eq!(comments[2].count_with_vars(&HashMap::new()), Some(2));
+    }
+
+    #[test]
+    fn arg_accessors() {
+        let matches = parse(&["CODESYNC( my-label ,$N)"]);
+        let comment = matches[0].to_comment(Path::new("file.rs")).unwrap();
+        let label = comment.label_arg();
+        assert_eq!(label.value(), "my-label");
+        assert_eq!(label.raw(), " my-label ");
+        let count = comment.count_arg().unwrap();
+        assert_eq!(count.value(), &Count::Var("N".to_string()));
+        assert_eq!(count.raw(), "$N");
+        assert_eq!(comment.count(), DEFAULT_COUNT);
+        let vars = HashMap::from([("N".to_string(), 5)]);
+        assert_eq!(comment.count_with_vars(&vars), Some(5));
+        assert_eq!(comment.count_with_vars(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn label_prefixes() {
+        assert!(has_label_prefix("payments/refunds/state", "payments", "/"));
+        assert!(has_label_prefix(
+            "payments/refunds/state",
+            "payments/refunds/",
+            "/"
+        ));
+        assert!(has_label_prefix("payments", "payments", "/"));
+        assert!(has_label_prefix("payments", "", "/"));
+        assert!(!has_label_prefix("paymentsv2/state", "payments", "/"));
+        assert!(!has_label_prefix("payments/refunds", "payments/ref", "/"));
+        assert!(has_label_prefix("payments::refunds", "payments", "::"));
+    }
+
+    #[test]
+    fn count_semantics_others() {
+        let matches = parse(&["// CODESYNC(a, 2)", "// CODESYNC(a)", "// CODESYNC(a)"]);
+        let comments: Vec<_> = matches
+            .iter()
+            .filter_map(|m| m.to_comment(Path::new("file.rs")))
+            .collect();
+        let vars = HashMap::new();
+        let others = CountOptions {
+            semantics: CountSemantics::Others,
+            ..CountOptions::default()
+        };
+        assert_eq!(check_count_with(&comments, &vars, others), CountCheck::Ok);
+        assert_eq!(
+            check_count_with(&comments[..2], &vars, others),
+            CountCheck::Mismatch {
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            check_count_with(&comments[1..], &vars, others),
+            CountCheck::Ok
+        );
+    }
+
+    #[test]
+    fn count_default() {
+        let matches = Matches::from_parsed(vec![(
+            "a.rs".into(),
+            "// CODESYNC(a)\n// CODESYNC(a)\n// CODESYNC(a)\n// CODESYNC(b, 2)\n// CODESYNC(b)\n"
+                .to_string(),
+        )]);
+        let groups = matches.group_by_label();
+        let vars = HashMap::new();
+        let check = |label: &str, default| {
+            let comments = &groups[label];
+            check_count_with(
+                comments,
+                &vars,
+                CountOptions {
+                    default,
+                    ..CountOptions::default()
+                },
+            )
+        };
+        assert_eq!(check("a", 3), CountCheck::Ok);
+        assert_eq!(
+            check("a", DEFAULT_COUNT),
+            CountCheck::Mismatch {
+                expected: 2,
+                found: 3
+            }
+        );
+        // Explicit counts take precedence
+        assert_eq!(check("b", 3), CountCheck::Ok);
+    }
+
+    #[test]
+    fn count_unit_file() {
+        let matches = Matches::from_parsed(vec![
+            (
+                "a.rs".into(),
+                "// CODESYNC(a, 2)\n// CODESYNC(a)\n".to_string(),
+            ),
+            ("b.rs".into(), "// CODESYNC(a)\n".to_string()),
+        ]);
+        let comments = &matches.group_by_label()["a"];
+        let vars = HashMap::new();
+        let check = |unit| {
+            check_count_with(
+                comments,
+                &vars,
+                CountOptions {
+                    unit,
+                    ..CountOptions::default()
+                },
+            )
+        };
+        assert_eq!(check(CountUnit::File), CountCheck::Ok);
+        assert_eq!(
+            check(CountUnit::Occurrence),
+            CountCheck::Mismatch {
+                expected: 2,
+                found: 3
+            }
+        );
+        // Duplicates in a single file make up a single file
+        assert_eq!(
+            check_count_with(
+                &comments[..2],
+                &vars,
+                CountOptions {
+                    unit: CountUnit::File,
+                    ..CountOptions::default()
+                }
+            ),
+            CountCheck::Mismatch {
+                expected: 2,
+                found: 1
+            }
+        );
+    }
+
+    #[test]
+    fn expected_counts() {
+        let matches = parse(&["// CODESYNC(a, 3)", "// CODESYNC(a)", "// CODESYNC(a, 2)"]);
+        let comments: Vec<_> = matches
+            .iter()
+            .filter_map(|m| m.to_comment(Path::new("file.rs")))
+            .collect();
+        let vars = HashMap::new();
+        let expected = |comments| expected_count(comments, &vars, CountOptions::default());
+        assert_eq!(
+            expected(&comments[..2]),
+            Some(ExpectedCount {
+                total: 3,
+                explicit: true
+            })
+        );
+        assert_eq!(
+            expected(&comments[1..2]),
+            Some(ExpectedCount {
+                total: DEFAULT_COUNT,
+                explicit: false
+            })
+        );
+        assert_eq!(expected(&comments), None);
+    }
+
+    #[test]
+    fn conflicting_explicit_counts() {
+        assert_eq!(
+            check(&["// CODESYNC(a, 3)", "// CODESYNC(a, 2)", "// CODESYNC(a)"]),
+            CountCheck::Conflicting
+        );
+    }
 }
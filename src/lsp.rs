@@ -0,0 +1,670 @@
+//! A minimal language server publishing CODESYNC diagnostics.
+//!
+//! The server runs the same checks as `codesync check`, with the same `codesync.toml` and options,
+//! over the workspace given by the editor. Documents open in the editor are checked from their
+//! buffers on every change, while the rest of the workspace is searched on disk when the server
+//! starts and whenever a file is saved.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap, HashSet},
+    error::Error,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, LabelStyle, Severity},
+    files::Files,
+};
+use codesync::{
+    config::{Config, ScopedConfigs},
+    vfs::MemoryFileSystem,
+    CollectOptions, Matches,
+};
+use regex::Regex;
+
+use crate::{json::Value, report::Reporter, CheckArgs, Checker, Color, FileId, FilesDB};
+
+/// Run the server over stdin/stdout until the client sends `exit`. Diagnostics are computed as by
+/// `check` with `args`, searching the workspace with `opts` and skipping labels matching
+/// `ignore_label`.
+pub fn run(
+    args: CheckArgs,
+    opts: CollectOptions,
+    ignore_label: Vec<Regex>,
+) -> Result<(), Box<dyn Error>> {
+    serve(io::stdin().lock(), io::stdout(), args, opts, ignore_label)
+}
+
+/// Run the server reading messages from `reader` and writing to `writer` until the client sends
+/// `exit` or the input ends. A message that can't be read or handled is logged to stderr, and
+/// answered with an error if it's a request, without stopping the server. Only failing to read or
+/// write the transport itself is fatal.
+fn serve(
+    mut reader: impl BufRead,
+    writer: impl Write,
+    mut args: CheckArgs,
+    opts: CollectOptions,
+    ignore_label: Vec<Regex>,
+) -> Result<(), Box<dyn Error>> {
+    // Every diagnostic is published, and nothing but messages may be written to stdout
+    args.collect_all = true;
+    args.output = None;
+    args.trace_label = None;
+    args.rule_stats = None;
+    args.stdin_content = false;
+    args.stdin_name = None;
+    let mut server = Server {
+        writer,
+        args,
+        opts,
+        ignore_label,
+        root: None,
+        workspace: None,
+        buffers: HashMap::new(),
+        uris: HashMap::new(),
+        published: HashSet::new(),
+    };
+    while let Some(msg) = read_message(&mut reader)? {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(err) => {
+                eprintln!("codesync lsp: skipping malformed message: {err}");
+                continue;
+            }
+        };
+        let id = msg.get("id").cloned();
+        match server.handle(msg) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => {
+                eprintln!("codesync lsp: {err}");
+                if let Some(id) = id {
+                    server.respond_error(id, -32603, err.to_string())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Server<W> {
+    writer: W,
+    /// Options of the checks, as given to `check`.
+    args: CheckArgs,
+    /// Options to search the workspace, without its root.
+    opts: CollectOptions,
+    ignore_label: Vec<Regex>,
+    /// The root of the workspace, used to check counts across files.
+    root: Option<PathBuf>,
+    /// Matches in the workspace on disk, as of the last time it was searched.
+    workspace: Option<Matches>,
+    /// Contents of the documents currently open in the editor.
+    buffers: HashMap<PathBuf, String>,
+    /// The uri used by the client for each path, so we publish diagnostics under the same name.
+    uris: HashMap<PathBuf, String>,
+    /// Files with diagnostics in the last check, to clear them once they're fixed.
+    published: HashSet<PathBuf>,
+}
+
+impl<W: Write> Server<W> {
+    /// Handle a message returning `false` if the server should exit.
+    fn handle(&mut self, msg: Value) -> Result<bool, Box<dyn Error>> {
+        let method = msg.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+        match (method, msg.get("id").cloned()) {
+            ("initialize", Some(id)) => {
+                self.root = params
+                    .get("rootUri")
+                    .and_then(Value::as_str)
+                    .and_then(uri_to_path);
+                let capabilities = Value::object([(
+                    "textDocumentSync",
+                    Value::object([
+                        ("openClose", true.into()),
+                        // Full document sync
+                        ("change", Value::Number(1)),
+                        ("save", true.into()),
+                    ]),
+                )]);
+                self.respond(id, Value::object([("capabilities", capabilities)]))?;
+            }
+            ("shutdown", Some(id)) => self.respond(id, Value::Null)?,
+            ("exit", _) => return Ok(false),
+            ("initialized", _) => self.refresh_workspace()?,
+            ("textDocument/didOpen", _) => {
+                let document = params.get("textDocument");
+                let uri = document.and_then(|d| d.get("uri")).and_then(Value::as_str);
+                let text = document.and_then(|d| d.get("text")).and_then(Value::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    self.update_buffer(uri, text.to_string())?;
+                }
+            }
+            ("textDocument/didChange", _) => {
+                let uri = params
+                    .get("textDocument")
+                    .and_then(|d| d.get("uri"))
+                    .and_then(Value::as_str);
+                // With full document sync the last change contains the whole document
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Value::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Value::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    self.update_buffer(uri, text.to_string())?;
+                }
+            }
+            ("textDocument/didClose", _) => {
+                let uri = params
+                    .get("textDocument")
+                    .and_then(|d| d.get("uri"))
+                    .and_then(Value::as_str);
+                if let Some(path) = uri.and_then(uri_to_path) {
+                    self.buffers.remove(&path);
+                    self.check()?;
+                }
+            }
+            ("textDocument/didSave", _) => self.refresh_workspace()?,
+            (_, Some(id)) => {
+                self.respond_error(id, -32601, format!("unsupported method `{method}`"))?;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn update_buffer(&mut self, uri: &str, text: String) -> Result<(), Box<dyn Error>> {
+        let Some(path) = uri_to_path(uri) else {
+            return Ok(());
+        };
+        self.uris.insert(path.clone(), uri.to_string());
+        self.buffers.insert(path, text);
+        self.check()
+    }
+
+    /// Search the workspace on disk again and recheck it.
+    fn refresh_workspace(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(root) = &self.root else {
+            return Ok(());
+        };
+        let opts = self.collect_options(root)?.root(root);
+        self.workspace = Some(Matches::collect_with_options(&opts)?);
+        self.check()
+    }
+
+    /// The options searching the workspace, including the keywords set in its `codesync.toml`.
+    fn collect_options(&self, root: &Path) -> Result<CollectOptions, Box<dyn Error>> {
+        let mut opts = self.opts.clone();
+        for (glob, keyword) in Config::load(root)?.keywords {
+            opts = opts.keyword_for(glob, keyword);
+        }
+        Ok(opts)
+    }
+
+    /// Check the workspace, with open documents in place of their files on disk, and publish the
+    /// diagnostics of every open document and of every file whose diagnostics changed. Without a
+    /// workspace, only open documents are checked, each on its own as with `check --stdin-content`.
+    fn check(&mut self) -> Result<(), Box<dyn Error>> {
+        let root = self.root.clone().unwrap_or_else(|| PathBuf::from("."));
+        let mut fs = MemoryFileSystem::new();
+        for (path, text) in &self.buffers {
+            fs.insert(path, text.as_str());
+        }
+        let opts = self.collect_options(&root)?.roots(self.buffers.keys());
+        let open = Matches::collect_from(&fs, &opts)?;
+        let mut matches = match self.workspace.clone() {
+            Some(mut workspace) => {
+                // An open document may have no matches left, so its file is dropped altogether
+                workspace.retain_files(|path| !self.buffers.contains_key(path));
+                workspace.merge(open)
+            }
+            None => open,
+        };
+        if !self.ignore_label.is_empty() {
+            matches.retain_labels(|label| !self.ignore_label.iter().any(|re| re.is_match(label)));
+        }
+
+        let mut args = self.args.clone();
+        args.stdin_content = self.workspace.is_none();
+        let diagnostics = Rc::new(RefCell::new(HashMap::new()));
+        Checker::new(args, ScopedConfigs::new(&root)?, None, Color::Never)?
+            .reporter(Box::new(Collector(diagnostics.clone())))
+            .run(&matches)?;
+        let mut diagnostics = diagnostics.take();
+
+        let mut paths: BTreeSet<PathBuf> = diagnostics.keys().cloned().collect();
+        paths.extend(self.buffers.keys().cloned());
+        paths.extend(std::mem::take(&mut self.published));
+        for path in paths {
+            let values = diagnostics.remove(&path).unwrap_or_default();
+            if !values.is_empty() {
+                self.published.insert(path.clone());
+            }
+            self.publish(&path, values)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, path: &Path, diagnostics: Vec<Value>) -> Result<(), Box<dyn Error>> {
+        let uri = self
+            .uris
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| path_to_uri(path));
+        self.send(Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Value::object([
+                    ("uri", uri.into()),
+                    ("diagnostics", Value::Array(diagnostics)),
+                ]),
+            ),
+        ]))
+    }
+
+    fn respond(&mut self, id: Value, result: Value) -> Result<(), Box<dyn Error>> {
+        self.send(Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("result", result),
+        ]))
+    }
+
+    fn respond_error(
+        &mut self,
+        id: Value,
+        code: i64,
+        message: String,
+    ) -> Result<(), Box<dyn Error>> {
+        let error = Value::object([("code", Value::Number(code)), ("message", message.into())]);
+        self.send(Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("error", error),
+        ]))
+    }
+
+    fn send(&mut self, msg: Value) -> Result<(), Box<dyn Error>> {
+        let body = msg.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A [`Reporter`] collecting diagnostics by file to publish them.
+struct Collector(Rc<RefCell<HashMap<PathBuf, Vec<Value>>>>);
+
+impl Reporter for Collector {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), codespan_reporting::files::Error> {
+        let mut diagnostics = self.0.borrow_mut();
+        for (path, value) in to_lsp_diagnostics(db, diagnostic)? {
+            diagnostics.entry(path).or_default().push(value);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Read a message framed with a `Content-Length` header returning `None` at the end of the input.
+/// The inner error is a malformed message, after which reading can continue with the next one,
+/// while the outer error means the input itself couldn't be read.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Result<Value, String>>> {
+    let mut content_length = None;
+    let mut bad_header = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(len) = line.strip_prefix("Content-Length:") {
+            match len.trim().parse::<usize>() {
+                Ok(len) => content_length = Some(len),
+                Err(err) => bad_header = Some(format!("invalid `Content-Length`: {err}")),
+            }
+        }
+    }
+    if let Some(err) = bad_header {
+        return Ok(Some(Err(err)));
+    }
+    let Some(len) = content_length else {
+        return Ok(Some(Err(
+            "message is missing the `Content-Length` header".to_string()
+        )));
+    };
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    let body = match String::from_utf8(body) {
+        Ok(body) => body,
+        Err(err) => return Ok(Some(Err(err.to_string()))),
+    };
+    Ok(Some(Value::parse(&body).map_err(|err| err.to_string())))
+}
+
+/// Convert a diagnostic into one LSP diagnostic per primary label, grouped by the label's file.
+/// Other labels are attached as related information.
+fn to_lsp_diagnostics(
+    db: &FilesDB,
+    diagnostic: &Diagnostic<FileId>,
+) -> Result<Vec<(PathBuf, Value)>, codespan_reporting::files::Error> {
+    let severity = match diagnostic.severity {
+        Severity::Bug | Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+        Severity::Help => 4,
+    };
+    let mut result = vec![];
+    for (i, label) in diagnostic.labels.iter().enumerate() {
+        if label.style != LabelStyle::Primary {
+            continue;
+        }
+        let mut message = diagnostic.message.clone();
+        if !label.message.is_empty() {
+            message = format!("{message}: {}", label.message);
+        }
+        for note in &diagnostic.notes {
+            message = format!("{message}\n{note}");
+        }
+        let related = diagnostic
+            .labels
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, other)| {
+                let path = PathBuf::from(db.files.name(other.file_id)?);
+                let location = Value::object([
+                    ("uri", path_to_uri(&path).into()),
+                    ("range", range(db, other.file_id, &other.range)?),
+                ]);
+                Ok(Value::object([
+                    ("location", location),
+                    ("message", other.message.as_str().into()),
+                ]))
+            })
+            .collect::<Result<Vec<_>, codespan_reporting::files::Error>>()?;
+        let value = Value::object([
+            ("range", range(db, label.file_id, &label.range)?),
+            ("severity", Value::Number(severity)),
+            ("source", "codesync".into()),
+            ("message", message.into()),
+            ("relatedInformation", Value::Array(related)),
+        ]);
+        result.push((PathBuf::from(db.files.name(label.file_id)?), value));
+    }
+    Ok(result)
+}
+
+fn range(
+    db: &FilesDB,
+    file_id: FileId,
+    span: &std::ops::Range<usize>,
+) -> Result<Value, codespan_reporting::files::Error> {
+    Ok(Value::object([
+        ("start", position(db, file_id, span.start)?),
+        ("end", position(db, file_id, span.end)?),
+    ]))
+}
+
+/// An LSP position. Columns are measured in UTF-16 code units.
+fn position(
+    db: &FilesDB,
+    file_id: FileId,
+    byte_index: usize,
+) -> Result<Value, codespan_reporting::files::Error> {
+    let line = db.files.line_index(file_id, byte_index)?;
+    let line_start = db.files.line_range(file_id, line)?.start;
+    let source = db.files.source(file_id)?;
+    let character = source[line_start..byte_index].encode_utf16().count();
+    Ok(Value::object([
+        ("line", line.into()),
+        ("character", character.into()),
+    ]))
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = path
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for b in path.display().to_string().bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::Cli;
+
+    fn frame(msg: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{msg}", msg.len())
+    }
+
+    /// Run the server over `input`, returning the messages it sent.
+    fn exchange(input: &str) -> Vec<Value> {
+        let mut output = vec![];
+        let Cli {
+            command: Some(crate::Args::Lsp(args)),
+            ..
+        } = Cli::try_parse_from(["codesync", "lsp"]).unwrap()
+        else {
+            unreachable!()
+        };
+        serve(
+            input.as_bytes(),
+            &mut output,
+            args,
+            CollectOptions::new(),
+            vec![],
+        )
+        .unwrap();
+        let mut reader = &output[..];
+        let mut msgs = vec![];
+        while let Some(msg) = read_message(&mut reader).unwrap() {
+            msgs.push(msg.unwrap());
+        }
+        msgs
+    }
+
+    fn diagnostics(msg: &Value) -> (&str, &[Value]) {
+        assert_eq!(
+            msg.get("method").and_then(Value::as_str),
+            Some("textDocument/publishDiagnostics")
+        );
+        let params = msg.get("params").unwrap();
+        let uri = params.get("uri").and_then(Value::as_str).unwrap();
+        (
+            uri,
+            params.get("diagnostics").and_then(Value::as_array).unwrap(),
+        )
+    }
+
+    #[test]
+    fn initialize() {
+        let input = [
+            frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+        ]
+        .concat();
+        let msgs = exchange(&input);
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[0].get("id"), Some(&Value::Number(1)));
+        let sync = msgs[0]
+            .get("result")
+            .and_then(|r| r.get("capabilities"))
+            .and_then(|c| c.get("textDocumentSync"))
+            .unwrap();
+        assert_eq!(sync.get("change"), Some(&Value::Number(1)));
+        assert_eq!(msgs[1].get("id"), Some(&Value::Number(2)));
+        assert_eq!(msgs[1].get("result"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn did_open_publishes_diagnostics() {
+        let open = r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///project/a.rs","text":"// CODESYNC(foo, x)\n// CODESYNC(bar)\n"}}}"#;
+        let close = r#"{"jsonrpc":"2.0","method":"textDocument/didClose","params":{"textDocument":{"uri":"file:///project/a.rs"}}}"#;
+        let msgs = exchange(&[frame(open), frame(close)].concat());
+        assert_eq!(msgs.len(), 2);
+
+        let (uri, opened) = diagnostics(&msgs[0]);
+        assert_eq!(uri, "file:///project/a.rs");
+        assert_eq!(opened.len(), 1);
+        let start = opened[0].get("range").and_then(|r| r.get("start")).unwrap();
+        assert_eq!(start.get("line"), Some(&Value::Number(0)));
+        assert_eq!(start.get("character"), Some(&Value::Number(16)));
+        assert_eq!(
+            opened[0].get("source").and_then(Value::as_str),
+            Some("codesync")
+        );
+
+        let (uri, closed) = diagnostics(&msgs[1]);
+        assert_eq!(uri, "file:///project/a.rs");
+        assert!(closed.is_empty());
+    }
+
+    #[test]
+    fn publishes_workspace_diagnostics() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.rs"), "// CODESYNC(foo, 3)\n").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "// CODESYNC(foo, 3)\n").unwrap();
+        let initialize = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"initialize","params":{{"rootUri":"{}"}}}}"#,
+            path_to_uri(dir.path())
+        );
+        let input = [
+            frame(&initialize),
+            frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+        ]
+        .concat();
+        let msgs = exchange(&input);
+        assert_eq!(msgs.len(), 3);
+        let mut uris: Vec<_> = msgs[1..]
+            .iter()
+            .map(|msg| {
+                let (uri, diagnostics) = diagnostics(msg);
+                assert_eq!(diagnostics.len(), 1);
+                uri.to_string()
+            })
+            .collect();
+        uris.sort();
+        assert_eq!(
+            uris,
+            [
+                path_to_uri(&dir.path().join("a.rs")),
+                path_to_uri(&dir.path().join("b.rs"))
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_workspace_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("codesync.toml"),
+            "[aliases]\nold = \"foo\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("a.rs"), "// CODESYNC(foo, 2)\n").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "// CODESYNC(old, 2)\n").unwrap();
+        let initialize = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"initialize","params":{{"rootUri":"{}"}}}}"#,
+            path_to_uri(dir.path())
+        );
+        // Dropping the alias from the buffer of `b.rs` leaves `foo` with a single comment
+        let change = format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{}","text":"// CODESYNC(new, 2)\n"}}}}}}"#,
+            path_to_uri(&dir.path().join("b.rs"))
+        );
+        let input = [
+            frame(&initialize),
+            frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+            frame(&change),
+        ]
+        .concat();
+        let msgs = exchange(&input);
+        assert_eq!(msgs.len(), 4);
+        // The alias counts both comments as `foo`, only warning about the old label
+        let (uri, renamed) = diagnostics(&msgs[1]);
+        assert_eq!(uri, path_to_uri(&dir.path().join("b.rs")));
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].get("severity"), Some(&Value::Number(2)));
+        let mut uris: Vec<_> = msgs[2..]
+            .iter()
+            .map(|msg| {
+                let (uri, diagnostics) = diagnostics(msg);
+                assert_eq!(diagnostics.len(), 1);
+                uri.to_string()
+            })
+            .collect();
+        uris.sort();
+        assert_eq!(
+            uris,
+            [
+                path_to_uri(&dir.path().join("a.rs")),
+                path_to_uri(&dir.path().join("b.rs"))
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        let input = [
+            "Content-Length: many\r\n\r\n".to_string(),
+            frame("{"),
+            frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"rootUri":"file:///missing/codesync/root"}}"#),
+            // Walking the missing root fails
+            frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+        ]
+        .concat();
+        let msgs = exchange(&input);
+        let ids: Vec<_> = msgs.iter().filter_map(|msg| msg.get("id")).collect();
+        assert_eq!(ids, [&Value::Number(1), &Value::Number(2)]);
+    }
+}
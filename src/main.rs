@@ -1,45 +1,794 @@
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     error::Error,
-    io::{self, Write},
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
     ops::Range,
     path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
 };
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, ValueEnum};
 use codespan_reporting::{
-    diagnostic::{Diagnostic, Label},
-    files::SimpleFiles,
-    term::{
-        self,
-        termcolor::{ColorChoice, ColorSpec, StandardStream, WriteColor},
-    },
+    diagnostic::{Diagnostic, Label, LabelStyle, Severity},
+    files::{Files, SimpleFiles},
+    term::termcolor::{ColorChoice, ColorSpec, NoColor, StandardStream, WriteColor},
+};
+use codesync::{
+    config::{self, Config, Level, ScopedConfigs, CONFIG_FILE},
+    inflector,
+    vfs::{FileSystem, MemoryFileSystem, OsFileSystem},
+    Arg, ArgsError, BinaryDetection, CollectOptions, CollectProfile, Comment, Count, CountCheck,
+    CountOptions, CountSemantics, CountUnit, ExpectedCount, InvalidMatch, Matches, Suppression,
+    DEFAULT_MAX_MATCHES_PER_FILE,
 };
-use codesync::{inflector, Arg, ArgsError, Comment, Matches};
+use git::{Blame, ChangedLines};
 use regex::Regex;
+use report::Reporter;
+
+mod explain;
+mod fix;
+mod git;
+mod json;
+mod lsp;
+mod report;
 
 #[derive(Parser)]
-#[command(disable_help_subcommand = true)]
+#[command(disable_help_subcommand = true, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Args>,
+    /// Print a detailed explanation of a diagnostic code, e.g., `incorrect-count`, and exit.
+    #[arg(long, value_name = "CODE")]
+    explain: Option<String>,
+    /// Directory to search for comments and `codesync.toml`.
+    ///
+    /// Defaults to the value of the `CODESYNC_ROOT` environment variable if set and non-empty, and
+    /// to the current directory otherwise.
+    #[arg(long, global = true, value_name = "DIR")]
+    path: Option<PathBuf>,
+    /// Search exactly the files listed in the given file, one path per line, instead of walking
+    /// the searched directory. With `-` the list is read from stdin.
+    ///
+    /// This is meant for build systems that write the set of files to check. `codesync.toml` is
+    /// still looked up in the directory given by `--path`.
+    #[arg(long, global = true, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+    /// Only search files tracked by git, skipping untracked ones such as scratch files. Files are
+    /// still subject to the other filters. Fails outside a git repository.
+    #[arg(long, global = true)]
+    git_tracked: bool,
+    /// Don't skip hidden files or files excluded by ignore files.
+    ///
+    /// This disables all default filters together: hidden files, `.gitignore`, `.ignore`, global
+    /// git excludes, and ignore files in parent directories. It's useful, e.g., to audit vendored
+    /// code. The `.git` directory is always skipped.
+    #[arg(long, global = true)]
+    no_default_ignores: bool,
+    /// Only search files matching the given glob, e.g., `src/**`. Can be given multiple times.
+    ///
+    /// Globs use gitignore syntax relative to the current directory. They take precedence over
+    /// ignore files, so a matching file is searched even if `.gitignore` excludes it.
+    #[arg(long, global = true, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip files and directories matching the given glob, e.g., `tests/fixtures/**` or
+    /// `*.min.js`. Can be given multiple times and takes precedence over `--include`.
+    #[arg(long, global = true, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Only search files with the given extension, e.g., `rs` or `.ts`. Can be given multiple
+    /// times.
+    ///
+    /// Extensions are compared ignoring case. Files must also pass ignore files and `--include`
+    /// and `--exclude` globs. A file given as the searched path is searched regardless of its
+    /// extension.
+    #[arg(long, global = true, value_name = "EXT", alias = "scan-extensions")]
+    ext: Vec<String>,
+    /// Don't descend more than this many directories below the searched directory, e.g., with `1`
+    /// only files directly inside it are searched.
+    #[arg(long, global = true, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Follow symbolic links when searching directories. A file reached through more than one path
+    /// is only searched once.
+    #[arg(long, global = true)]
+    follow_symlinks: bool,
+    /// Number of threads used to search files, with `0` meaning one per available CPU. Output is
+    /// the same for any number of threads.
+    #[arg(long, global = true, value_name = "N", default_value_t = 1)]
+    threads: usize,
+    /// Ignore occurrences of `CODESYNC` immediately preceded or followed by an identifier
+    /// character, e.g., `CODESYNCHRONIZED` or `XCODESYNC(a)`, instead of reporting them.
+    #[arg(
+        long,
+        global = true,
+        value_name = "BOOL",
+        default_value_t = true,
+        action = clap::ArgAction::Set
+    )]
+    strict_keyword_boundary: bool,
+    /// Report a comma followed by an empty count, e.g., `CODESYNC(my-label,)`, as an invalid count
+    /// instead of reading the comment as having no count.
+    #[arg(long, global = true)]
+    strict_trailing_comma: bool,
+    /// Let arguments missing their closing parenthesis continue on the next line, e.g.,
+    /// `// CODESYNC(very-long-` followed by `// label)`.
+    ///
+    /// A trailing backslash is removed from the first line, and the indentation and a line comment
+    /// delimiter from the second, before joining them without whitespace, so the example has
+    /// label `very-long-label`.
+    #[arg(long, global = true)]
+    multiline: bool,
+    /// Accept a label without parentheses, e.g., `// CODESYNC my-label`, as shorthand for a
+    /// comment without a count. The parenthesized form is still preferred.
+    #[arg(long, global = true)]
+    bare_labels: bool,
+    /// Also accept the keyword written as a lowercase `@` tag, e.g., `// @codesync(my-label)`, for
+    /// documentation tools that don't handle the bare keyword. Both spellings share labels.
+    #[arg(long, global = true)]
+    tag_spelling: bool,
+    /// Only accept the keyword when the text before it on its line ends with a match of the given
+    /// regex, e.g., `^\s*//\s*` for line comments at the start of a line. Other occurrences of
+    /// the keyword are ignored.
+    #[arg(long, global = true, value_name = "REGEX")]
+    comment_prefix: Option<String>,
+    /// How files with binary data, i.e., NUL bytes, are handled.
+    #[arg(long, global = true, value_enum, value_name = "MODE", default_value_t = BinaryDetectionArg::None)]
+    binary_detection: BinaryDetectionArg,
+    /// Print every file in which binary data was detected with `--binary-detection` to stderr, e.g.,
+    /// to find out why a comment in it isn't found.
+    #[arg(long, global = true)]
+    report_binary: bool,
+    /// Stop collecting matches in a file after this many and warn about it, to guard against
+    /// degenerate files repeating the keyword. `0` means no limit.
+    #[arg(long, global = true, value_name = "N", default_value_t = DEFAULT_MAX_MATCHES_PER_FILE)]
+    max_matches_per_file: usize,
+    /// Exclude comments whose label matches the given regex from all checks, as if they weren't
+    /// there. Can be given multiple times. `list` and `show` also skip them unless `--all` is given.
+    #[arg(long, global = true, value_name = "REGEX")]
+    ignore_label: Vec<Regex>,
+    /// When to use colors. `auto` uses colors when printing to a terminal unless the `NO_COLOR`
+    /// environment variable is set.
+    #[arg(long, global = true, value_enum, default_value_t = Color::Auto)]
+    color: Color,
+    /// Print the time spent walking directories, searching files, parsing matches, and checking,
+    /// plus the number of files and bytes searched, to stderr. Parsing is part of searching and is
+    /// summed across threads.
+    #[arg(long, global = true)]
+    profile: bool,
+}
+
+impl Cli {
+    /// The directory to search: `--path`, then `CODESYNC_ROOT`, then the current directory.
+    fn root(&self) -> PathBuf {
+        self.path
+            .clone()
+            .or_else(|| {
+                std::env::var_os(ROOT_ENV)
+                    .filter(|root| !root.is_empty())
+                    .map(PathBuf::from)
+            })
+            .unwrap_or_else(|| PathBuf::from("./"))
+    }
+}
+
+/// Environment variable setting the directory to search when `--path` isn't given.
+const ROOT_ENV: &str = "CODESYNC_ROOT";
+
+#[derive(clap::Subcommand)]
 enum Args {
     /// Check that all CODESYNC matches are well-formed and their counts are correct.
     Check(CheckArgs),
     /// Show all valid CODESYNC comments with a given label. This ignores invalid matches.
-    Show { label: String },
+    Show {
+        #[arg(required_unless_present = "prefix", conflicts_with = "prefix")]
+        label: Option<String>,
+        #[command(flatten)]
+        filter: LabelFilter,
+        /// Print the expected and found number of comments before the comments of each label, as
+        /// compared by `check`.
+        #[arg(long)]
+        counts: bool,
+        /// How explicit counts are interpreted with `--counts`.
+        #[arg(long, value_enum, default_value_t = CountSemanticsArg::Total)]
+        count_semantics: CountSemanticsArg,
+    },
     /// List all labels from valid comments. This ignores invalid matches.
-    List,
+    List {
+        #[command(flatten)]
+        filter: LabelFilter,
+        /// Only include labels matching the given regex.
+        #[arg(long)]
+        label_pattern: Option<Regex>,
+        /// Only include labels with at least this many comments.
+        #[arg(long, value_name = "N")]
+        min_count: Option<usize>,
+        /// Only include labels with at most this many comments.
+        #[arg(long, value_name = "N")]
+        max_count: Option<usize>,
+        /// Print the number of comments found for each label after the label, separated by a tab.
+        #[arg(long)]
+        counts: bool,
+        /// Print the location (`file:line:col`) of every comment indented beneath its label.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Repair common typos in comments, prompting before applying each fix.
+    #[command(group(clap::ArgGroup::new("fixes").required(true).multiple(true)))]
+    Fix {
+        /// Fix malformed comments and labels that look like typos, e.g., a missing `)` or `;`
+        /// instead of `,`.
+        #[arg(long, group = "fixes")]
+        malformed: bool,
+        /// Give comments canonical spacing, as checked by `check --canonical-spacing`.
+        #[arg(long, group = "fixes")]
+        spacing: bool,
+        /// Apply fixes that are very likely correct without prompting and skip the rest.
+        #[arg(long)]
+        yes: bool,
+        /// Skip files with uncommitted changes, staged or not, so fixes don't get mixed with work in
+        /// progress. Outside a git repository, all files are fixed with a warning.
+        #[arg(long, conflicts_with = "only_dirty")]
+        only_clean: bool,
+        /// Only fix files with uncommitted changes, staged or not, e.g., the files being edited.
+        /// Outside a git repository, all files are fixed with a warning.
+        #[arg(long)]
+        only_dirty: bool,
+    },
+    /// Print the expected and found number of comments for every label.
+    Summary {
+        /// How explicit counts are interpreted.
+        #[arg(long, value_enum, default_value_t = CountSemanticsArg::Total)]
+        count_semantics: CountSemanticsArg,
+        /// Print the summary as a JSON array instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the number of files with matches, labels, comments, and invalid matches.
+    Stats {
+        /// Print how many labels have each number of comments instead.
+        #[arg(long)]
+        count_distribution: bool,
+        /// Print a hash of every label with its expected and found number of comments instead.
+        ///
+        /// The hash changes when labels or their counts change. It doesn't cover anything else
+        /// `check` reports on, e.g., invalid matches or style settings. See the README for exactly
+        /// what's hashed.
+        #[arg(long, conflicts_with = "count_distribution")]
+        hash: bool,
+        /// Print the stats as a JSON object instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print every match, valid or not, as a row of tab-separated values for ad-hoc analysis with
+    /// other tools.
+    ///
+    /// The columns are `file`, `byte_start`, `byte_end`, `label`, `count`, and `valid`, after a
+    /// header row. Rows are ordered by file path and byte offset. Invalid matches have an empty label
+    /// and count, and the count is empty for comments without one. Tabs, newlines, carriage returns,
+    /// and backslashes in values are escaped as `\t`, `\n`, `\r`, and `\\`.
+    Dump,
+    /// Check that `codesync.toml` and the nested ones are valid without searching for comments.
+    ValidateConfig,
+    /// Print the settings `check` uses with the given options after merging them with
+    /// `codesync.toml`, as TOML. This doesn't search for comments.
+    PrintConfig {
+        #[command(flatten)]
+        check: CheckArgs,
+        /// Print the settings as a JSON object instead of TOML.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print how the given text is parsed, e.g., `codesync parse 'CODESYNC(foo, 3)'`: the label,
+    /// count, and spans of every comment, or why a match is invalid. Spans are byte offsets into
+    /// the text. Reads the text from stdin if it's not given.
+    Parse {
+        text: Option<String>,
+        /// Print the matches as a JSON array instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Install a git pre-commit hook running `codesync check --git-tracked`. Delete the hook file
+    /// to uninstall it.
+    InstallHook {
+        /// Overwrite an existing hook that wasn't installed by codesync.
+        #[arg(long)]
+        force: bool,
+        /// Print the hook instead of installing it, e.g., to add it to an existing hook.
+        #[arg(long)]
+        print: bool,
+    },
+    /// Print a script completing subcommands, options, and their values for the given shell, e.g.,
+    /// `codesync completions bash > /etc/bash_completion.d/codesync`.
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print a JSON Schema describing the objects printed by `check --format jsonl`.
+    #[command(hide = true)]
+    JsonSchema,
+    /// Run a language server publishing diagnostics to editors over stdin/stdout.
+    ///
+    /// Takes the same options as `check` and reports the same diagnostics for the workspace, with
+    /// the contents of open documents in place of the files on disk. Options only affecting how
+    /// diagnostics are printed are ignored.
+    Lsp(CheckArgs),
 }
 
-#[derive(clap::Args)]
+#[derive(Clone, clap::Args)]
 struct CheckArgs {
-    /// Check that all labels use the same casing.
-    #[arg(long)]
-    consistent_casing: Option<Case>,
+    #[command(flatten)]
+    casing: CasingArgs,
     /// Check that there is no extra whitespace around arguments.
     #[arg(long)]
     no_extra_whitespace: bool,
+    /// Check that arguments are written with canonical spacing, e.g., `CODESYNC(my-label, 2)`:
+    /// exactly one space after the comma and no other whitespace. Fixed by `codesync fix --spacing`.
+    #[arg(long)]
+    canonical_spacing: bool,
     /// Check that labels match the given regex.
     #[arg(long)]
     label_pattern: Option<Regex>,
+    /// Allow comments with an empty label, e.g., `CODESYNC()` or `CODESYNC(, 2)`.
+    #[arg(long)]
+    allow_empty_label: bool,
+    /// Don't warn about labels that only have digits, e.g., `CODESYNC(42)`, which are usually a
+    /// count with a missing label.
+    #[arg(long)]
+    allow_numeric_label: bool,
+    /// Report comments using the given label, e.g., a placeholder like `TODO` used while drafting.
+    /// Can be given multiple times. Labels must match exactly.
+    #[arg(long, value_name = "LABEL")]
+    forbid_label: Vec<String>,
+    /// Check that labels are at most this many characters long (counting Unicode scalar values).
+    #[arg(long, value_name = "N")]
+    max_label_length: Option<usize>,
+    /// Check that every comment has a note explaining it as its last argument, e.g.,
+    /// `CODESYNC(my-label, 2, "keep in sync with the parser")`. Empty notes are reported too.
+    #[arg(long)]
+    require_note: bool,
+    /// Warn about distinct labels that only differ in casing or word separators, e.g., `MyLabel`
+    /// and `my-label`, since they likely refer to the same thing.
+    #[arg(long)]
+    warn_case_collisions: bool,
+    /// Count labels that only differ in word separators together, e.g., `my-label` and `my_label`,
+    /// and warn about the mixed spellings so they can be unified. Diagnostics still show each
+    /// comment's own spelling.
+    #[arg(long)]
+    normalize_separators: bool,
+    /// Check that an explicit count is written on the first occurrence of its label (by file path
+    /// and then position in the file).
+    #[arg(long)]
+    count_on_first: bool,
+    /// Only report style checks (`--consistent-casing`, `--no-extra-whitespace`,
+    /// `--canonical-spacing`, `--label-pattern`, `--max-label-length`, and `--require-note`) on
+    /// comments overlapping lines changed since the given git ref.
+    ///
+    /// Comments are still collected from the whole project and all other checks, e.g., counts,
+    /// are reported everywhere. Untracked files are not considered changed.
+    #[arg(long, value_name = "GIT_REF")]
+    since: Option<String>,
+    /// How explicit counts are interpreted.
+    #[arg(long, value_enum, default_value_t = CountSemanticsArg::Total)]
+    count_semantics: CountSemanticsArg,
+    /// Which comments with the same label are counted together.
+    #[arg(long, value_enum, default_value_t = CountScope::Global)]
+    count_scope: CountScope,
+    /// What is compared against the count of a label.
+    #[arg(long, value_enum, default_value_t = CountUnitArg::Occurrence)]
+    count_unit: CountUnitArg,
+    /// Report a label whose number of comments is off by at most this many from its count as a
+    /// warning instead of an error, e.g., while changes land across files.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    count_tolerance: usize,
+    /// Don't report a label whose number of comments is off by less than this many from its
+    /// count, to focus on labels that drifted significantly.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    count_report_threshold: usize,
+    /// Add a note to the diagnostic of a label whose number of comments is off by more than this
+    /// many from its count, suggesting that a typo made unrelated comments share the label and
+    /// listing labels with a similar spelling.
+    #[arg(long, value_name = "N")]
+    collision_hint_threshold: Option<usize>,
+    /// Read the expected number of comments of labels without an explicit count from the given
+    /// file instead of using the default of 2. The file has a `label=count` line per label or is
+    /// a JSON object mapping labels to counts.
+    #[arg(long, value_name = "PATH")]
+    count_from_file: Option<PathBuf>,
+    /// Fail if no valid comments are found, e.g., because the tool is run in the wrong directory
+    /// or all files are ignored.
+    #[arg(long)]
+    fail_on_empty: bool,
+    /// Run every check even if earlier ones found errors, e.g., check counts despite malformed
+    /// comments, and report all diagnostics at once. By default, checking stops after the first
+    /// group of checks reporting errors.
+    #[arg(long)]
+    collect_all: bool,
+    /// Print how many diagnostics were reported for each code to stderr once checking finishes,
+    /// most frequent first, e.g., to decide what to clean up first. With `--rule-stats=json` the
+    /// tally is printed as a JSON object instead.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text"
+    )]
+    rule_stats: Option<RuleStatsFormat>,
+    /// Print every comment with the given label and how its expected count is derived to stderr,
+    /// e.g., to understand why the label is reported. This doesn't change what is reported or the
+    /// exit status.
+    #[arg(long, value_name = "LABEL")]
+    trace_label: Option<String>,
+    /// Format used to report diagnostics.
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    /// Indent the report for reading instead of printing it on a single line. Only applies to
+    /// `--format codeclimate`, since `jsonl` must print one object per line.
+    #[arg(long)]
+    pretty: bool,
+    /// Point at no more than this many comments in a diagnostic about the count of a label, with
+    /// a note saying how many were left out. `0` means no limit.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_LABELS_PER_DIAGNOSTIC)]
+    max_labels_per_diagnostic: usize,
+    /// Which comments a diagnostic about the count of a label is anchored to, i.e., points at as
+    /// primary locations. Formats reporting a single location per diagnostic use the first one.
+    #[arg(long, value_enum, default_value_t = Anchor::All)]
+    anchor_diagnostic: Anchor,
+    /// Add a note to each diagnostic with the author and commit that last changed the lines it
+    /// points to, as reported by `git blame`. Omitted for files git doesn't track.
+    #[arg(long)]
+    blame: bool,
+    /// Check source read from stdin instead of searching files, e.g., an editor's unsaved buffer.
+    /// Checks across files, i.e., counts, are skipped, while every other check runs on the buffer.
+    /// Global options selecting files, e.g., `--files-from` or `--git-tracked`, are ignored.
+    #[arg(long)]
+    stdin_content: bool,
+    /// The path diagnostics use for the source read with `--stdin-content`, which also selects
+    /// the keyword and comment syntax by extension, e.g., `src/lib.rs`. Defaults to `<stdin>`.
+    #[arg(long, value_name = "PATH", requires = "stdin_content")]
+    stdin_name: Option<PathBuf>,
+    /// Write diagnostics to the given file instead of stdout/stderr. Colors are disabled.
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Print paths relative to the searched directory, with `/` as separator, in the `jsonl`,
+    /// `codeclimate`, `junit`, and `gnu` formats, so reports stay valid when moved to another
+    /// machine. Otherwise paths are printed as found, e.g., absolute when `--path` is.
+    #[arg(long)]
+    output_relative_uris: bool,
+    /// Don't print individual diagnostics, only the number of them by code and by file once
+    /// checking finishes. The exit code is the same as without it.
+    #[arg(long, conflicts_with = "format")]
+    summary_only: bool,
+    /// Report diagnostics with the given code at the given level, e.g., `missing-note=warn`. The
+    /// level is `error`, `warn`, or `allow` to not report them at all. Can be given multiple times.
+    /// The `[severity]` table of `codesync.toml` takes precedence.
+    #[arg(long, value_name = "CODE=LEVEL")]
+    lint: Vec<LintLevel>,
+}
+
+/// A diagnostic code and the level to report it at, given to `--lint` as `CODE=LEVEL`.
+#[derive(Clone)]
+struct LintLevel {
+    code: String,
+    level: Level,
+}
+
+impl FromStr for LintLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, level) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected `CODE=LEVEL`, found `{s}`"))?;
+        check_code(code)?;
+        let level = Level::parse(level).ok_or_else(|| {
+            format!("invalid level `{level}`, expected `error`, `warn`, or `allow`")
+        })?;
+        Ok(Self {
+            code: code.to_string(),
+            level,
+        })
+    }
+}
+
+/// Fail if `code` isn't a diagnostic code.
+fn check_code(code: &str) -> Result<(), String> {
+    match explain::explanation(code) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "unknown diagnostic code `{code}`, see `--explain` for the list of codes"
+        )),
+    }
+}
+
+/// The level of every diagnostic code whose default is overridden with `--lint` or in the
+/// `[severity]` table of `config`, which takes precedence.
+fn lint_levels(args: &CheckArgs, config: &Config) -> Result<BTreeMap<String, Level>, String> {
+    let mut levels: BTreeMap<_, _> = args
+        .lint
+        .iter()
+        .map(|lint| (lint.code.clone(), lint.level))
+        .collect();
+    for (code, level) in &config.severity {
+        check_code(code).map_err(|err| format!("in `[severity]` of `{CONFIG_FILE}`: {err}"))?;
+        levels.insert(code.clone(), *level);
+    }
+    Ok(levels)
+}
+
+#[derive(clap::Args)]
+struct LabelFilter {
+    /// Only include labels equal to or nested under this prefix, e.g., `payments` includes
+    /// `payments/refunds/state`.
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Separator between the segments of a label used to match `--prefix`.
+    #[arg(long, default_value = "/", requires = "prefix")]
+    separator: String,
+    /// Include labels excluded with `--ignore-label`.
+    #[arg(long)]
+    all: bool,
+}
+
+impl LabelFilter {
+    fn includes(&self, label: &str) -> bool {
+        match &self.prefix {
+            Some(prefix) => codesync::has_label_prefix(label, prefix, &self.separator),
+            None => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum RuleStatsFormat {
+    Text,
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// Human readable diagnostics printed to stderr.
+    Human,
+    /// One JSON object per diagnostic printed to stdout as soon as it's produced. The schema of
+    /// each object is printed by `codesync json-schema`.
+    Jsonl,
+    /// A Code Climate report printed to stdout once checking finishes, as consumed by GitLab's
+    /// Code Quality widget.
+    #[value(name = "codeclimate")]
+    CodeClimate,
+    /// A JUnit XML report printed to stdout once checking finishes, with a test case for each file
+    /// with diagnostics.
+    Junit,
+    /// One `file:line:col: error: message [code]` line per diagnostic printed to stdout, as
+    /// produced by GNU tools and parsed by most editors.
+    Gnu,
+    /// Only the number of diagnostics by code and by file, selected with `--summary-only`.
+    #[value(skip)]
+    Summary,
+}
+
+impl Format {
+    /// The reporter for the format. `pretty` indents JSON documents, see `--pretty`.
+    fn reporter(self, writer: Box<dyn WriteColor>, pretty: bool) -> Box<dyn Reporter> {
+        match self {
+            Format::Human => Box::new(report::Human::new(writer)),
+            Format::Jsonl => Box::new(report::Jsonl::new(writer)),
+            Format::CodeClimate => Box::new(report::CodeClimate::new(writer).pretty(pretty)),
+            Format::Junit => Box::new(report::Junit::new(writer)),
+            Format::Gnu => Box::new(report::Gnu::new(writer)),
+            Format::Summary => Box::new(report::Summary::new(writer)),
+        }
+    }
+}
+
+#[derive(Clone, clap::Args)]
+struct CasingArgs {
+    /// Check that all labels use the same casing.
+    #[arg(long)]
+    consistent_casing: Option<Case>,
+    /// Whether a run of digits following a letter starts a new word when checking snake-like
+    /// casings, e.g., with `split` the label `foo2bar` is written as `foo_2bar` in snake case.
+    #[arg(long, value_enum, default_value_t = DigitBoundary::Keep)]
+    digit_boundary: DigitBoundary,
+    /// A word written in uppercase when checking camelCase, PascalCase, Train-Case, Sentence case,
+    /// and Title Case, e.g., with `ID` the label `userId` is written as `userID`. Can be given
+    /// multiple times.
+    #[arg(long, value_name = "WORD")]
+    acronym: Vec<String>,
+    /// A word written in lowercase when checking Title Case unless it's the first word, e.g.,
+    /// `of`. Can be given multiple times and replaces the default list: a, an, and, as, at, but,
+    /// by, for, in, nor, of, on, or, the, to.
+    #[arg(long, value_name = "WORD")]
+    small_word: Vec<String>,
+}
+
+impl CasingArgs {
+    fn casing(&self) -> Option<Casing> {
+        self.consistent_casing.map(|case| self.with_case(case))
+    }
+
+    /// The casing `case` with the options given on the command line.
+    fn with_case(&self, case: Case) -> Casing {
+        Casing {
+            case,
+            split_digits: self.digit_boundary == DigitBoundary::Split,
+            acronyms: Rc::new(self.acronym.iter().cloned().collect()),
+            small_words: (!self.small_word.is_empty())
+                .then(|| Rc::new(self.small_word.iter().cloned().collect())),
+        }
+    }
+}
+
+#[derive(Copy, Clone, clap::ValueEnum)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// The color choice for a stream, where `is_terminal` tells whether the stream is attached to
+    /// a terminal.
+    fn choice(self, is_terminal: bool) -> ColorChoice {
+        match self {
+            Color::Auto if is_terminal && !no_color_env() => ColorChoice::Auto,
+            Color::Auto | Color::Never => ColorChoice::Never,
+            Color::Always => ColorChoice::Always,
+        }
+    }
+
+    fn stdout(self) -> StandardStream {
+        StandardStream::stdout(self.choice(io::stdout().is_terminal()))
+    }
+
+    fn stderr(self) -> StandardStream {
+        StandardStream::stderr(self.choice(io::stderr().is_terminal()))
+    }
+}
+
+/// Whether colors are disabled with a non-empty `NO_COLOR` environment variable.
+fn no_color_env() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+#[derive(Copy, Clone, clap::ValueEnum)]
+enum CountSemanticsArg {
+    /// `CODESYNC(my-label, 3)` means there are 3 comments with label `my-label` in total.
+    Total,
+    /// `CODESYNC(my-label, 3)` means there are 3 comments with label `my-label` besides this one.
+    Others,
+}
+
+#[derive(Copy, Clone, clap::ValueEnum)]
+enum BinaryDetectionArg {
+    /// Skip files containing a NUL byte.
+    Quit,
+    /// Search files containing NUL bytes treating each one as a line terminator.
+    Convert,
+    /// Search all files as text.
+    None,
+}
+
+impl BinaryDetectionArg {
+    fn detection(self) -> BinaryDetection {
+        match self {
+            BinaryDetectionArg::Quit => BinaryDetection::Quit,
+            BinaryDetectionArg::Convert => BinaryDetection::Convert,
+            BinaryDetectionArg::None => BinaryDetection::None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, clap::ValueEnum)]
+enum CountUnitArg {
+    /// Every comment with the label.
+    Occurrence,
+    /// Every file with at least one comment with the label, so `CODESYNC(my-label, 3)` means the
+    /// label appears in 3 files, however many times in each.
+    File,
+}
+
+impl CountUnitArg {
+    fn unit(self) -> CountUnit {
+        match self {
+            CountUnitArg::Occurrence => CountUnit::Occurrence,
+            CountUnitArg::File => CountUnit::File,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum CountScope {
+    /// All comments in the project.
+    Global,
+    /// Comments under the same directory with its own `codesync.toml`, or under none. A
+    /// subdirectory with a configuration, even an empty one, starts a new scope.
+    Directory,
+}
+
+impl CountSemanticsArg {
+    fn semantics(self) -> CountSemantics {
+        match self {
+            CountSemanticsArg::Total => CountSemantics::Total,
+            CountSemanticsArg::Others => CountSemantics::Others,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum Anchor {
+    /// Only the first comment by path and position, with the rest as secondary locations, so the
+    /// anchor is stable across runs.
+    First,
+    /// Every comment.
+    All,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum DigitBoundary {
+    /// Digits start a new word, e.g., `FOO_BAR_3`.
+    Split,
+    /// Digits are part of the preceding word, e.g., `FOO_BAR3`.
+    Keep,
+}
+
+/// The casing labels are checked against.
+#[derive(Clone)]
+struct Casing {
+    case: Case,
+    /// Whether digits start a new word in snake-like cases. Camel-like cases ignore this.
+    split_digits: bool,
+    /// Words written in uppercase in camelCase, PascalCase, Sentence case, and Title Case. Other
+    /// cases ignore this.
+    acronyms: Rc<HashSet<String>>,
+    /// Words written in lowercase in Title Case, or `None` for
+    /// [`inflector::case::DEFAULT_SMALL_WORDS`]. Other cases ignore this.
+    small_words: Option<Rc<HashSet<String>>>,
+}
+
+impl Casing {
+    fn has_case(&self, s: &str) -> bool {
+        if self.split_digits || !self.acronyms.is_empty() || self.small_words.is_some() {
+            s == self.to_case(s)
+        } else {
+            self.case.has_case(s)
+        }
+    }
+
+    fn to_case(&self, s: &str) -> String {
+        let split = self.split_digits;
+        match self.case {
+            Case::Kebab => inflector::to_kebab_case_with_digits(s, split),
+            Case::ScreamingSnake => inflector::to_screaming_snake_case_with_digits(s, split),
+            Case::Snake => inflector::to_snake_case_with_digits(s, split),
+            Case::Cobol => inflector::to_cobol_case_with_digits(s, split),
+            Case::Camel => inflector::to_camel_case(s, &self.acronyms),
+            Case::Pascal => inflector::to_pascal_case_with_acronyms(s, &self.acronyms),
+            Case::Sentence => inflector::to_sentence_case_with_acronyms(s, &self.acronyms),
+            Case::Title => match &self.small_words {
+                Some(small_words) => inflector::to_title_case_with(s, &self.acronyms, small_words),
+                None if self.acronyms.is_empty() => self.case.to_case(s),
+                None => {
+                    let small_words = inflector::case::DEFAULT_SMALL_WORDS
+                        .iter()
+                        .map(|w| w.to_string())
+                        .collect();
+                    inflector::to_title_case_with(s, &self.acronyms, &small_words)
+                }
+            },
+            Case::Train => inflector::to_train_case_with_acronyms(s, &self.acronyms),
+        }
+    }
+}
+
+impl std::fmt::Display for Casing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.case)
+    }
 }
 
 #[derive(Copy, Clone, clap::ValueEnum)]
@@ -56,6 +805,12 @@ enum Case {
     Snake,
     #[value(name = "Train-Case", aliases(["train-case", "train"]))]
     Train,
+    #[value(name = "COBOL-CASE", aliases(["cobol-case", "cobol", "screaming-kebab-case", "screaming-kebab"]))]
+    Cobol,
+    #[value(name = "Sentence case", aliases(["sentence-case", "sentence"]))]
+    Sentence,
+    #[value(name = "Title Case", aliases(["title-case", "title"]))]
+    Title,
 }
 
 impl Case {
@@ -67,6 +822,9 @@ impl Case {
             Case::ScreamingSnake => inflector::is_screaming_snake_case(s),
             Case::Snake => inflector::is_snake_case(s),
             Case::Train => inflector::is_train_case(s),
+            Case::Cobol => inflector::is_cobol_case(s),
+            Case::Sentence => inflector::is_sentence_case(s),
+            Case::Title => inflector::is_title_case(s),
         }
     }
 
@@ -78,6 +836,9 @@ impl Case {
             Case::ScreamingSnake => inflector::to_screaming_snake_case(s),
             Case::Snake => inflector::to_snake_case(s),
             Case::Train => inflector::to_train_case(s),
+            Case::Cobol => inflector::to_cobol_case(s),
+            Case::Sentence => inflector::to_sentence_case(s),
+            Case::Title => inflector::to_title_case(s),
         }
     }
 
@@ -89,6 +850,9 @@ impl Case {
             Case::ScreamingSnake => "screaming snake",
             Case::Snake => "snake",
             Case::Train => "train",
+            Case::Cobol => "cobol",
+            Case::Sentence => "sentence",
+            Case::Title => "title",
         }
     }
 }
@@ -106,169 +870,1855 @@ struct ShowArgs {
 
 type FileId = usize;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+/// Exit code used when checks report errors.
+const EXIT_VIOLATIONS: i32 = 1;
+/// Exit code used when codesync itself fails, e.g., a file can't be read. This is the same code
+/// clap uses for usage errors.
+const EXIT_FAILURE: i32 = 2;
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli) {
+        eprintln!("error: {err}");
+        std::process::exit(EXIT_FAILURE);
+    }
+}
+
+fn run(mut cli: Cli) -> Result<(), Box<dyn Error>> {
+    if let Some(code) = &cli.explain {
+        let Some(explanation) = explain::explanation(code) else {
+            let codes: Vec<_> = explain::codes().map(|c| format!("`{c}`")).collect();
+            return Err(format!(
+                "unknown code `{code}`, expected one of {}",
+                codes.join(", ")
+            )
+            .into());
+        };
+        println!("{explanation}");
+        return Ok(());
+    }
+    let root = cli.root();
+    let Some(command) = cli.command.take() else {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand or `--explain` is required",
+            )
+            .exit();
+    };
+
+    let mut collect_opts = CollectOptions::new()
+        .root(&root)
+        .include_hidden(cli.no_default_ignores)
+        .no_ignore(cli.no_default_ignores)
+        .strict_keyword_boundary(cli.strict_keyword_boundary)
+        .strict_trailing_comma(cli.strict_trailing_comma)
+        .multiline(cli.multiline)
+        .bare_labels(cli.bare_labels)
+        .tag_spelling(cli.tag_spelling)
+        .comment_prefix(cli.comment_prefix.as_deref())
+        .max_depth(cli.max_depth)
+        .follow_symlinks(cli.follow_symlinks)
+        .binary_detection(cli.binary_detection.detection())
+        .max_matches_per_file((cli.max_matches_per_file > 0).then_some(cli.max_matches_per_file))
+        .threads(cli.threads)
+        .include(&cli.include)
+        .exclude(&cli.exclude)
+        .extensions(&cli.ext);
+    // With `check --stdin-content` the only file searched is the buffer read from stdin
+    let mut stdin_fs = None;
+    if let Args::Check(args) = &command {
+        if args.stdin_content {
+            let name = args
+                .stdin_name
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(STDIN_NAME));
+            let mut fs = MemoryFileSystem::new();
+            fs.insert(&name, io::read_to_string(io::stdin())?);
+            collect_opts = collect_opts.root(name);
+            stdin_fs = Some(fs);
+        }
+    }
+    let fs: &dyn FileSystem = match &stdin_fs {
+        Some(fs) => fs,
+        None => &OsFileSystem,
+    };
+    // The language server searches the workspace given by the editor
+    let lsp_opts = collect_opts.clone();
+    if let (Some(list), None) = (&cli.files_from, &stdin_fs) {
+        collect_opts = collect_opts.roots(read_file_list(list)?);
+    }
+    if cli.git_tracked && stdin_fs.is_none() {
+        let tracked = git::tracked_files(git_dir(&root))
+            .map_err(|err| format!("`--git-tracked` requires a git repository: {err}"))?;
+        collect_opts = collect_opts.only_files(tracked);
+    }
+
+    let collect = |include_ignored: bool| -> Result<Matches, Box<dyn Error>> {
+        let mut opts = collect_opts.clone();
+        for (glob, keyword) in Config::load(&root)?.keywords {
+            opts = opts.keyword_for(glob, keyword);
+        }
+        let mut matches = Matches::collect_from(fs, &opts)?;
+        if cli.profile {
+            print_profile(matches.profile());
+        }
+        if cli.report_binary {
+            for (path, offset) in matches.binary_files() {
+                eprintln!("{}: binary data at byte {offset}", path.display());
+            }
+        }
+        for path in matches.truncated_files() {
+            eprintln!(
+                "warning: {}: stopped collecting matches after {}, see `--max-matches-per-file`",
+                path.display(),
+                cli.max_matches_per_file
+            );
+        }
+        if !include_ignored && !cli.ignore_label.is_empty() {
+            matches.retain_labels(|label| !cli.ignore_label.iter().any(|re| re.is_match(label)));
+        }
+        Ok(matches)
+    };
 
-    let matches = Matches::collect()?;
-    match args {
+    match command {
         Args::Check(args) => {
-            Checker::new(args).check(&matches)?;
+            let configs = ScopedConfigs::new(&root)?;
+            let changed = args
+                .since
+                .as_deref()
+                .map(|since| ChangedLines::since(git_dir(&root), since))
+                .transpose()?;
+            let matches = collect(false)?;
+            Checker::new(args, configs, changed, cli.color)?
+                .relative_uris(&root)
+                .profile(cli.profile)
+                .check(&matches)?;
+        }
+        Args::Show {
+            label,
+            filter,
+            counts,
+            count_semantics,
+        } => {
+            let vars = Config::load(&root)?.vars;
+            let matches = collect(filter.all)?;
+            let mut db = FilesDB::new();
+            db.add_sources(&matches);
+            let mut emitter = Emitter::new(false, Format::Human, false, cli.color);
+            let groups = matches
+                .group_by_label()
+                .into_iter()
+                .filter(|(l, _)| match &label {
+                    Some(label) => l == label,
+                    None => filter.includes(l),
+                });
+            for (label, comments) in groups {
+                if counts {
+                    let expected = codesync::expected_count(
+                        &comments,
+                        &vars,
+                        CountOptions {
+                            semantics: count_semantics.semantics(),
+                            ..CountOptions::default()
+                        },
+                    );
+                    emitter.emit(&db, count_header(label, expected, comments.len()))?;
+                }
+                let locations = comments
+                    .iter()
+                    .map(|c| db.location(c.file(), c.byte_offset()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for (i, comment) in comments.iter().enumerate() {
+                    let related: Vec<_> = locations
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .map(|(_, loc)| format!("{}:{}", loc.file, loc.line))
+                        .collect();
+                    let mut diagnostic = Diagnostic::note()
+                        .with_message(format!(
+                            "{}: {} with label `{label}`",
+                            locations[i],
+                            if comment.is_doc_comment() {
+                                "doc comment"
+                            } else {
+                                "comment"
+                            }
+                        ))
+                        .with_labels(vec![db.label(comment.file(), comment.span())?]);
+                    if !related.is_empty() {
+                        diagnostic = diagnostic.with_notes(vec![format!(
+                            "{} related: {}",
+                            related.len(),
+                            related.join(", ")
+                        )]);
+                    }
+                    emitter.emit(&db, diagnostic)?;
+                }
+            }
         }
-        Args::Show { label } => {
+        Args::List {
+            filter,
+            label_pattern,
+            min_count,
+            max_count,
+            counts,
+            verbose,
+        } => {
+            let matches = collect(filter.all)?;
             let mut db = FilesDB::new();
-            let mut emitter = Emitter::new(false);
-            let comments = matches.comments().filter(|c| &c.label() == &label);
-            let diagnostic = Diagnostic::note()
-                .with_message(format!("showing comments for label `{label}`"))
-                .with_labels(db.labels(comments)?);
-            emitter.emit(&db, diagnostic)?;
-        }
-        Args::List => {
-            let stdout = &mut StandardStream::stdout(ColorChoice::Auto);
-            stdout.set_color(ColorSpec::new().set_bold(true))?;
-            for (label, _) in matches.group_by_label() {
-                write!(stdout, "{label}\n")?;
-            }
-            stdout.reset()?;
+            let stdout = &mut cli.color.stdout();
+            for (label, comments) in matches.group_by_label() {
+                let found = comments.len();
+                if !filter.includes(label)
+                    || label_pattern.as_ref().is_some_and(|re| !re.is_match(label))
+                    || min_count.is_some_and(|min| found < min)
+                    || max_count.is_some_and(|max| found > max)
+                {
+                    continue;
+                }
+                stdout.set_color(ColorSpec::new().set_bold(true))?;
+                if counts {
+                    write!(stdout, "{label}\t{found}")?;
+                } else {
+                    write!(stdout, "{label}")?;
+                }
+                stdout.reset()?;
+                writeln!(stdout)?;
+                if verbose {
+                    for comment in &comments {
+                        let location = db.location(comment.file(), comment.byte_offset())?;
+                        writeln!(stdout, "    {location}")?;
+                    }
+                }
+            }
             writeln!(stdout)?;
         }
+        Args::Fix {
+            malformed,
+            spacing,
+            yes,
+            only_clean,
+            only_dirty,
+        } => {
+            let filter = if only_clean || only_dirty {
+                match git::dirty_files(git_dir(&root)) {
+                    Ok(dirty) => Some(DirtyFilter::new(dirty, only_dirty)),
+                    Err(err) => {
+                        let flag = if only_clean {
+                            "only-clean"
+                        } else {
+                            "only-dirty"
+                        };
+                        let message = format!("`--{flag}` requires a git repository: {err}");
+                        eprintln!("warning: fixing all files, {message}");
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let matches = collect(true)?;
+            fix_comments(&matches, malformed, spacing, yes, filter.as_ref())?;
+        }
+        Args::Summary {
+            count_semantics,
+            json,
+        } => {
+            let config = Config::load(&root)?;
+            let matches = collect(false)?;
+            print_summary(&matches, &config, count_semantics.semantics(), json)?;
+        }
+        Args::Stats {
+            count_distribution,
+            hash,
+            json,
+        } => {
+            let matches = collect(false)?;
+            if hash {
+                let hash = format!("{:016x}", labels_hash(&matches, &Config::load(&root)?.vars));
+                if json {
+                    println!("{}", json::Value::object([("hash", hash.into())]));
+                } else {
+                    println!("{hash}");
+                }
+            } else {
+                print_stats(&matches, count_distribution, json)?;
+            }
+        }
+        Args::Dump => {
+            let matches = collect(true)?;
+            dump_matches(&matches, &mut io::stdout().lock())?;
+        }
+        Args::ValidateConfig => {
+            let mut errors: Vec<_> = Config::validate(&root)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            if errors.is_empty() {
+                let config = Config::load(&root)?;
+                let mut codes: Vec<_> = config.severity.keys().collect();
+                codes.sort();
+                errors.extend(codes.into_iter().filter_map(|code| {
+                    check_code(code)
+                        .err()
+                        .map(|err| format!("in `[severity]` of `{CONFIG_FILE}`: {err}"))
+                }));
+                let casing = |config: &Config| config.consistent_casing.as_deref().map(config_case);
+                errors.extend(casing(&config).and_then(Result::err));
+                for dir in config::nested_config_dirs(&root) {
+                    let nested = config::load_nested(&dir)?;
+                    if let Some(Err(err)) = casing(&nested) {
+                        errors.push(format!("in `{}`: {err}", dir.display()));
+                    }
+                }
+            }
+            for err in &errors {
+                eprintln!("error: {err}");
+            }
+            if !errors.is_empty() {
+                std::process::exit(EXIT_VIOLATIONS);
+            }
+        }
+        Args::PrintConfig { check, json } => {
+            let config = Config::load(&root)?;
+            let settings = effective_config(&cli, &root, &check, &config)?;
+            if json {
+                println!("{settings}");
+            } else {
+                print!("{}", to_toml(&settings));
+            }
+        }
+        Args::Parse { text, json } => {
+            let text = match text {
+                Some(text) => text,
+                None => io::read_to_string(io::stdin())?,
+            };
+            print_parsed(&text, json)?;
+        }
+        Args::InstallHook { force, print } => {
+            if print {
+                print!("{PRE_COMMIT_HOOK}");
+            } else {
+                install_hook(force)?;
+            }
+        }
+        Args::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "codesync", &mut io::stdout());
+        }
+        Args::JsonSchema => {
+            println!("{}", report::Jsonl::schema(explain::codes()));
+        }
+        Args::Lsp(args) => {
+            lsp::run(args, lsp_opts, cli.ignore_label.clone())?;
+        }
     }
 
     Ok(())
 }
 
-struct Emitter {
-    writer: StandardStream,
-    config: codespan_reporting::term::Config,
-    has_errors: bool,
+/// The directory git commands run in: the searched directory, or the directory of a searched file.
+fn git_dir(root: &Path) -> &Path {
+    let dir = match root.parent() {
+        Some(parent) if root.is_file() => parent,
+        _ => root,
+    };
+    if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    }
 }
 
-impl Emitter {
-    fn new(stderr: bool) -> Self {
-        let writer = if stderr {
-            StandardStream::stderr(ColorChoice::Auto)
+/// Which files `fix` skips with `--only-clean` or `--only-dirty`.
+struct DirtyFilter {
+    /// Canonical paths of the files with uncommitted changes
+    dirty: HashSet<PathBuf>,
+    /// Whether to skip clean files instead of dirty ones
+    only_dirty: bool,
+}
+
+impl DirtyFilter {
+    fn new(dirty: impl IntoIterator<Item = PathBuf>, only_dirty: bool) -> Self {
+        let dirty = dirty
+            .into_iter()
+            .filter_map(|path| std::fs::canonicalize(path).ok())
+            .collect();
+        Self { dirty, only_dirty }
+    }
+
+    /// Why `path` is skipped, or `None` if it isn't.
+    fn skip_reason(&self, path: &Path) -> Option<&'static str> {
+        let dirty = std::fs::canonicalize(path).is_ok_and(|path| self.dirty.contains(&path));
+        match (dirty, self.only_dirty) {
+            (true, false) => Some("has uncommitted changes"),
+            (false, true) => Some("has no uncommitted changes"),
+            _ => None,
+        }
+    }
+}
+
+/// Suggest fixes for invalid matches and suspicious labels if `malformed` is set, and for comments
+/// without canonical spacing if `spacing` is set. Prompt for each one unless `yes` is set and write
+/// the accepted ones back to their files. Files skipped by `filter`, if any, are listed instead.
+fn fix_comments(
+    matches: &Matches,
+    malformed: bool,
+    spacing: bool,
+    yes: bool,
+    filter: Option<&DirtyFilter>,
+) -> Result<(), Box<dyn Error>> {
+    let sources: HashMap<&Path, Arc<str>> = matches.sources().collect();
+    let mut suggestions = vec![];
+    if malformed {
+        for m in matches.invalid_matches() {
+            if let Some(fix) = fix::suggest_for_invalid(&sources[m.file()], &m) {
+                suggestions.push((m.file(), fix));
+            }
+        }
+        for comment in matches.comments() {
+            if let Some(fix) = fix::suggest_for_comment(comment) {
+                suggestions.push((comment.file(), fix));
+            }
+        }
+    }
+    if spacing {
+        for comment in matches.comments() {
+            // A comment with a suspicious label is left alone, since its arguments may change.
+            if malformed && fix::suggest_for_comment(comment).is_some() {
+                continue;
+            }
+            if let Some(fix) = fix::suggest_spacing(&sources[comment.file()], comment) {
+                suggestions.push((comment.file(), fix));
+            }
+        }
+    }
+    if let Some(filter) = filter {
+        let mut skipped = BTreeMap::new();
+        suggestions.retain(|(path, _)| match filter.skip_reason(path) {
+            Some(reason) => {
+                skipped.insert(*path, reason);
+                false
+            }
+            None => true,
+        });
+        for (path, reason) in skipped {
+            println!("skipped {}: {reason}", path.display());
+        }
+    }
+
+    let mut db = FilesDB::new();
+    db.add_sources(matches);
+    let mut stdin = io::stdin().lock();
+    let mut accepted: HashMap<&Path, Vec<fix::Fix>> = HashMap::new();
+    for (path, mut fix) in suggestions {
+        let source = &sources[path];
+        let line = line_around(source, fix.span.clone());
+        let location = db.location(path, fix.span.start)?;
+        let fixed = fix::apply(line.text, &mut [shift(&fix, line.start)]);
+        println!(
+            "{location}:
+  - {}
+  + {fixed}",
+            line.text
+        );
+        if yes {
+            if !fix.confident {
+                println!("skipped, not confident enough to apply without prompting");
+                continue;
+            }
         } else {
-            StandardStream::stdout(ColorChoice::Auto)
-        };
-        Self {
-            writer,
-            config: codespan_reporting::term::Config::default(),
-            has_errors: false,
+            match prompt(&mut stdin, "apply fix? [y/n/e(dit)] ")?.as_str() {
+                "y" | "yes" => {}
+                "e" | "edit" => {
+                    fix.span = line.start..line.start + line.text.len();
+                    // Keep the indentation of the line as typed
+                    fix.replacement = prompt_line(&mut stdin, "replace line with: ")?;
+                }
+                _ => continue,
+            }
+        }
+        let fixes = accepted.entry(path).or_default();
+        // Fixes of comments on the same line overlap once one of them is edited
+        if fixes.iter().any(|other| fix::overlaps(other, &fix)) {
+            println!("skipped, overlaps a fix already accepted");
+            continue;
         }
+        fixes.push(fix);
     }
 
-    fn abort_if_errors(&self) {
-        if self.has_errors {
-            std::process::exit(1);
+    let mut count = 0;
+    for (path, mut fixes) in accepted {
+        if fixes.is_empty() {
+            continue;
         }
+        let source = &sources[path];
+        if let Some(reason) = unwritable_reason(source, &std::fs::read(path)?) {
+            println!("skipped {}: {reason}", path.display());
+            continue;
+        }
+        count += fixes.len();
+        std::fs::write(path, fix::apply(source, &mut fixes))?;
     }
+    println!(
+        "applied {count} {}",
+        if count == 1 { "fix" } else { "fixes" }
+    );
+    Ok(())
+}
 
-    fn emit(
-        &mut self,
-        db: &FilesDB,
-        diagnostic: Diagnostic<FileId>,
-    ) -> Result<(), codespan_reporting::files::Error> {
-        self.has_errors = true;
-        term::emit(
-            &mut self.writer.lock(),
-            &self.config,
-            &db.files,
-            &diagnostic,
-        )
+/// Why fixes computed on `source`, the contents of a file as searched, can't be written over the
+/// file, whose contents are now `on_disk`, if they can't. The contents searched replace invalid
+/// UTF-8, so writing them back would change bytes no fix touches.
+fn unwritable_reason(source: &str, on_disk: &[u8]) -> Option<&'static str> {
+    if on_disk == source.as_bytes() {
+        None
+    } else if std::str::from_utf8(on_disk).is_err() {
+        Some("not valid UTF-8")
+    } else {
+        Some("changed since it was searched")
     }
 }
 
-struct Checker {
-    args: CheckArgs,
-    db: FilesDB,
-    emitter: Emitter,
+/// The line containing `span` and the offset where it starts.
+struct Line<'a> {
+    text: &'a str,
+    start: usize,
 }
 
-impl Checker {
-    fn new(args: CheckArgs) -> Self {
-        Self {
-            args,
-            db: FilesDB::new(),
-            emitter: Emitter::new(true),
-        }
+fn line_around(source: &str, span: Range<usize>) -> Line<'_> {
+    let start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[span.end..]
+        .find('\n')
+        .map_or(source.len(), |i| span.end + i);
+    Line {
+        text: &source[start..end],
+        start,
     }
+}
 
-    fn check(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
-        self.report_invalid_matches(&matches)?;
-        self.abort_if_errors();
+/// `fix` with its span relative to `offset`.
+fn shift(fix: &fix::Fix, offset: usize) -> fix::Fix {
+    fix::Fix {
+        span: fix.span.start - offset..fix.span.end - offset,
+        ..fix.clone()
+    }
+}
 
-        for (label, comments) in matches.group_by_label() {
-            self.report_incorrect_counts(label, &comments)?;
-        }
-        self.abort_if_errors();
+/// Ask for an answer, ignoring surrounding whitespace.
+fn prompt(stdin: &mut impl io::BufRead, message: &str) -> io::Result<String> {
+    Ok(prompt_line(stdin, message)?.trim().to_string())
+}
 
-        self.report_inconsistent_casing(matches)?;
-        self.abort_if_errors();
+/// Ask for a line of text keeping its whitespace, but not the line terminator.
+fn prompt_line(stdin: &mut impl io::BufRead, message: &str) -> io::Result<String> {
+    print!("{message}");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    answer.truncate(answer.trim_end_matches(['\n', '\r']).len());
+    Ok(answer)
+}
 
-        self.report_label_regex_mismatch(matches)?;
+/// Line identifying hooks written by `install-hook`, so they can be replaced without `--force`.
+const HOOK_MARKER: &str = "# Installed by `codesync install-hook`";
 
-        self.report_no_extra_whitespace(matches)?;
-        self.abort_if_errors();
+/// The pre-commit hook written by `install-hook`.
+const PRE_COMMIT_HOOK: &str = "\
+#!/bin/sh
+# Installed by `codesync install-hook`. Delete this file to uninstall.
+exec codesync check --git-tracked
+";
 
+/// Write [`PRE_COMMIT_HOOK`] as the pre-commit hook of the repository in the current directory.
+fn install_hook(force: bool) -> Result<(), Box<dyn Error>> {
+    let path = git::hook_path("pre-commit")?;
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(contents) => Some(contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(format!("failed to read `{}`: {err}", path.display()).into()),
+    };
+    if existing.as_deref() == Some(PRE_COMMIT_HOOK) {
+        println!("pre-commit hook already installed at `{}`", path.display());
+        return Ok(());
+    }
+    if !can_replace_hook(existing.as_deref(), force) {
+        return Err(format!(
+            "`{}` already exists, use `--force` to overwrite it or `--print` to add the check to it",
+            path.display()
+        )
+        .into());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, PRE_COMMIT_HOOK)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    println!("installed pre-commit hook at `{}`", path.display());
+    Ok(())
+}
+
+/// Whether a hook with contents `existing`, if any, may be overwritten: it doesn't exist, was
+/// written by `install-hook`, or `force` is given. A hook counts as written by `install-hook` only
+/// if it's [`PRE_COMMIT_HOOK`] or, ignoring the shebang and blank lines, consists of exactly the
+/// marker line and our `exec` line. Hooks that merely contain the marker, e.g., because the
+/// output of `--print` was pasted into them, may have other commands and aren't replaced.
+fn can_replace_hook(existing: Option<&str>, force: bool) -> bool {
+    let Some(hook) = existing else {
+        return true;
+    };
+    if force || hook == PRE_COMMIT_HOOK {
+        return true;
+    }
+    let exec = PRE_COMMIT_HOOK
+        .lines()
+        .find(|line| line.starts_with("exec "));
+    let mut lines = hook
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("#!"));
+    matches!(
+        (lines.next(), lines.next(), lines.next()),
+        (Some(marker), Some(line), None) if marker.starts_with(HOOK_MARKER) && Some(line) == exec
+    )
+}
+
+/// Read the paths listed one per line in `list`, or in stdin if `list` is `-`. Empty lines are
+/// skipped.
+fn read_file_list(list: &Path) -> Result<Vec<PathBuf>, String> {
+    let contents = if list == Path::new("-") {
+        io::read_to_string(io::stdin())
+    } else {
+        std::fs::read_to_string(list)
+    };
+    let contents =
+        contents.map_err(|err| format!("failed to read file list `{}`: {err}", list.display()))?;
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// The header printed by `show --counts` before the comments with `label`, a warning if the
+/// number of comments found doesn't match the expected one.
+fn count_header(label: &str, expected: Option<ExpectedCount>, found: usize) -> Diagnostic<FileId> {
+    match expected {
+        Some(expected) => {
+            let header = if expected.total as usize == found {
+                Diagnostic::note()
+            } else {
+                Diagnostic::warning()
+            };
+            let default = if expected.explicit { "" } else { " (default)" };
+            header.with_message(format!(
+                "label `{label}`: expected {}{default}, found {found}",
+                expected.total
+            ))
+        }
+        None => Diagnostic::warning().with_message(format!(
+            "label `{label}`: comments specify different counts, found {found}"
+        )),
+    }
+}
+
+/// Print a row for every label with its expected number of comments, the number found, and
+/// whether they match.
+fn print_summary(
+    matches: &Matches,
+    config: &Config,
+    semantics: CountSemantics,
+    json: bool,
+) -> io::Result<()> {
+    let rows: Vec<_> = matches
+        .group_by_label()
+        .into_iter()
+        .map(|(label, comments)| {
+            let opts = CountOptions {
+                semantics,
+                ..CountOptions::default()
+            };
+            let expected = codesync::expected_count(&comments, &config.vars, opts);
+            let status = match expected {
+                Some(expected) if expected.total as usize == comments.len() => "ok",
+                Some(_) => "mismatch",
+                None => "conflicting",
+            };
+            (label, expected, comments.len(), status)
+        })
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+    if json {
+        let rows = rows
+            .into_iter()
+            .map(|(label, expected, found, status)| {
+                json::Value::object([
+                    ("label", label.into()),
+                    (
+                        "expected",
+                        expected.map_or(json::Value::Null, |e| e.total.into()),
+                    ),
+                    ("explicit", expected.is_some_and(|e| e.explicit).into()),
+                    ("found", found.into()),
+                    ("status", status.into()),
+                ])
+            })
+            .collect();
+        return writeln!(stdout, "{}", json::Value::Array(rows));
+    }
+
+    let expected: Vec<_> = rows
+        .iter()
+        .map(|(_, expected, _, _)| match expected {
+            Some(e) if e.explicit => e.total.to_string(),
+            Some(e) => format!("{} (default)", e.total),
+            None => "-".to_string(),
+        })
+        .collect();
+    let label_width = rows.iter().map(|r| r.0.len()).chain([5]).max().unwrap();
+    let expected_width = expected.iter().map(String::len).chain([8]).max().unwrap();
+    writeln!(
+        stdout,
+        "{:label_width$}  {:expected_width$}  {:>5}  STATUS",
+        "LABEL", "EXPECTED", "FOUND"
+    )?;
+    for ((label, _, found, status), expected) in rows.iter().zip(&expected) {
+        writeln!(
+            stdout,
+            "{label:label_width$}  {expected:expected_width$}  {found:>5}  {status}"
+        )?;
+    }
+    Ok(())
+}
+
+/// Print every match in `text` for `parse`.
+fn print_parsed(text: &str, json: bool) -> io::Result<()> {
+    fn span(span: Range<usize>) -> json::Value {
+        json::Value::array([span.start.into(), span.end.into()])
+    }
+
+    let matches = Matches::from_buffer(Path::new("<input>"), text);
+    let mut stdout = io::stdout().lock();
+    if json {
+        let entries = matches.entries().map(|entry| match entry {
+            codesync::Entry::Valid(comment) => json::Value::object([
+                ("valid", true.into()),
+                ("span", span(comment.span())),
+                ("label", comment.label().into()),
+                ("label_span", span(comment.label_arg().span())),
+                (
+                    "count",
+                    comment.count_arg().map(|c| c.value().to_string()).into(),
+                ),
+                (
+                    "count_span",
+                    comment.count_arg().map(|c| span(c.span())).into(),
+                ),
+                ("note", comment.note_arg().map(|n| n.value().clone()).into()),
+                (
+                    "note_span",
+                    comment.note_arg().map(|n| span(n.span())).into(),
+                ),
+            ]),
+            codesync::Entry::Invalid(m) => json::Value::object([
+                ("valid", false.into()),
+                ("span", span(m.span())),
+                ("error", m.error_kind().into()),
+                ("message", m.error.to_string().into()),
+                ("error_span", m.error.span().map(span).into()),
+            ]),
+        });
+        return writeln!(stdout, "{}", json::Value::array(entries));
+    }
+
+    let mut found = false;
+    for entry in matches.entries() {
+        found = true;
+        match entry {
+            codesync::Entry::Valid(comment) => {
+                let s = comment.span();
+                writeln!(stdout, "{:?}  {}", s, &text[s.clone()])?;
+                let label = comment.label_arg();
+                writeln!(
+                    stdout,
+                    "  {:6}  {:?}  {}",
+                    "label",
+                    label.span(),
+                    label.value()
+                )?;
+                if let Some(count) = comment.count_arg() {
+                    writeln!(
+                        stdout,
+                        "  {:6}  {:?}  {}",
+                        "count",
+                        count.span(),
+                        count.value()
+                    )?;
+                }
+                if let Some(note) = comment.note_arg() {
+                    writeln!(
+                        stdout,
+                        "  {:6}  {:?}  {}",
+                        "note",
+                        note.span(),
+                        note.value()
+                    )?;
+                }
+            }
+            codesync::Entry::Invalid(m) => {
+                writeln!(stdout, "{:?}  {}", m.span(), m.snippet())?;
+                let error_span = m.error.span().unwrap_or_else(|| m.span());
+                writeln!(
+                    stdout,
+                    "  {:6}  {:?}  {}: {}",
+                    "error",
+                    error_span,
+                    m.error_kind(),
+                    m.error
+                )?;
+            }
+        }
+    }
+    if !found {
+        writeln!(stdout, "no matches found")?;
+    }
+    Ok(())
+}
+
+/// Print totals over all matches or, with `count_distribution`, the number of labels for each
+/// number of comments.
+fn print_stats(matches: &Matches, count_distribution: bool, json: bool) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    if count_distribution {
+        let distribution = count_distribution_of(matches);
+        if json {
+            let fields = distribution
+                .into_iter()
+                .map(|(count, labels)| (count.to_string(), labels.into()));
+            return writeln!(stdout, "{}", json::Value::object(fields));
+        }
+        writeln!(stdout, "{:>8}  LABELS", "COMMENTS")?;
+        for (count, labels) in distribution {
+            writeln!(stdout, "{count:>8}  {labels}")?;
+        }
+        return Ok(());
+    }
+
+    let stats = [
+        ("files", matches.sources().count()),
+        ("labels", matches.group_by_label().len()),
+        ("comments", matches.comments().count()),
+        ("invalid", matches.invalid_matches().count()),
+    ];
+    if json {
+        let fields = stats.into_iter().map(|(name, n)| (name, n.into()));
+        return writeln!(stdout, "{}", json::Value::object(fields));
+    }
+    for (name, n) in stats {
+        writeln!(stdout, "{name:8}  {n}")?;
+    }
+    Ok(())
+}
+
+/// The number of diagnostics of each code for `--rule-stats`, most frequent first and then sorted
+/// by code.
+fn rule_stats(codes: &HashMap<String, usize>) -> Vec<(&str, usize)> {
+    let mut stats: Vec<_> = codes.iter().map(|(code, n)| (code.as_str(), *n)).collect();
+    stats.sort_by(|(a, a_n), (b, b_n)| b_n.cmp(a_n).then_with(|| a.cmp(b)));
+    stats
+}
+
+/// Write every match as a tab-separated row for `dump`.
+fn dump_matches(matches: &Matches, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "file\tbyte_start\tbyte_end\tlabel\tcount\tvalid")?;
+    for entry in matches.entries() {
+        let span = entry.span();
+        let (label, count) = match &entry {
+            codesync::Entry::Valid(comment) => (
+                comment.label().to_string(),
+                comment
+                    .count_arg()
+                    .map_or(String::new(), |c| c.value().to_string()),
+            ),
+            codesync::Entry::Invalid(_) => (String::new(), String::new()),
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            escape_tsv(&codesync::normalized_path(entry.file())),
+            span.start,
+            span.end,
+            escape_tsv(&label),
+            escape_tsv(&count),
+            matches!(entry, codesync::Entry::Valid(_)),
+        )?;
+    }
+    Ok(())
+}
+
+/// `value` with characters that would break a tab-separated row escaped with a backslash.
+fn escape_tsv(value: &str) -> Cow<'_, str> {
+    if !value.contains(['\t', '\n', '\r', '\\']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len() + 1);
+    for c in value.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Whether a count mismatch is off by at most `tolerance` comments. Conflicting counts are never
+/// tolerated.
+fn within_tolerance(check: &CountCheck, tolerance: usize) -> bool {
+    match *check {
+        CountCheck::Mismatch { expected, found } => found.abs_diff(expected as usize) <= tolerance,
+        CountCheck::Ok | CountCheck::Conflicting => false,
+    }
+}
+
+/// How the expected count of `comments` is derived, for `--trace-label`. `default` is the count
+/// from `--count-from-file`, if any.
+fn count_derivation(
+    comments: &[Comment],
+    vars: &HashMap<String, u16>,
+    semantics: CountSemantics,
+    default: Option<u16>,
+) -> String {
+    let mut explicit: Vec<_> = comments
+        .iter()
+        .filter_map(|c| c.count_arg()?.value().resolve(vars))
+        .collect();
+    explicit.sort();
+    explicit.dedup();
+    match (&explicit[..], default) {
+        ([], Some(default)) => format!("expected {default}, from `--count-from-file`"),
+        ([], None) => format!("expected {}, the default", codesync::DEFAULT_COUNT),
+        ([count], _) => match semantics {
+            CountSemantics::Total => format!("expected {count}, from an explicit count"),
+            CountSemantics::Others => format!(
+                "expected {}, from an explicit count of {count} other comments",
+                count.saturating_add(1)
+            ),
+        },
+        (counts, _) => {
+            let counts: Vec<_> = counts.iter().map(u16::to_string).collect();
+            format!(
+                "no expected count, explicit counts conflict: {}",
+                counts.join(", ")
+            )
+        }
+    }
+}
+
+/// The note suggesting that comments share `label` by mistake, listing [`similar_labels`].
+fn collision_hint(label: &str, labels: &[&str]) -> String {
+    let message =
+        "such a large difference may mean that a typo made unrelated comments use this label";
+    let similar = similar_labels(label, labels);
+    if similar.is_empty() {
+        return message.to_string();
+    }
+    let similar: Vec<_> = similar
+        .iter()
+        .map(|l| format!("`{}`", truncate_label(l)))
+        .collect();
+    format!("{message}, similar labels: {}", similar.join(", "))
+}
+
+/// The labels in `labels` other than `label` at most [`MAX_SIMILAR_LABEL_DISTANCE`] edits away
+/// from it, closest first.
+fn similar_labels<'a>(label: &str, labels: &[&'a str]) -> Vec<&'a str> {
+    let mut similar: Vec<_> = labels
+        .iter()
+        .filter(|&&other| other != label)
+        .map(|&other| (edit_distance(label, other), other))
+        .filter(|&(distance, _)| distance <= MAX_SIMILAR_LABEL_DISTANCE)
+        .collect();
+    similar.sort();
+    similar.dedup();
+    similar.into_iter().map(|(_, other)| other).collect()
+}
+
+/// Maximum [`edit_distance`] between labels considered similar by [`similar_labels`].
+const MAX_SIMILAR_LABEL_DISTANCE: usize = 2;
+
+/// The Levenshtein distance between `a` and `b`, i.e., the minimum number of characters inserted,
+/// removed, or replaced to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefix of `a` processed so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Parse the contents of a `--count-from-file` file: a JSON object mapping labels to counts or a
+/// `label=count` line per label. Empty lines and lines starting with `#` are skipped.
+fn parse_count_file(contents: &str) -> Result<HashMap<String, u16>, String> {
+    if contents.trim_start().starts_with('{') {
+        let value = json::Value::parse(contents).map_err(|err| err.to_string())?;
+        let json::Value::Object(fields) = value else {
+            unreachable!("a document starting with `{{` is an object");
+        };
+        return fields
+            .into_iter()
+            .map(|(label, count)| match count {
+                json::Value::Number(n) if u16::try_from(n).is_ok() => Ok((label, n as u16)),
+                _ => Err(format!(
+                    "invalid count {count} for label `{label}`, expected an integer between 0 and {}",
+                    u16::MAX
+                )),
+            })
+            .collect();
+    }
+    let mut counts = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((label, count)) = line.rsplit_once('=') else {
+            return Err(format!(
+                "line {}: expected `label=count`, found `{line}`",
+                i + 1
+            ));
+        };
+        let count = count.trim().parse().map_err(|_| {
+            format!(
+                "line {}: invalid count `{}`, expected an integer between 0 and {}",
+                i + 1,
+                count.trim(),
+                u16::MAX
+            )
+        })?;
+        counts.insert(label.trim().to_string(), count);
+    }
+    Ok(counts)
+}
+
+/// Whether a count mismatch is off by less than `threshold` comments and shouldn't be reported.
+/// Conflicting counts are always reported.
+fn below_threshold(check: &CountCheck, threshold: usize) -> bool {
+    match *check {
+        CountCheck::Mismatch { expected, found } => found.abs_diff(expected as usize) < threshold,
+        CountCheck::Ok | CountCheck::Conflicting => false,
+    }
+}
+
+/// Print the `--profile` breakdown of collecting matches to stderr.
+fn print_profile(profile: &CollectProfile) {
+    eprintln!("{:8}  {:?}", "walk", profile.walk);
+    eprintln!("{:8}  {:?}", "search", profile.search);
+    eprintln!("{:8}  {:?}", "parse", profile.parse);
+    eprintln!("{:8}  {}", "files", profile.files);
+    eprintln!("{:8}  {}", "bytes", profile.bytes);
+}
+
+/// Parse the setting `consistent_casing` of a configuration.
+fn config_case(name: &str) -> Result<Case, String> {
+    Case::from_str(name, true)
+        .map_err(|_| format!("invalid `consistent_casing` in `{CONFIG_FILE}`: `{name}`"))
+}
+
+/// The settings used by `check` in the root directory, with settings in `config` taking precedence
+/// over the command line like when checking. Unset optional settings are `null`.
+fn effective_config(
+    cli: &Cli,
+    root: &Path,
+    args: &CheckArgs,
+    config: &Config,
+) -> Result<json::Value, Box<dyn Error>> {
+    fn name(value: impl clap::ValueEnum) -> Option<String> {
+        value.to_possible_value().map(|v| v.get_name().to_string())
+    }
+    fn path(path: &Path) -> json::Value {
+        path.display().to_string().into()
+    }
+    fn strings<T: ToString>(values: &[T]) -> json::Value {
+        json::Value::array(values.iter().map(|v| v.to_string().into()))
+    }
+
+    let casing = match &config.consistent_casing {
+        Some(name) => Some(config_case(name)?),
+        None => args.casing.consistent_casing,
+    };
+    let label_pattern = config
+        .label_pattern
+        .as_ref()
+        .or(args.label_pattern.as_ref());
+    let mut vars: Vec<_> = config.vars.iter().collect();
+    vars.sort();
+    let mut aliases: Vec<_> = config.aliases.iter().collect();
+    aliases.sort();
+    let levels = lint_levels(args, config)?;
+    let keywords = config
+        .keywords
+        .iter()
+        .map(|(glob, keyword)| (glob.as_str(), keyword.as_str().into()));
+
+    Ok(json::Value::object([
+        ("path", path(root)),
+        ("files_from", cli.files_from.as_deref().map(path).into()),
+        ("include", strings(&cli.include)),
+        ("exclude", strings(&cli.exclude)),
+        ("ext", strings(&cli.ext)),
+        ("no_default_ignores", cli.no_default_ignores.into()),
+        ("max_depth", cli.max_depth.into()),
+        ("follow_symlinks", cli.follow_symlinks.into()),
+        ("threads", cli.threads.into()),
+        (
+            "strict_keyword_boundary",
+            cli.strict_keyword_boundary.into(),
+        ),
+        ("strict_trailing_comma", cli.strict_trailing_comma.into()),
+        ("multiline", cli.multiline.into()),
+        ("bare_labels", cli.bare_labels.into()),
+        ("tag_spelling", cli.tag_spelling.into()),
+        ("comment_prefix", cli.comment_prefix.clone().into()),
+        ("binary_detection", name(cli.binary_detection).into()),
+        ("max_matches_per_file", cli.max_matches_per_file.into()),
+        ("ignore_label", strings(&cli.ignore_label)),
+        ("consistent_casing", casing.and_then(name).into()),
+        ("digit_boundary", name(args.casing.digit_boundary).into()),
+        ("acronym", strings(&args.casing.acronym)),
+        (
+            "small_word",
+            strings(
+                config
+                    .small_words
+                    .as_ref()
+                    .unwrap_or(&args.casing.small_word),
+            ),
+        ),
+        ("no_extra_whitespace", args.no_extra_whitespace.into()),
+        ("canonical_spacing", args.canonical_spacing.into()),
+        ("label_pattern", label_pattern.map(Regex::to_string).into()),
+        ("allow_empty_label", args.allow_empty_label.into()),
+        ("allow_numeric_label", args.allow_numeric_label.into()),
+        ("forbid_label", strings(&args.forbid_label)),
+        ("max_label_length", args.max_label_length.into()),
+        ("require_note", args.require_note.into()),
+        ("warn_case_collisions", args.warn_case_collisions.into()),
+        ("normalize_separators", args.normalize_separators.into()),
+        ("count_on_first", args.count_on_first.into()),
+        ("since", args.since.clone().into()),
+        ("count_semantics", name(args.count_semantics).into()),
+        ("count_scope", name(args.count_scope).into()),
+        ("count_unit", name(args.count_unit).into()),
+        ("count_tolerance", args.count_tolerance.into()),
+        ("count_report_threshold", args.count_report_threshold.into()),
+        ("trace_label", args.trace_label.as_deref().into()),
+        (
+            "collision_hint_threshold",
+            args.collision_hint_threshold.into(),
+        ),
+        (
+            "max_labels_per_diagnostic",
+            args.max_labels_per_diagnostic.into(),
+        ),
+        ("anchor_diagnostic", name(args.anchor_diagnostic).into()),
+        (
+            "count_from_file",
+            args.count_from_file.as_deref().map(path).into(),
+        ),
+        ("fail_on_empty", args.fail_on_empty.into()),
+        ("collect_all", args.collect_all.into()),
+        ("rule_stats", args.rule_stats.and_then(name).into()),
+        ("format", name(args.format).into()),
+        ("pretty", args.pretty.into()),
+        ("blame", args.blame.into()),
+        ("stdin_content", args.stdin_content.into()),
+        ("stdin_name", args.stdin_name.as_deref().map(path).into()),
+        ("output", args.output.as_deref().map(path).into()),
+        ("summary_only", args.summary_only.into()),
+        ("output_relative_uris", args.output_relative_uris.into()),
+        (
+            "vars",
+            json::Value::object(vars.into_iter().map(|(name, n)| (name, (*n).into()))),
+        ),
+        ("keywords", json::Value::object(keywords)),
+        (
+            "aliases",
+            json::Value::object(
+                aliases
+                    .into_iter()
+                    .map(|(old, new)| (old, new.as_str().into())),
+            ),
+        ),
+        (
+            "severity",
+            json::Value::object(
+                levels
+                    .into_iter()
+                    .map(|(code, level)| (code, level.as_str().into())),
+            ),
+        ),
+    ]))
+}
+
+/// Render a JSON object as a TOML document. Nested objects become tables after the other keys and
+/// `null` values are left out since TOML can't represent them.
+fn to_toml(value: &json::Value) -> String {
+    fn key(key: &str) -> String {
+        let bare = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if bare {
+            key.to_string()
+        } else {
+            json::Value::from(key).to_string()
+        }
+    }
+
+    let json::Value::Object(fields) = value else {
+        return String::new();
+    };
+    let mut out = String::new();
+    let mut tables = String::new();
+    for (name, value) in fields {
+        match value {
+            json::Value::Null => {}
+            json::Value::Object(table) => {
+                tables.push_str(&format!("\n[{}]\n", key(name)));
+                for (name, value) in table {
+                    if *value != json::Value::Null {
+                        tables.push_str(&format!("{} = {value}\n", key(name)));
+                    }
+                }
+            }
+            // JSON strings, numbers, and arrays of them are valid TOML
+            value => out.push_str(&format!("{} = {value}\n", key(name))),
+        }
+    }
+    out + &tables
+}
+
+/// A [`codesync::stable_hash`] of the label, the expected count, and the number of comments of
+/// each label in order. The expected count is computed with the `vars` in `codesync.toml` and
+/// written as `-` if the counts conflict.
+fn labels_hash(matches: &Matches, vars: &HashMap<String, u16>) -> u64 {
+    let mut parts = vec![];
+    for (label, comments) in matches.group_by_label() {
+        let expected = codesync::expected_count(&comments, vars, CountOptions::default());
+        parts.push(label.to_string());
+        parts.push(expected.map_or("-".to_string(), |e| e.total.to_string()));
+        parts.push(comments.len().to_string());
+    }
+    codesync::stable_hash(parts.iter().map(String::as_str))
+}
+
+/// The number of labels keyed by how many comments they have.
+fn count_distribution_of(matches: &Matches) -> BTreeMap<usize, usize> {
+    let mut distribution = BTreeMap::new();
+    for comments in matches.group_by_label().values() {
+        *distribution.entry(comments.len()).or_insert(0) += 1;
+    }
+    distribution
+}
+
+/// Reports diagnostics through the [`Reporter`] selected by `--format`, keeping track of whether
+/// any error was reported.
+struct Emitter {
+    reporter: Box<dyn Reporter>,
+    has_errors: bool,
+    /// Number of diagnostics reported for each code, see `--rule-stats`.
+    codes: HashMap<String, usize>,
+}
+
+impl Emitter {
+    fn new(stderr: bool, format: Format, pretty: bool, color: Color) -> Self {
+        let writer = if stderr && matches!(format, Format::Human | Format::Summary) {
+            color.stderr()
+        } else {
+            color.stdout()
+        };
+        Self::with_writer(Box::new(writer), format, pretty)
+    }
+
+    /// An emitter writing to the file at `path` without colors, truncating it if it exists.
+    fn to_file(path: &Path, format: Format, pretty: bool) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::with_writer(
+            Box::new(NoColor::new(file)),
+            format,
+            pretty,
+        ))
+    }
+
+    fn with_writer(writer: Box<dyn WriteColor>, format: Format, pretty: bool) -> Self {
+        Self {
+            reporter: format.reporter(writer, pretty),
+            has_errors: false,
+            codes: HashMap::new(),
+        }
+    }
+
+    /// Print buffered diagnostics and flush the output. Must be called once after all diagnostics
+    /// have been emitted.
+    fn finish(&mut self) -> io::Result<()> {
+        self.reporter.finish()
+    }
+
+    fn emit(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: Diagnostic<FileId>,
+    ) -> Result<(), codespan_reporting::files::Error> {
+        self.has_errors |= diagnostic.severity >= Severity::Error;
+        if let Some(code) = &diagnostic.code {
+            *self.codes.entry(code.clone()).or_default() += 1;
+        }
+        self.reporter.report(db, &diagnostic)
+    }
+}
+
+struct Checker {
+    args: CheckArgs,
+    /// Configuration of the project and its subdirectories.
+    configs: ScopedConfigs,
+    /// Lines changed since `--since`, if given, to which style checks are restricted.
+    changed: Option<ChangedLines>,
+    /// Blame of the lines diagnostics point to, if `--blame` is given.
+    blame: Option<Blame>,
+    db: FilesDB,
+    emitter: Emitter,
+    /// When checking started, if `--profile` is given.
+    started: Option<Instant>,
+    /// Suppression directives in the checked files, see [`Checker::suppress`].
+    suppressions: Vec<InlineSuppression>,
+    /// Levels overriding the default severity of diagnostics, see [`lint_levels`].
+    levels: BTreeMap<String, Level>,
+    /// Expected counts of labels without an explicit one, see [`parse_count_file`].
+    default_counts: HashMap<String, u16>,
+}
+
+/// A suppression directive found while checking.
+struct InlineSuppression {
+    path: PathBuf,
+    file_id: FileId,
+    /// The 0-based line of the match the directive applies to
+    line: usize,
+    suppression: Suppression,
+    /// Whether the directive suppressed at least one diagnostic
+    used: bool,
+}
+
+impl Checker {
+    fn new(
+        args: CheckArgs,
+        configs: ScopedConfigs,
+        changed: Option<ChangedLines>,
+        color: Color,
+    ) -> Result<Self, Box<dyn Error>> {
+        let levels = lint_levels(&args, configs.root())?;
+        let default_counts = match &args.count_from_file {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| parse_count_file(&contents))
+                .map_err(|err| format!("failed to read counts from `{}`: {err}", path.display()))?,
+            None => HashMap::new(),
+        };
+        let format = if args.summary_only {
+            Format::Summary
+        } else {
+            args.format
+        };
+        // `jsonl` must print one object per line, so only the Code Climate report can be indented
+        if args.pretty && format != Format::CodeClimate {
+            return Err("`--pretty` requires `--format codeclimate`".into());
+        }
+        let emitter = match &args.output {
+            Some(path) => Emitter::to_file(path, format, args.pretty)?,
+            None => Emitter::new(true, format, args.pretty, color),
+        };
+        let db = FilesDB {
+            max_labels: (args.max_labels_per_diagnostic > 0)
+                .then_some(args.max_labels_per_diagnostic),
+            anchor: args.anchor_diagnostic,
+            ..FilesDB::new()
+        };
+        Ok(Self {
+            blame: args.blame.then(Blame::default),
+            args,
+            configs,
+            changed,
+            db,
+            emitter,
+            started: None,
+            suppressions: vec![],
+            levels,
+            default_counts,
+        })
+    }
+
+    /// Print paths relative to `root`, the searched directory or file, with
+    /// `--output-relative-uris`.
+    fn relative_uris(mut self, root: &Path) -> Self {
+        if self.args.output_relative_uris {
+            let base = match root.parent() {
+                Some(parent) if root.is_file() => parent,
+                _ => root,
+            };
+            self.db.display_base = Some(base.to_path_buf());
+        }
+        self
+    }
+
+    /// Report diagnostics to `reporter` instead of in the format given by `--format`, e.g., to
+    /// publish them to an editor.
+    fn reporter(mut self, reporter: Box<dyn Reporter>) -> Self {
+        self.emitter.reporter = reporter;
+        self
+    }
+
+    /// Print the time spent checking to stderr when done, counting from now.
+    fn profile(mut self, enabled: bool) -> Self {
+        self.started = enabled.then(Instant::now);
+        self
+    }
+
+    /// Report the diagnostics of `matches`, exiting with [`EXIT_VIOLATIONS`] if there are errors.
+    fn check(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        self.run(matches)?;
+        self.exit_if_errors()?;
+        self.finish()?;
+        Ok(())
+    }
+
+    /// Run every check on `matches` reporting diagnostics without exiting, unless checking stops
+    /// after errors, i.e., without `--collect-all`, see [`Checker::abort_if_errors`].
+    fn run(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        self.db.add_sources(matches);
+        self.collect_suppressions(matches)?;
+        self.trace_label(matches)?;
+
+        self.report_invalid_matches(&matches)?;
+        self.report_empty_labels(matches)?;
+        self.report_numeric_labels(matches)?;
+        self.abort_if_errors()?;
+
+        self.report_empty(matches)?;
+        self.abort_if_errors()?;
+
+        self.report_undefined_vars(matches)?;
+        self.report_forbidden_labels(matches)?;
+        self.abort_if_errors()?;
+
+        self.report_deprecated_labels(matches)?;
+
+        self.report_case_collisions(matches)?;
+        self.report_mixed_separators(matches)?;
+
+        // Counts can't be checked without the other files, see `--stdin-content`
+        let groups = if self.args.stdin_content {
+            vec![]
+        } else {
+            self.count_groups(matches)
+        };
+        let mut labels: Vec<&str> = groups.iter().map(|(label, _)| label.as_str()).collect();
+        labels.dedup();
+        for (label, comments) in &groups {
+            self.report_incorrect_counts(label, comments, &labels)?;
+        }
+        self.abort_if_errors()?;
+
+        for (label, comments) in &groups {
+            self.report_count_not_on_first(label, comments)?;
+        }
+
+        self.report_inconsistent_casing(matches)?;
+        self.abort_if_errors()?;
+
+        self.report_label_regex_mismatch(matches)?;
+
+        self.report_long_labels(matches)?;
+        self.report_missing_notes(matches)?;
+
+        self.report_no_extra_whitespace(matches)?;
+        self.report_non_canonical_spacing(matches)?;
+        self.abort_if_errors()?;
+
+        self.report_unused_suppressions()?;
+        Ok(())
+    }
+
+    fn collect_suppressions(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        let mut seen = HashSet::new();
+        for entry in matches.entries() {
+            let file_id = self.db.file_id(entry.file())?;
+            let line = self.db.files.line_index(file_id, entry.span().start)?;
+            for suppression in entry.suppressions() {
+                // A directive is shared by all matches on its line
+                if seen.insert((file_id, suppression.span().start)) {
+                    self.suppressions.push(InlineSuppression {
+                        path: entry.file().to_path_buf(),
+                        file_id,
+                        line,
+                        suppression: suppression.clone(),
+                        used: false,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Report directives that didn't suppress anything. Only called once all checks have run, and
+    /// restricted to changed lines with `--since` like style checks.
+    fn report_unused_suppressions(&mut self) -> Result<(), Box<dyn Error>> {
+        let unused: Vec<_> = self
+            .suppressions
+            .iter()
+            .filter(|s| !s.used)
+            .map(|s| (s.path.clone(), s.suppression.span()))
+            .collect();
+        for (path, span) in unused {
+            if let Some(changed) = &self.changed {
+                let lines = self.db.line_range(&path, span.clone())?;
+                if !changed.overlaps(&path, lines) {
+                    continue;
+                }
+            }
+            let mut diagnostic = self.db.unused_suppression_diagnostic(&path, span)?;
+            if self.apply_level(&mut diagnostic) {
+                self.emit_unsuppressed(diagnostic)?;
+            }
+        }
         Ok(())
     }
 
     fn report_invalid_matches(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
         for m in matches.invalid_matches() {
-            let diagnostic = match m.error {
-                ArgsError::Malformed => self.db.malformed_diagnostic(m.file(), m.span())?,
-                ArgsError::InvalidCount { start, end } => {
-                    self.db.invalid_count_diagnostic(m.file(), start..end)?
+            let diagnostic = self.db.invalid_match_diagnostic(&m)?;
+            self.emit_diagnostic(diagnostic)?;
+        }
+        Ok(())
+    }
+
+    fn report_empty_labels(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if self.args.allow_empty_label {
+            return Ok(());
+        }
+        for comment in matches.comments() {
+            if comment.label().is_empty() {
+                let diagnostic = self.db.empty_label_diagnostic(comment)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn report_numeric_labels(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if self.args.allow_numeric_label {
+            return Ok(());
+        }
+        for comment in matches.comments() {
+            if comment.has_numeric_label() {
+                let diagnostic = self.db.numeric_label_diagnostic(comment)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn report_empty(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        // A single buffer without comments is expected, e.g., most files open in an editor
+        if self.args.stdin_content {
+            return Ok(());
+        }
+        if self.args.fail_on_empty && matches.comments().next().is_none() {
+            let diagnostic = Diagnostic::error()
+                .with_message("no codesync comments found")
+                .with_code("empty")
+                .with_notes(vec![
+                    "make sure codesync runs at the root of the project and that files with comments aren't ignored, e.g., by trying `--no-default-ignores`".to_string(),
+                ]);
+            self.emit_diagnostic(diagnostic)?;
+        }
+        Ok(())
+    }
+
+    fn report_undefined_vars(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        for comment in matches.comments() {
+            if let Some(name) = comment.count_var() {
+                if !self.configs.root().vars.contains_key(name) {
+                    let diagnostic = self.db.undefined_var_diagnostic(comment, name)?;
+                    self.emit_diagnostic(diagnostic)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn report_forbidden_labels(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if self.args.forbid_label.is_empty() {
+            return Ok(());
+        }
+        for comment in matches.comments() {
+            if self.args.forbid_label.iter().any(|l| l == comment.label()) {
+                let diagnostic = self.db.forbidden_label_diagnostic(comment)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn report_deprecated_labels(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        for (label, comments) in matches.group_by_label() {
+            let new = self.configs.root().canonical_label(label).to_string();
+            if new == label {
+                continue;
+            }
+            for comment in comments {
+                let diagnostic = self.db.deprecated_label_diagnostic(comment, &new)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn report_case_collisions(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if !self.args.warn_case_collisions {
+            return Ok(());
+        }
+        // An example comment for each spelling grouped by a case-insensitive key
+        let mut spellings: BTreeMap<String, Vec<Comment>> = BTreeMap::new();
+        for (label, comments) in matches.group_by_label() {
+            spellings
+                .entry(codesync::normalize_label(label))
+                .or_default()
+                .push(comments[0]);
+        }
+        for examples in spellings.values() {
+            if examples.len() > 1 {
+                let diagnostic = self.db.case_collision_diagnostic(examples)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn report_mixed_separators(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if !self.args.normalize_separators {
+            return Ok(());
+        }
+        let config = self.configs.root();
+        // An example comment for each spelling grouped as for checking counts
+        let mut spellings: BTreeMap<String, Vec<Comment>> = BTreeMap::new();
+        for (label, comments) in matches.group_by_label() {
+            spellings
+                .entry(codesync::normalize_separators(
+                    config.canonical_label(label),
+                ))
+                .or_default()
+                .push(comments[0]);
+        }
+        for examples in spellings.values() {
+            if examples.len() > 1 {
+                let diagnostic = self.db.mixed_separators_diagnostic(examples)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Comments counted together by label, split by directory with `--count-scope directory`.
+    /// Comments with a label renamed in `[aliases]` are counted with the new label, and with
+    /// `--normalize-separators` labels only differing in separators are merged, see
+    /// [`merge_separator_spellings`].
+    fn count_groups<'a>(&mut self, matches: &'a Matches) -> Vec<(String, Vec<Comment<'a>>)> {
+        let config = self.configs.root();
+        let mut groups: BTreeMap<String, Vec<Comment>> = BTreeMap::new();
+        for (label, comments) in matches.group_by_label() {
+            let label = config.canonical_label(label).to_string();
+            groups.entry(label).or_default().extend(comments);
+        }
+        if self.args.normalize_separators {
+            groups = merge_separator_spellings(groups);
+        }
+        for comments in groups.values_mut() {
+            codesync::sort_by_location(comments);
+        }
+        if self.args.count_scope == CountScope::Global {
+            return groups.into_iter().collect();
+        }
+        let mut scoped = vec![];
+        for (label, comments) in groups {
+            let mut by_dir: BTreeMap<PathBuf, Vec<Comment>> = BTreeMap::new();
+            for comment in comments {
+                let dir = self.configs.scope_dir(comment.file());
+                by_dir.entry(dir).or_default().push(comment);
+            }
+            scoped.extend(
+                by_dir
+                    .into_values()
+                    .map(|comments| (label.clone(), comments)),
+            );
+        }
+        scoped
+    }
+
+    /// Print the comments with the label given by `--trace-label` and how their count is checked
+    /// to stderr. Comments are grouped as for checking counts, see `--count-scope`.
+    fn trace_label(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        let Some(label) = self.args.trace_label.clone() else {
+            return Ok(());
+        };
+        let groups: Vec<_> = self
+            .count_groups(matches)
+            .into_iter()
+            .filter(|(l, _)| *l == label)
+            .collect();
+        if groups.is_empty() {
+            eprintln!("trace `{label}`: no comments found");
+        }
+        let semantics = self.args.count_semantics.semantics();
+        let unit = self.args.count_unit.unit();
+        let default = self.default_counts.get(&label).copied();
+        for (_, comments) in groups {
+            let n = comments.len();
+            eprintln!("trace `{label}`: {n} {}", pluralize("comment", n));
+            let vars = &self.configs.root().vars;
+            for comment in &comments {
+                let count = match comment.count_arg().map(|c| c.value()) {
+                    Some(count @ Count::Var(_)) => match count.resolve(vars) {
+                        Some(n) => format!("count {count} = {n}"),
+                        None => format!("count {count} (undefined)"),
+                    },
+                    Some(count) => format!("count {count}"),
+                    None => "no count".to_string(),
+                };
+                let location = self.db.location(comment.file(), comment.span().start)?;
+                eprintln!("  {location}: {count}");
+            }
+            eprintln!(
+                "  {}",
+                count_derivation(&comments, vars, semantics, default)
+            );
+            let opts = CountOptions {
+                semantics,
+                unit,
+                default: default.unwrap_or(codesync::DEFAULT_COUNT),
+            };
+            let check = codesync::check_count_with(&comments, vars, opts);
+            let status = match check {
+                CountCheck::Ok => "ok".to_string(),
+                CountCheck::Mismatch { found, .. } => {
+                    let unit = match unit {
+                        CountUnit::Occurrence => "comment",
+                        CountUnit::File => "file",
+                    };
+                    format!("found {found} {}, incorrect", pluralize(unit, found))
                 }
+                CountCheck::Conflicting => "conflicting counts".to_string(),
             };
-            self.emit_diagnostic(diagnostic)?;
+            eprintln!("  {status}");
         }
         Ok(())
     }
 
+    /// Report the count of `label` if it's incorrect. `labels` are all labels, to look for similar
+    /// ones with `--collision-hint-threshold`.
     fn report_incorrect_counts(
         &mut self,
         label: &str,
         comments: &[Comment],
+        labels: &[&str],
     ) -> Result<(), Box<dyn Error>> {
-        let counts: Vec<_> = comments
-            .iter()
-            .map(Comment::count)
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect();
-
-        match &counts[..] {
-            [] => {}
-            [count] => {
-                let expected = *count as usize;
-                let found = comments.len();
-                if found != expected {
-                    let message = format!(
-                        "expected {expected} {} with label `{label}`, found {found}",
-                        pluralize("comment", expected)
-                    );
-                    let diagnostic = self.db.mismatched_counts_diagnostic(comments, message)?;
-                    self.emit_diagnostic(diagnostic)?;
-                }
+        let semantics = self.args.count_semantics.semantics();
+        let unit = self.args.count_unit.unit();
+        let vars = &self.configs.root().vars;
+        let default = self
+            .default_counts
+            .get(label)
+            .copied()
+            .unwrap_or(codesync::DEFAULT_COUNT);
+        let opts = CountOptions {
+            semantics,
+            unit,
+            default,
+        };
+        let check = codesync::check_count_with(comments, vars, opts);
+        if below_threshold(&check, self.args.count_report_threshold) {
+            return Ok(());
+        }
+        let tolerated = within_tolerance(&check, self.args.count_tolerance);
+        if let Some(mut diagnostic) = self
+            .db
+            .incorrect_count_diagnostic(label, comments, check, unit)?
+        {
+            if tolerated {
+                diagnostic.severity = Severity::Warning;
+                diagnostic.notes.push(format!(
+                    "reported as a warning since it's within `--count-tolerance {}`",
+                    self.args.count_tolerance
+                ));
             }
-            _ => {
-                let message = format!("not all comments with label `{label}` have the same count",);
-                let diagnostic = self.db.mismatched_counts_diagnostic(comments, message)?;
-                self.emit_diagnostic(diagnostic)?;
+            if let (Some(threshold), CountCheck::Mismatch { expected, found }) =
+                (self.args.collision_hint_threshold, check)
+            {
+                if found.abs_diff(expected as usize) > threshold {
+                    diagnostic.notes.push(collision_hint(label, labels));
+                }
             }
+            self.emit_diagnostic(diagnostic)?;
         }
+        Ok(())
+    }
 
+    fn report_count_not_on_first(
+        &mut self,
+        label: &str,
+        comments: &[Comment],
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.args.count_on_first {
+            return Ok(());
+        }
+        let [first, rest @ ..] = comments else {
+            return Ok(());
+        };
+        if !first.count_is_default() {
+            return Ok(());
+        }
+        if let Some(anchor) = rest.iter().find(|c| !c.count_is_default()) {
+            let diagnostic = self
+                .db
+                .count_not_on_first_diagnostic(label, *first, *anchor)?;
+            self.emit_diagnostic(diagnostic)?;
+        }
         Ok(())
     }
 
     fn report_inconsistent_casing(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
-        if let Some(case) = self.args.consistent_casing {
-            for comment in matches.comments() {
-                if !case.has_case(comment.label()) {
-                    let diagnostic = self.db.invalid_case_diagnostic(comment, case)?;
-                    self.emit_diagnostic(diagnostic)?;
-                }
+        for comment in self.style_checked_comments(matches)? {
+            let Some(case) = self.casing_for(comment.file())? else {
+                continue;
+            };
+            if !case.has_case(comment.label()) {
+                let diagnostic = self.db.invalid_case_diagnostic(comment, &case)?;
+                self.emit_diagnostic(diagnostic)?;
             }
         }
         Ok(())
@@ -276,7 +2726,11 @@ impl Checker {
 
     fn report_no_extra_whitespace(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
         if self.args.no_extra_whitespace {
-            for comment in matches.comments() {
+            for comment in self.style_checked_comments(matches)? {
+                if let Some(gap) = comment.keyword_gap() {
+                    let diagnostic = self.db.keyword_gap_diagnostic(comment.file(), gap)?;
+                    self.emit_diagnostic(diagnostic)?;
+                }
                 if let Some(count_arg) = comment.count_arg() {
                     if count_arg.has_extra_whitespace() {
                         let diagnostic = self
@@ -300,78 +2754,561 @@ impl Checker {
         Ok(())
     }
 
+    fn report_non_canonical_spacing(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if self.args.canonical_spacing {
+            for comment in self.style_checked_comments(matches)? {
+                let edits = comment.spacing_edits();
+                if !edits.is_empty() {
+                    let diagnostic = self.db.spacing_diagnostic(comment, &edits)?;
+                    self.emit_diagnostic(diagnostic)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn report_label_regex_mismatch(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
-        if let Some(re) = &self.args.label_pattern {
-            for comment in matches.comments() {
-                if !re.is_match(comment.label()) {
-                    let diagnostic = self.db.regex_mismatch_diagnostic(comment)?;
-                    self.emitter.emit(&self.db, diagnostic)?;
+        for comment in self.style_checked_comments(matches)? {
+            let config = self.configs.for_file(comment.file())?;
+            let Some(re) = config
+                .label_pattern
+                .as_ref()
+                .or(self.args.label_pattern.as_ref())
+            else {
+                continue;
+            };
+            if !re.is_match(comment.label()) {
+                let diagnostic = self.db.regex_mismatch_diagnostic(comment)?;
+                self.emit_diagnostic(diagnostic)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The casing labels in `file` must use. The nearest configuration setting `consistent_casing`
+    /// takes precedence over `--consistent-casing`.
+    fn casing_for(&mut self, file: &Path) -> Result<Option<Casing>, Box<dyn Error>> {
+        let config = self.configs.for_file(file)?;
+        let casing = match &config.consistent_casing {
+            Some(name) => Some(self.args.casing.with_case(config_case(name)?)),
+            None => self.args.casing.casing(),
+        };
+        Ok(casing.map(|mut casing| {
+            if let Some(words) = &config.small_words {
+                casing.small_words = Some(Rc::new(words.iter().cloned().collect()));
+            }
+            casing
+        }))
+    }
+
+    fn report_long_labels(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if let Some(max) = self.args.max_label_length {
+            for comment in self.style_checked_comments(matches)? {
+                if let Some(len) = label_too_long(comment.label(), max) {
+                    let diagnostic = self.db.long_label_diagnostic(comment, len, max)?;
+                    self.emit_diagnostic(diagnostic)?;
                 }
             }
         }
         Ok(())
     }
 
-    fn abort_if_errors(&self) {
-        self.emitter.abort_if_errors();
+    fn report_missing_notes(&mut self, matches: &Matches) -> Result<(), Box<dyn Error>> {
+        if self.args.require_note {
+            for comment in self.style_checked_comments(matches)? {
+                if comment
+                    .note_arg()
+                    .is_none_or(|note| note.value().trim().is_empty())
+                {
+                    let diagnostic = self.db.missing_note_diagnostic(comment)?;
+                    self.emit_diagnostic(diagnostic)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Comments style checks should report on, i.e., all comments or only the ones overlapping
+    /// changed lines when `--since` is given.
+    fn style_checked_comments<'a>(
+        &mut self,
+        matches: &'a Matches,
+    ) -> Result<Vec<Comment<'a>>, codespan_reporting::files::Error> {
+        let Some(changed) = &self.changed else {
+            return Ok(matches.comments().collect());
+        };
+        let mut comments = vec![];
+        for comment in matches.comments() {
+            let lines = self.db.line_range(comment.file(), comment.span())?;
+            if changed.overlaps(comment.file(), lines) {
+                comments.push(comment);
+            }
+        }
+        Ok(comments)
+    }
+
+    /// Stop checking if errors were reported so far, unless `--collect-all` is given.
+    fn abort_if_errors(&mut self) -> io::Result<()> {
+        if self.args.collect_all {
+            return Ok(());
+        }
+        self.exit_if_errors()
+    }
+
+    fn exit_if_errors(&mut self) -> io::Result<()> {
+        if self.emitter.has_errors {
+            self.finish()?;
+            std::process::exit(EXIT_VIOLATIONS);
+        }
+        Ok(())
+    }
+
+    /// Print buffered diagnostics and, with `--profile`, the time spent checking. With
+    /// `--rule-stats`, the number of diagnostics of each code is printed too.
+    fn finish(&mut self) -> io::Result<()> {
+        self.emitter.finish()?;
+        if let Some(format) = self.args.rule_stats {
+            let stats = rule_stats(&self.emitter.codes);
+            match format {
+                RuleStatsFormat::Text => {
+                    eprintln!("{:>8}  CODE", "COUNT");
+                    for (code, n) in stats {
+                        eprintln!("{n:>8}  {code}");
+                    }
+                }
+                RuleStatsFormat::Json => {
+                    let fields = stats.into_iter().map(|(code, n)| (code, n.into()));
+                    eprintln!("{}", json::Value::object(fields));
+                }
+            }
+        }
+        if let Some(started) = self.started {
+            eprintln!("{:8}  {:?}", "check", started.elapsed());
+        }
+        Ok(())
     }
 
     fn emit_diagnostic(
         &mut self,
-        diagnostic: Diagnostic<FileId>,
+        mut diagnostic: Diagnostic<FileId>,
+    ) -> Result<(), codespan_reporting::files::Error> {
+        if !self.apply_level(&mut diagnostic) || self.suppress(&diagnostic)? {
+            return Ok(());
+        }
+        self.emit_unsuppressed(diagnostic)
+    }
+
+    /// Set the severity of the diagnostic to the level configured for its code, if any, returning
+    /// `false` if the code is allowed and the diagnostic shouldn't be reported.
+    fn apply_level(&self, diagnostic: &mut Diagnostic<FileId>) -> bool {
+        let code = diagnostic.code.as_ref();
+        match code.and_then(|code| self.levels.get(code)) {
+            Some(Level::Allow) => return false,
+            Some(Level::Error) => diagnostic.severity = Severity::Error,
+            Some(Level::Warn) => diagnostic.severity = Severity::Warning,
+            None => {}
+        }
+        true
+    }
+
+    /// Whether a suppression directive applying to the line of one of the diagnostic's primary
+    /// labels allows its code. Directives that do are marked as used.
+    fn suppress(
+        &mut self,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<bool, codespan_reporting::files::Error> {
+        let Some(code) = &diagnostic.code else {
+            return Ok(false);
+        };
+        let mut suppressed = false;
+        for label in &diagnostic.labels {
+            if label.style != LabelStyle::Primary {
+                continue;
+            }
+            let line = self.db.files.line_index(label.file_id, label.range.start)?;
+            for s in &mut self.suppressions {
+                if s.file_id == label.file_id && s.line == line && s.suppression.allows(code) {
+                    s.used = true;
+                    suppressed = true;
+                }
+            }
+        }
+        Ok(suppressed)
+    }
+
+    fn emit_unsuppressed(
+        &mut self,
+        mut diagnostic: Diagnostic<FileId>,
     ) -> Result<(), codespan_reporting::files::Error> {
+        if let Some(blame) = &mut self.blame {
+            let mut seen = HashSet::new();
+            for label in &diagnostic.labels {
+                let file = self.db.files.name(label.file_id)?;
+                let line = self.db.files.line_index(label.file_id, label.range.start)? + 1;
+                if !seen.insert((label.file_id, line)) {
+                    continue;
+                }
+                if let Some(blame) = blame.line(Path::new(&file), line) {
+                    diagnostic
+                        .notes
+                        .push(format!("{file}:{line} last changed by {blame}"));
+                }
+            }
+        }
         self.emitter.emit(&self.db, diagnostic)
     }
 }
 
 struct FilesDB {
-    pub files: SimpleFiles<String, String>,
+    pub files: SimpleFiles<String, Arc<str>>,
     path_to_file_id: HashMap<PathBuf, FileId>,
+    /// Where sources not registered with [`FilesDB::add_sources`] are read from.
+    fs: Box<dyn FileSystem>,
+    /// Directory paths are printed relative to by machine formats, see [`FilesDB::display_name`].
+    display_base: Option<PathBuf>,
+    /// Paths identifying collected files in fingerprints, see [`Matches::fingerprint_paths`].
+    fingerprint_paths: HashMap<FileId, String>,
+    /// Maximum number of comments pointed at by a count diagnostic, see
+    /// `--max-labels-per-diagnostic`.
+    max_labels: Option<usize>,
+    /// See `--anchor-diagnostic`
+    anchor: Anchor,
 }
 
-impl FilesDB {
-    fn new() -> Self {
-        Self {
-            files: SimpleFiles::new(),
-            path_to_file_id: HashMap::new(),
-        }
+impl FilesDB {
+    fn new() -> Self {
+        Self::with_file_system(Box::new(OsFileSystem))
+    }
+
+    fn with_file_system(fs: Box<dyn FileSystem>) -> Self {
+        Self {
+            files: SimpleFiles::new(),
+            path_to_file_id: HashMap::new(),
+            fs,
+            display_base: None,
+            fingerprint_paths: HashMap::new(),
+            max_labels: None,
+            anchor: Anchor::All,
+        }
+    }
+
+    /// The path of a file as printed by machine formats: relative to the directory set with
+    /// `--output-relative-uris`, if any, and otherwise as found.
+    fn display_name(&self, file_id: FileId) -> Result<String, codespan_reporting::files::Error> {
+        let name = self.files.name(file_id)?;
+        Ok(match &self.display_base {
+            Some(base) => codesync::normalized_path(&codesync::relative_to(Path::new(&name), base)),
+            None => name,
+        })
+    }
+
+    fn labels<'a>(
+        &mut self,
+        comments: impl IntoIterator<Item = Comment<'a>>,
+    ) -> io::Result<Vec<Label<FileId>>> {
+        comments
+            .into_iter()
+            .map(|comment| self.label(comment.file(), comment.span()))
+            .collect::<io::Result<_>>()
+    }
+
+    /// Register the contents of all files with matches as seen when searching them, so they're
+    /// not read again from disk.
+    fn add_sources(&mut self, matches: &Matches) {
+        for (path, contents) in matches.sources() {
+            self.path_to_file_id
+                .entry(path.to_path_buf())
+                .or_insert_with(|| self.files.add(path.display().to_string(), contents));
+        }
+        for (path, fingerprint_path) in matches.fingerprint_paths() {
+            let file_id = self.path_to_file_id[path];
+            self.fingerprint_paths
+                .entry(file_id)
+                .or_insert(fingerprint_path);
+        }
+    }
+
+    fn label(&mut self, path: &Path, span: Range<usize>) -> io::Result<Label<FileId>> {
+        let file_id = self.file_id(path)?;
+        Ok(Label::primary(file_id, span))
+    }
+
+    fn secondary_label(&mut self, path: &Path, span: Range<usize>) -> io::Result<Label<FileId>> {
+        let file_id = self.file_id(path)?;
+        Ok(Label::secondary(file_id, span))
+    }
+
+    fn location(
+        &mut self,
+        path: &Path,
+        byte_index: usize,
+    ) -> Result<Location, codespan_reporting::files::Error> {
+        let file_id = self.file_id(path)?;
+        let location = self.files.location(file_id, byte_index)?;
+        Ok(Location {
+            file: path.display().to_string(),
+            line: location.line_number,
+            column: location.column_number,
+        })
+    }
+
+    /// The 1-based lines spanned by `span`, with an exclusive end.
+    fn line_range(
+        &mut self,
+        path: &Path,
+        span: Range<usize>,
+    ) -> Result<Range<usize>, codespan_reporting::files::Error> {
+        let file_id = self.file_id(path)?;
+        let start = self.files.line_index(file_id, span.start)?;
+        let end = self.files.line_index(file_id, span.end)?;
+        Ok(start + 1..end + 2)
+    }
+
+    fn file_id(&mut self, path: &Path) -> io::Result<FileId> {
+        match self.path_to_file_id.entry(path.to_path_buf()) {
+            Entry::Occupied(entry) => Ok(*entry.get()),
+            Entry::Vacant(entry) => {
+                let source = String::from_utf8(self.fs.read(path)?)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let file_id = self.files.add(path.display().to_string(), source.into());
+                entry.insert(file_id);
+                Ok(file_id)
+            }
+        }
+    }
+
+    fn diagnostic_to_json(
+        &self,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<json::Value, codespan_reporting::files::Error> {
+        let labels = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let source = self.files.source(label.file_id)?;
+                let text = source.get(label.range.clone()).unwrap_or_default();
+                let start = self.files.location(label.file_id, label.range.start)?;
+                let end = self.files.location(label.file_id, label.range.end)?;
+                Ok(json::Value::object([
+                    ("file", self.display_name(label.file_id)?.into()),
+                    ("start", label.range.start.into()),
+                    ("end", label.range.end.into()),
+                    ("line", start.line_number.into()),
+                    ("column", start.column_number.into()),
+                    ("end_line", end.line_number.into()),
+                    ("end_column", end.column_number.into()),
+                    ("text", text.into()),
+                    ("message", label.message.as_str().into()),
+                ]))
+            })
+            .collect::<Result<Vec<_>, codespan_reporting::files::Error>>()?;
+        Ok(json::Value::object([
+            ("severity", severity_name(diagnostic.severity).into()),
+            ("code", code_value(diagnostic)),
+            ("message", diagnostic.message.as_str().into()),
+            ("labels", json::Value::Array(labels)),
+            (
+                "notes",
+                json::Value::array(diagnostic.notes.iter().map(|n| n.as_str().into())),
+            ),
+        ]))
+    }
+
+    /// Identify an issue by its check, the file of its first label, and the text under that label.
+    /// Unlike line numbers or byte offsets, these don't change when unrelated code is edited. The
+    /// file is identified by its path relative to the root it was collected from, like in
+    /// [`Comment::fingerprint`], so the key doesn't depend on the directory codesync runs in.
+    fn fingerprint_key(
+        &self,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<String, codespan_reporting::files::Error> {
+        let code = diagnostic.code.as_deref().unwrap_or_default();
+        let Some(label) = diagnostic.labels.first() else {
+            return Ok(format!("{code}\0{}", diagnostic.message));
+        };
+        let source = self.files.source(label.file_id)?;
+        let text = source.get(label.range.clone()).unwrap_or_default();
+        let path = match self.fingerprint_paths.get(&label.file_id) {
+            Some(path) => path.clone(),
+            None => codesync::normalized_path(Path::new(&self.files.name(label.file_id)?)),
+        };
+        Ok(format!("{code}\0{path}\0{text}"))
+    }
+
+    /// Convert a diagnostic into a [Code Climate issue](https://github.com/codeclimate/platform/blob/master/spec/analyzers/SPEC.md#data-types).
+    fn diagnostic_to_codeclimate(
+        &self,
+        diagnostic: &Diagnostic<FileId>,
+        fingerprint: String,
+    ) -> Result<json::Value, codespan_reporting::files::Error> {
+        let (path, line) = match diagnostic.labels.first() {
+            Some(label) => (
+                self.files.name(label.file_id)?,
+                self.files.line_index(label.file_id, label.range.start)? + 1,
+            ),
+            None => (String::new(), 1),
+        };
+        let base = self.display_base.as_deref().unwrap_or(Path::new("."));
+        let path = codesync::normalized_path(&codesync::relative_to(Path::new(&path), base));
+        let severity = match diagnostic.severity {
+            Severity::Bug => "critical",
+            Severity::Error => "major",
+            Severity::Warning => "minor",
+            Severity::Note | Severity::Help => "info",
+        };
+        Ok(json::Value::object([
+            ("type", "issue".into()),
+            ("description", diagnostic.message.as_str().into()),
+            ("check_name", code_value(diagnostic)),
+            ("fingerprint", fingerprint.into()),
+            ("severity", severity.into()),
+            (
+                "location",
+                json::Value::object([
+                    ("path", path.into()),
+                    ("lines", json::Value::object([("begin", line.into())])),
+                ]),
+            ),
+        ]))
+    }
+
+    fn regex_mismatch_diagnostic(&mut self, comment: Comment) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(comment.file(), comment.label_arg().span())?;
+        Ok(Diagnostic::error()
+            .with_message("label doesn't match regex")
+            .with_code("label-pattern")
+            .with_labels(vec![label]))
+    }
+
+    fn empty_label_diagnostic(&mut self, comment: Comment) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(comment.file(), comment.label_arg().span())?;
+        Ok(Diagnostic::error()
+            .with_message("label must not be empty")
+            .with_code("empty-label")
+            .with_labels(vec![label]))
+    }
+
+    fn numeric_label_diagnostic(&mut self, comment: Comment) -> io::Result<Diagnostic<FileId>> {
+        let label = self
+            .label(comment.file(), comment.label_arg().span())?
+            .with_message("parsed as the label");
+        Ok(Diagnostic::warning()
+            .with_message(format!(
+                "label `{}` is a number, was it meant as a count?",
+                truncate_label(comment.label())
+            ))
+            .with_code("numeric-label")
+            .with_labels(vec![label])
+            .with_notes(vec![
+                "a count must follow a label, e.g., `CODESYNC(my-label, 2)`".to_string(),
+            ]))
     }
 
-    fn labels<'a>(
+    fn long_label_diagnostic(
         &mut self,
-        comments: impl IntoIterator<Item = Comment<'a>>,
-    ) -> io::Result<Vec<Label<FileId>>> {
-        comments
-            .into_iter()
-            .map(|comment| self.label(comment.file(), comment.span()))
-            .collect::<io::Result<_>>()
+        comment: Comment,
+        len: usize,
+        max: usize,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(comment.file(), comment.label_arg().span())?;
+        Ok(Diagnostic::error()
+            .with_message("label is too long")
+            .with_code("label-length")
+            .with_labels(vec![label])
+            .with_notes(vec![format!(
+                "label has {len} characters but the maximum is {max}"
+            )]))
     }
 
-    fn label(&mut self, path: &Path, span: Range<usize>) -> io::Result<Label<FileId>> {
-        let file_id = self.try_get_or_insert(path, || std::fs::read_to_string(path))?;
-        Ok(Label::primary(file_id, span))
+    fn missing_note_diagnostic(&mut self, comment: Comment) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(comment.file(), comment.span())?;
+        Ok(Diagnostic::error()
+            .with_message("comment has no note")
+            .with_code("missing-note")
+            .with_labels(vec![label])
+            .with_notes(vec![format!(
+                "add a note explaining what must be kept in sync with `, \"explanation\"`, e.g., \
+                 `CODESYNC({}{}, \"explanation\")`",
+                truncate_label(comment.label()),
+                comment
+                    .count_arg()
+                    .map(|count| format!(", {}", count.canonical()))
+                    .unwrap_or_default()
+            )]))
+    }
+
+    fn forbidden_label_diagnostic(&mut self, comment: Comment) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(comment.file(), comment.label_arg().span())?;
+        Ok(Diagnostic::error()
+            .with_message(format!(
+                "label `{}` is forbidden",
+                truncate_label(comment.label())
+            ))
+            .with_code("forbidden-label")
+            .with_labels(vec![label])
+            .with_notes(vec![
+                "labels passed to `--forbid-label` are placeholders and must be replaced with a descriptive label".to_string(),
+            ]))
     }
 
-    fn try_get_or_insert<E>(
+    fn unused_suppression_diagnostic(
         &mut self,
         path: &Path,
-        f: impl Fn() -> Result<String, E>,
-    ) -> Result<FileId, E> {
-        match self.path_to_file_id.entry(path.to_path_buf()) {
-            Entry::Occupied(entry) => Ok(*entry.get()),
-            Entry::Vacant(entry) => {
-                let file_id = self.files.add(path.display().to_string(), f()?);
-                entry.insert(file_id);
-                Ok(file_id)
-            }
-        }
+        span: Range<usize>,
+    ) -> io::Result<Diagnostic<FileId>> {
+        Ok(Diagnostic::warning()
+            .with_message("unused suppression")
+            .with_code("unused-suppression")
+            .with_labels(vec![self
+                .label(path, span)?
+                .with_message("no diagnostic was suppressed")])
+            .with_notes(vec![
+                "remove the directive or the codes in it that are no longer reported".to_string(),
+            ]))
     }
 
-    fn regex_mismatch_diagnostic(&mut self, comment: Comment) -> io::Result<Diagnostic<FileId>> {
-        let label = self.label(comment.file(), comment.label_arg().span())?;
+    fn deprecated_label_diagnostic(
+        &mut self,
+        comment: Comment,
+        new: &str,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self
+            .label(comment.file(), comment.label_arg().span())?
+            .with_message(format!("counted as `{}`", truncate_label(new)));
+        Ok(Diagnostic::warning()
+            .with_message(format!(
+                "label `{}` was renamed to `{}`",
+                truncate_label(comment.label()),
+                truncate_label(new)
+            ))
+            .with_code("deprecated-label")
+            .with_labels(vec![label])
+            .with_notes(vec![format!(
+                "the old label is an alias in `[aliases]` of `{CONFIG_FILE}`, rename the comment to complete the migration"
+            )]))
+    }
+
+    fn count_not_on_first_diagnostic(
+        &mut self,
+        label: &str,
+        first: Comment,
+        anchor: Comment,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let count_span = anchor
+            .count_span()
+            .expect("anchor should have an explicit count");
+        let primary = self.label(anchor.file(), count_span)?;
+        let secondary = self.secondary_label(first.file(), first.span())?;
         Ok(Diagnostic::error()
-            .with_message("label doesn't match regex")
-            .with_labels(vec![label]))
+            .with_message(format!(
+                "count for label `{}` is not on its first occurrence",
+                truncate_label(label)
+            ))
+            .with_code("count-not-on-first")
+            .with_labels(vec![
+                primary,
+                secondary.with_message("first occurrence is here"),
+            ]))
     }
 
     fn extra_whitespace_diagnostic<T>(
@@ -382,34 +3319,268 @@ impl FilesDB {
         let label = self.label(file, arg.span())?;
         Ok(Diagnostic::error()
             .with_message("argument has extra whitespace")
+            .with_code("extra-whitespace")
+            .with_labels(vec![label]))
+    }
+
+    fn keyword_gap_diagnostic(
+        &mut self,
+        file: &Path,
+        gap: Range<usize>,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(file, gap)?;
+        Ok(Diagnostic::error()
+            .with_message("extra whitespace before opening parenthesis")
+            .with_code("extra-whitespace")
             .with_labels(vec![label]))
     }
 
+    fn spacing_diagnostic(
+        &mut self,
+        comment: Comment,
+        edits: &[(Range<usize>, &str)],
+    ) -> io::Result<Diagnostic<FileId>> {
+        let labels = edits
+            .iter()
+            .map(|(span, replacement)| {
+                let message = if replacement.is_empty() {
+                    "remove this whitespace"
+                } else {
+                    "use a single space"
+                };
+                Ok(self
+                    .label(comment.file(), span.clone())?
+                    .with_message(message))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Diagnostic::error()
+            .with_message("arguments don't have canonical spacing")
+            .with_code("canonical-spacing")
+            .with_labels(labels)
+            .with_notes(vec![
+                "write exactly one space after the comma and no other whitespace, e.g., \
+                 `CODESYNC(my-label, 2)`"
+                    .to_string(),
+            ]))
+    }
+
     fn invalid_case_diagnostic(
         &mut self,
         comment: Comment,
-        case: Case,
+        case: &Casing,
     ) -> io::Result<Diagnostic<FileId>> {
         let label = self
             .label(comment.file(), comment.span())?
             .with_message(format!(
                 "should be written as {}",
-                case.to_case(comment.label())
+                truncate_label(&case.to_case(comment.label()))
             ));
         Ok(Diagnostic::error()
             .with_message(format!("label doesn't use {case} case"))
+            .with_code("consistent-casing")
             .with_labels(vec![label]))
     }
 
+    fn case_collision_diagnostic(
+        &mut self,
+        examples: &[Comment],
+    ) -> io::Result<Diagnostic<FileId>> {
+        self.spellings_diagnostic(
+            examples,
+            "labels differ only in casing",
+            "case-collision",
+            "are counted as different labels",
+        )
+    }
+
+    fn mixed_separators_diagnostic(
+        &mut self,
+        examples: &[Comment],
+    ) -> io::Result<Diagnostic<FileId>> {
+        self.spellings_diagnostic(
+            examples,
+            "labels differ only in word separators",
+            "mixed-separators",
+            "are counted together because of `--normalize-separators`",
+        )
+    }
+
+    /// A warning pointing at an example comment for each of the spellings of a label, with a note
+    /// listing them followed by `note`.
+    fn spellings_diagnostic(
+        &mut self,
+        examples: &[Comment],
+        message: &str,
+        code: &str,
+        note: &str,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let mut labels = vec![];
+        for (i, comment) in examples.iter().enumerate() {
+            let span = comment.label_arg().span();
+            let label = if i == 0 {
+                self.label(comment.file(), span)?
+            } else {
+                self.secondary_label(comment.file(), span)?
+            };
+            labels.push(label.with_message(format!(
+                "spelled `{}` here",
+                truncate_label(comment.label())
+            )));
+        }
+        let spellings: Vec<_> = examples
+            .iter()
+            .map(|c| format!("`{}`", truncate_label(c.label())))
+            .collect();
+        Ok(Diagnostic::warning()
+            .with_message(message)
+            .with_code(code)
+            .with_labels(labels)
+            .with_notes(vec![format!("{} {note}", spellings.join(", "))]))
+    }
+
     fn mismatched_counts_diagnostic(
         &mut self,
         comments: &[Comment],
         message: impl Into<String>,
     ) -> io::Result<Diagnostic<FileId>> {
-        let labels = self.labels(comments.iter().copied())?;
-        Ok(Diagnostic::error()
+        let mut comments = comments.to_vec();
+        if self.anchor == Anchor::First {
+            comments.sort_by_key(|c| (c.file(), c.span().start));
+        }
+        let shown = self.max_labels.unwrap_or(usize::MAX).min(comments.len());
+        let mut labels = self.labels(comments[..shown].iter().copied())?;
+        if self.anchor == Anchor::First {
+            for label in labels.iter_mut().skip(1) {
+                label.style = LabelStyle::Secondary;
+            }
+        }
+        let mut diagnostic = Diagnostic::error()
             .with_message(message)
-            .with_labels(labels))
+            .with_code("incorrect-count")
+            .with_labels(labels);
+        if shown < comments.len() {
+            diagnostic.notes.push(format!(
+                "... and {} more, see `--max-labels-per-diagnostic`",
+                comments.len() - shown
+            ));
+        }
+        Ok(diagnostic)
+    }
+
+    fn incorrect_count_diagnostic(
+        &mut self,
+        label: &str,
+        comments: &[Comment],
+        check: CountCheck,
+        unit: CountUnit,
+    ) -> io::Result<Option<Diagnostic<FileId>>> {
+        let label = truncate_label(label);
+        let message = match (check, unit) {
+            (CountCheck::Ok, _) => return Ok(None),
+            (CountCheck::Mismatch { expected, found }, CountUnit::Occurrence) => format!(
+                "expected {expected} {} with label `{label}`, found {found}",
+                pluralize("comment", expected as usize)
+            ),
+            (CountCheck::Mismatch { expected, found }, CountUnit::File) => format!(
+                "expected {expected} {} with label `{label}`, found {found}",
+                pluralize("file", expected as usize)
+            ),
+            (CountCheck::Conflicting, _) => {
+                format!("not all comments with label `{label}` have the same count")
+            }
+        };
+        Ok(Some(self.mismatched_counts_diagnostic(comments, message)?))
+    }
+
+    fn invalid_match_diagnostic(&mut self, m: &InvalidMatch) -> io::Result<Diagnostic<FileId>> {
+        let span = m.error.span().unwrap_or_else(|| m.span());
+        match m.error {
+            ArgsError::Malformed => self.malformed_diagnostic(m.file(), span),
+            ArgsError::InvalidCount { .. } => self.invalid_count_diagnostic(
+                m.file(),
+                span,
+                "second argument must be an integer".to_string(),
+            ),
+            ArgsError::CountOutOfRange { .. } => self.invalid_count_diagnostic(
+                m.file(),
+                span,
+                format!("count must be between 0 and {}", u16::MAX),
+            ),
+            ArgsError::MissingClosingParen { .. } => {
+                self.missing_closing_diagnostic(m.file(), span, ')')
+            }
+            ArgsError::MissingClosingBracket { .. } => {
+                self.missing_closing_diagnostic(m.file(), span, ']')
+            }
+            ArgsError::MismatchedBrackets { .. } => {
+                self.mismatched_brackets_diagnostic(m.file(), span)
+            }
+            ArgsError::EmptyCount { .. } => self.empty_count_diagnostic(m.file(), span),
+            ArgsError::NestedKeyword { .. } => self.nested_keyword_diagnostic(m.file(), span),
+        }
+    }
+
+    fn undefined_var_diagnostic(
+        &mut self,
+        comment: Comment,
+        name: &str,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let count_span = comment
+            .count_span()
+            .expect("comment should have an explicit count");
+        let label = self.label(comment.file(), count_span)?;
+        Ok(Diagnostic::error()
+            .with_message(format!("undefined variable `${name}`"))
+            .with_code("undefined-var")
+            .with_labels(vec![label])
+            .with_notes(vec![format!(
+                "variables must be defined in the `[vars]` table of `{CONFIG_FILE}`"
+            )]))
+    }
+
+    fn missing_closing_diagnostic(
+        &mut self,
+        path: &Path,
+        span: Range<usize>,
+        close: char,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(path, span)?;
+        Ok(Diagnostic::error()
+            .with_message("malformed codesync comment")
+            .with_code("malformed")
+            .with_labels(vec![label])
+            .with_notes(vec![format!("missing closing `{close}`")]))
+    }
+
+    fn mismatched_brackets_diagnostic(
+        &mut self,
+        path: &Path,
+        span: Range<usize>,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(path, span)?;
+        Ok(Diagnostic::error()
+            .with_message("malformed codesync comment")
+            .with_code("malformed")
+            .with_labels(vec![label])
+            .with_notes(vec![
+                "arguments must be written between `(` and `)` or between `[` and `]`".to_string(),
+            ]))
+    }
+
+    fn nested_keyword_diagnostic(
+        &mut self,
+        path: &Path,
+        span: Range<usize>,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(path, span)?.with_message("nested keyword");
+        Ok(Diagnostic::error()
+            .with_message("malformed codesync comment")
+            .with_code("malformed")
+            .with_labels(vec![label])
+            .with_notes(vec![
+                "a codesync comment cannot contain another one; remove one of the keywords"
+                    .to_string(),
+            ]))
     }
 
     fn malformed_diagnostic(
@@ -422,20 +3593,110 @@ impl FilesDB {
 
         Ok(Diagnostic::error()
             .with_message("malformed codesync comment")
+            .with_code("malformed")
             .with_labels(vec![label])
             .with_notes(vec![note]))
     }
 
+    fn empty_count_diagnostic(
+        &mut self,
+        path: &Path,
+        span: Range<usize>,
+    ) -> io::Result<Diagnostic<FileId>> {
+        let label = self.label(path, span)?.with_message("trailing comma");
+        Ok(Diagnostic::error()
+            .with_message("empty count after trailing comma")
+            .with_code("invalid-count")
+            .with_labels(vec![label])
+            .with_notes(vec![
+                "remove the comma or write a count, e.g., `CODESYNC(my-label, 3)`".to_string(),
+            ]))
+    }
+
     fn invalid_count_diagnostic(
         &mut self,
         path: &Path,
         span: Range<usize>,
+        note: String,
     ) -> io::Result<Diagnostic<FileId>> {
         let label = self.label(path, span)?;
         Ok(Diagnostic::error()
             .with_message("invalid count")
+            .with_code("invalid-count")
             .with_labels(vec![label])
-            .with_notes(vec!["second argument must be an integer".to_string()]))
+            .with_notes(vec![note]))
+    }
+}
+
+/// The length of `label` in characters if it's longer than `max`.
+fn label_too_long(label: &str, max: usize) -> Option<usize> {
+    let len = label.chars().count();
+    (len > max).then_some(len)
+}
+
+/// A `file:line:column` location that most terminals and editors recognize as a link.
+struct Location {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn code_value(diagnostic: &Diagnostic<FileId>) -> json::Value {
+    diagnostic
+        .code
+        .as_deref()
+        .map_or(json::Value::Null, json::Value::from)
+}
+
+/// Default of `--max-labels-per-diagnostic`, enough to see where a label is used without the
+/// comments of a heavily-referenced label filling the terminal.
+const DEFAULT_MAX_LABELS_PER_DIAGNOSTIC: usize = 25;
+
+/// Path of the source read by `check --stdin-content` when `--stdin-name` isn't given.
+const STDIN_NAME: &str = "<stdin>";
+
+/// Maximum number of characters of a label included in diagnostic messages.
+const MAX_DISPLAYED_LABEL_LEN: usize = 40;
+
+/// Merges the groups of labels that are the same after [`codesync::normalize_separators`]. A merged
+/// group keeps the first spelling in sorted order.
+fn merge_separator_spellings<'a>(
+    groups: BTreeMap<String, Vec<Comment<'a>>>,
+) -> BTreeMap<String, Vec<Comment<'a>>> {
+    let mut by_key: BTreeMap<String, (String, Vec<Comment>)> = BTreeMap::new();
+    for (label, comments) in groups {
+        by_key
+            .entry(codesync::normalize_separators(&label))
+            .or_insert_with(|| (label, vec![]))
+            .1
+            .extend(comments);
+    }
+    by_key.into_values().collect()
+}
+
+/// Truncate a label to [`MAX_DISPLAYED_LABEL_LEN`] characters, marking the cut with an ellipsis,
+/// so messages stay readable. The full label is still shown in the source snippet and included
+/// as the `text` of labels in structured output.
+fn truncate_label(label: &str) -> Cow<'_, str> {
+    match label.char_indices().nth(MAX_DISPLAYED_LABEL_LEN) {
+        Some((i, _)) => Cow::Owned(format!("{}…", &label[..i])),
+        None => Cow::Borrowed(label),
     }
 }
 
@@ -446,3 +3707,471 @@ fn pluralize(word: &str, count: usize) -> String {
         format!("{word}s")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_toml() {
+        let settings = json::Value::object([
+            ("path", "./".into()),
+            ("max_depth", json::Value::Null),
+            ("vars", json::Value::object([("N", 3u16.into())])),
+            ("include", json::Value::array(["*.rs".into()])),
+            (
+                "keywords",
+                json::Value::object([("**/*.md", "DOCSYNC".into())]),
+            ),
+        ]);
+        assert_eq!(
+            to_toml(&settings),
+            "path = \"./\"\ninclude = [\"*.rs\"]\n\n[vars]\nN = 3\n\n[keywords]\n\"**/*.md\" = \"DOCSYNC\"\n"
+        );
+    }
+
+    #[test]
+    fn mixed_separators() {
+        let source = "// CODESYNC(my_label)\n// CODESYNC(my-label)\n// CODESYNC(My-Label)\n";
+        let matches = Matches::from_buffer(Path::new("a.rs"), source);
+        let groups = matches
+            .group_by_label()
+            .into_iter()
+            .map(|(label, comments)| (label.to_string(), comments))
+            .collect();
+        let merged = merge_separator_spellings(groups);
+        let counts: Vec<_> = merged
+            .iter()
+            .map(|(label, comments)| (label.as_str(), comments.len()))
+            .collect();
+        assert_eq!(counts, [("My-Label", 1), ("my-label", 2)]);
+        let spellings: Vec<_> = merged["my-label"].iter().map(|c| c.label()).collect();
+        assert_eq!(spellings, ["my-label", "my_label"]);
+    }
+
+    #[test]
+    fn unwritable_fixed_files() {
+        let matches = Matches::from_buffer(Path::new("a.rs"), "// CODESYNC(a\n");
+        let (_, source) = matches.sources().next().unwrap();
+        assert_eq!(unwritable_reason(&source, b"// CODESYNC(a\n"), None);
+        assert_eq!(
+            unwritable_reason("?// CODESYNC(a\n", b"\xe9// CODESYNC(a\n"),
+            Some("not valid UTF-8")
+        );
+        assert_eq!(
+            unwritable_reason(&source, b"// CODESYNC(b\n"),
+            Some("changed since it was searched")
+        );
+    }
+
+    #[test]
+    fn prompt_answers() {
+        let mut stdin = "  y \n    // CODESYNC(a, 2)  \r\n".as_bytes();
+        assert_eq!(prompt(&mut stdin, "").unwrap(), "y");
+        assert_eq!(
+            prompt_line(&mut stdin, "").unwrap(),
+            "    // CODESYNC(a, 2)  "
+        );
+    }
+
+    #[test]
+    fn rule_stats_by_frequency() {
+        let codes = HashMap::from([
+            ("malformed".to_string(), 1),
+            ("incorrect-count".to_string(), 3),
+            ("extra-whitespace".to_string(), 1),
+        ]);
+        assert_eq!(
+            rule_stats(&codes),
+            [
+                ("incorrect-count", 3),
+                ("extra-whitespace", 1),
+                ("malformed", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn dump_rows() {
+        let a = Matches::from_buffer(Path::new("b.rs"), "// CODESYNC(foo, $N)\n// CODESYNC(\n");
+        let b = Matches::from_buffer(Path::new("a.rs"), "// CODESYNC(a\tb)\n");
+        let matches = a.merge(b);
+        let mut out = vec![];
+        dump_matches(&matches, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "file\tbyte_start\tbyte_end\tlabel\tcount\tvalid\n\
+             a.rs\t3\t16\ta\\tb\t\ttrue\n\
+             b.rs\t3\t20\tfoo\t$N\ttrue\n\
+             b.rs\t24\t32\t\t\tfalse\n"
+        );
+        assert_eq!(escape_tsv("a\\b\r\n"), "a\\\\b\\r\\n");
+    }
+
+    #[test]
+    fn count_tolerance() {
+        let mismatch = |expected, found| CountCheck::Mismatch { expected, found };
+        assert!(within_tolerance(&mismatch(3, 2), 1));
+        assert!(within_tolerance(&mismatch(3, 4), 1));
+        assert!(!within_tolerance(&mismatch(3, 5), 1));
+        assert!(!within_tolerance(&mismatch(3, 2), 0));
+        assert!(!within_tolerance(&CountCheck::Conflicting, 10));
+    }
+
+    #[test]
+    fn replace_hook() {
+        assert!(can_replace_hook(None, false));
+        assert!(can_replace_hook(Some(PRE_COMMIT_HOOK), false));
+        let reformatted = "#!/usr/bin/env sh\n\n# Installed by `codesync install-hook`\n\
+                           exec codesync check --git-tracked\n";
+        assert!(can_replace_hook(Some(reformatted), false));
+        let pasted = format!("#!/bin/sh\ncargo test || exit 1\n{PRE_COMMIT_HOOK}");
+        assert!(!can_replace_hook(Some(&pasted), false));
+        let edited = PRE_COMMIT_HOOK.replace("--git-tracked", "--staged");
+        assert!(!can_replace_hook(Some(&edited), false));
+        assert!(can_replace_hook(Some(&pasted), true));
+        let custom = "#!/bin/sh\ncargo test\n";
+        assert!(!can_replace_hook(Some(custom), false));
+        assert!(can_replace_hook(Some(custom), true));
+    }
+
+    #[test]
+    fn show_count_header() {
+        let expected = |total, explicit| Some(ExpectedCount { total, explicit });
+        let header = count_header("foo", expected(2, true), 2);
+        assert_eq!(header.severity, Severity::Note);
+        assert_eq!(header.message, "label `foo`: expected 2, found 2");
+        let header = count_header("foo", expected(2, false), 3);
+        assert_eq!(header.severity, Severity::Warning);
+        assert_eq!(header.message, "label `foo`: expected 2 (default), found 3");
+        let header = count_header("foo", None, 3);
+        assert_eq!(header.severity, Severity::Warning);
+        assert_eq!(
+            header.message,
+            "label `foo`: comments specify different counts, found 3"
+        );
+    }
+
+    #[test]
+    fn parse_count_files() {
+        let counts = parse_count_file("# generated\nfoo=3\n\n bar/baz = 0 \n").unwrap();
+        assert_eq!(
+            counts,
+            HashMap::from([("foo".into(), 3), ("bar/baz".into(), 0)])
+        );
+        let counts = parse_count_file(r#"{"foo": 3, "a=b": 1}"#).unwrap();
+        assert_eq!(
+            counts,
+            HashMap::from([("foo".into(), 3), ("a=b".into(), 1)])
+        );
+
+        assert_eq!(
+            parse_count_file("foo=3\nbar\n").unwrap_err(),
+            "line 2: expected `label=count`, found `bar`"
+        );
+        assert_eq!(
+            parse_count_file("foo=-1\n").unwrap_err(),
+            "line 1: invalid count `-1`, expected an integer between 0 and 65535"
+        );
+        assert!(parse_count_file(r#"{"foo": "3"}"#).is_err());
+        assert!(parse_count_file(r#"{"foo": 70000}"#).is_err());
+        assert!(parse_count_file("{").is_err());
+    }
+
+    #[test]
+    fn count_report_threshold() {
+        let mismatch = |expected, found| CountCheck::Mismatch { expected, found };
+        assert!(below_threshold(&mismatch(3, 2), 2));
+        assert!(below_threshold(&mismatch(3, 4), 2));
+        assert!(!below_threshold(&mismatch(3, 5), 2));
+        assert!(!below_threshold(&mismatch(3, 2), 0));
+        assert!(!below_threshold(&CountCheck::Conflicting, 10));
+    }
+
+    #[test]
+    fn truncate_labels() {
+        let label = "a".repeat(MAX_DISPLAYED_LABEL_LEN);
+        assert_eq!(truncate_label(&label), label);
+        let long = format!("{label}b");
+        assert_eq!(truncate_label(&long), format!("{label}…"));
+        // Never splits a multi-byte character
+        let wide = "é".repeat(MAX_DISPLAYED_LABEL_LEN + 1);
+        assert_eq!(
+            truncate_label(&wide),
+            format!("{}…", "é".repeat(MAX_DISPLAYED_LABEL_LEN))
+        );
+    }
+
+    /// Check that `value` only has the properties described in `schema` and all required ones.
+    fn assert_matches_schema(value: &json::Value, schema: &json::Value) {
+        let json::Value::Object(fields) = value else {
+            panic!("expected an object, found {value}");
+        };
+        let properties = schema.get("properties").unwrap();
+        for (key, field) in fields {
+            let property = properties
+                .get(key)
+                .unwrap_or_else(|| panic!("`{key}` is missing from the schema"));
+            if let Some(items) = property.get("items") {
+                for item in field.as_array().unwrap() {
+                    if items.get("properties").is_some() {
+                        assert_matches_schema(item, items);
+                    }
+                }
+            }
+            if let Some(values) = property.get("enum") {
+                assert!(values.as_array().unwrap().contains(field), "{key}: {field}");
+            }
+        }
+        for key in schema.get("required").unwrap().as_array().unwrap() {
+            assert!(
+                value.get(key.as_str().unwrap()).is_some(),
+                "`{key}` is missing"
+            );
+        }
+    }
+
+    #[test]
+    fn count_distribution() {
+        let matches = Matches::from_buffer(
+            Path::new("a.rs"),
+            "// CODESYNC(a)\n// CODESYNC(a)\n// CODESYNC(b)\n// CODESYNC(c)\n",
+        );
+        let distribution = count_distribution_of(&matches);
+        assert_eq!(distribution, BTreeMap::from([(1, 2), (2, 1)]));
+    }
+
+    #[test]
+    fn dirty_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let (clean, dirty) = (dir.path().join("clean.rs"), dir.path().join("dirty.rs"));
+        std::fs::write(&clean, "").unwrap();
+        std::fs::write(&dirty, "").unwrap();
+        // Spelled differently than the files found
+        let changed = [dir.path().join("./dirty.rs"), dir.path().join("deleted.rs")];
+
+        let filter = DirtyFilter::new(changed.clone(), false);
+        assert_eq!(filter.skip_reason(&clean), None);
+        assert_eq!(filter.skip_reason(&dirty), Some("has uncommitted changes"));
+        let filter = DirtyFilter::new(changed, true);
+        assert_eq!(
+            filter.skip_reason(&clean),
+            Some("has no uncommitted changes")
+        );
+        assert_eq!(filter.skip_reason(&dirty), None);
+    }
+
+    #[test]
+    fn anchor_first_comment() {
+        let a = Matches::from_buffer(Path::new("a.rs"), "x\n// CODESYNC(l, 3)\n");
+        let b = Matches::from_buffer(Path::new("b.rs"), "// CODESYNC(l)\n// CODESYNC(l)\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&a);
+        db.add_sources(&b);
+        // Out of order on purpose
+        let comments: Vec<_> = b.comments().chain(a.comments()).collect();
+        let styles = |db: &mut FilesDB| {
+            let diagnostic = db.mismatched_counts_diagnostic(&comments, "").unwrap();
+            let files = &db.files;
+            diagnostic
+                .labels
+                .iter()
+                .map(|l| (files.name(l.file_id).unwrap(), l.range.start, l.style))
+                .collect::<Vec<_>>()
+        };
+        assert!(styles(&mut db)
+            .iter()
+            .all(|(_, _, style)| *style == LabelStyle::Primary));
+
+        db.anchor = Anchor::First;
+        assert_eq!(
+            styles(&mut db),
+            [
+                ("a.rs".to_string(), 5, LabelStyle::Primary),
+                ("b.rs".to_string(), 3, LabelStyle::Secondary),
+                ("b.rs".to_string(), 18, LabelStyle::Secondary),
+            ]
+        );
+    }
+
+    #[test]
+    fn max_labels_per_diagnostic() {
+        let source = "// CODESYNC(a, 5)\n".repeat(3);
+        let matches = Matches::from_buffer(Path::new("a.rs"), &source);
+        let comments = &matches.group_by_label()["a"];
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let diagnostic = db.mismatched_counts_diagnostic(comments, "").unwrap();
+        assert_eq!(diagnostic.labels.len(), 3);
+        assert!(diagnostic.notes.is_empty());
+
+        db.max_labels = Some(2);
+        let diagnostic = db.mismatched_counts_diagnostic(comments, "").unwrap();
+        assert_eq!(diagnostic.labels.len(), 2);
+        assert_eq!(
+            diagnostic.notes,
+            ["... and 1 more, see `--max-labels-per-diagnostic`"]
+        );
+    }
+
+    #[test]
+    fn count_derivations() {
+        let derivation = |source: &str, semantics, default| {
+            let matches = Matches::from_buffer(Path::new("a.rs"), source);
+            let vars = HashMap::from([("N".to_string(), 4)]);
+            count_derivation(&matches.group_by_label()["a"], &vars, semantics, default)
+        };
+        let total = CountSemantics::Total;
+        assert_eq!(
+            derivation("// CODESYNC(a)\n", total, None),
+            "expected 2, the default"
+        );
+        assert_eq!(
+            derivation("// CODESYNC(a)\n", total, Some(5)),
+            "expected 5, from `--count-from-file`"
+        );
+        assert_eq!(
+            derivation("// CODESYNC(a, $N)\n// CODESYNC(a)\n", total, Some(5)),
+            "expected 4, from an explicit count"
+        );
+        assert_eq!(
+            derivation("// CODESYNC(a, 2)\n", CountSemantics::Others, None),
+            "expected 3, from an explicit count of 2 other comments"
+        );
+        assert_eq!(
+            derivation("// CODESYNC(a, 3)\n// CODESYNC(a, 2)\n", total, None),
+            "no expected count, explicit counts conflict: 2, 3"
+        );
+    }
+
+    #[test]
+    fn similar_label_hints() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("my-label", "my_label"), 1);
+        assert_eq!(edit_distance("naïve", "naive"), 1);
+
+        let labels = ["foo", "foo-bar", "foo-baz", "fooo-bar", "other", "foo-bar"];
+        assert_eq!(similar_labels("foo-bar", &labels), ["foo-baz", "fooo-bar"]);
+        assert_eq!(
+            collision_hint("other", &labels),
+            "such a large difference may mean that a typo made unrelated comments use this label"
+        );
+        assert!(collision_hint("fo", &labels).ends_with(" use this label, similar labels: `foo`"));
+    }
+
+    #[test]
+    fn labels_hashes() {
+        let hash = |source: &str| {
+            let matches = Matches::from_buffer(Path::new("a.rs"), source);
+            labels_hash(&matches, &HashMap::new())
+        };
+        let base = hash("// CODESYNC(a, 2)\n// CODESYNC(a)\n// CODESYNC(b)\n");
+        assert_eq!(base, 0x530252e83b63fc3f);
+        // Positions, comment syntax, and surrounding code don't matter
+        assert_eq!(
+            base,
+            hash("fn f() {}\n# CODESYNC(b)\n\n/* CODESYNC(a) */\n    // CODESYNC(a, 2)\n")
+        );
+        assert_ne!(
+            base,
+            hash("// CODESYNC(a, 3)\n// CODESYNC(a)\n// CODESYNC(b)\n")
+        );
+        assert_ne!(
+            base,
+            hash("// CODESYNC(a, 2)\n// CODESYNC(a)\n// CODESYNC(c)\n")
+        );
+        assert_ne!(base, hash("// CODESYNC(a, 2)\n// CODESYNC(a)\n"));
+    }
+
+    #[test]
+    fn reads_sources_from_file_system() {
+        let mut fs = codesync::vfs::MemoryFileSystem::new();
+        fs.insert("a.rs", "fn a() {}\n// CODESYNC(a)\n");
+        let mut db = FilesDB::with_file_system(Box::new(fs));
+        let location = db.location(Path::new("a.rs"), 13).unwrap();
+        assert_eq!(location.to_string(), "a.rs:2:4");
+        assert!(db.location(Path::new("b.rs"), 0).is_err());
+    }
+
+    #[test]
+    fn screaming_kebab_casing() {
+        let args = CasingArgs {
+            consistent_casing: Some(Case::from_str("screaming-kebab", true).unwrap()),
+            digit_boundary: DigitBoundary::Keep,
+            acronym: vec![],
+            small_word: vec![],
+        };
+        let casing = args.casing().unwrap();
+        assert!(casing.has_case("FOO-BAR"));
+        assert!(!casing.has_case("foo-bar"));
+
+        let matches = Matches::from_buffer(Path::new("a.rs"), "// CODESYNC(foo_bar)\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let comment = matches.comments().next().unwrap();
+        let diagnostic = db.invalid_case_diagnostic(comment, &casing).unwrap();
+        assert_eq!(diagnostic.message, "label doesn't use cobol case");
+        assert_eq!(diagnostic.labels[0].message, "should be written as FOO-BAR");
+    }
+
+    #[test]
+    fn relative_display_names() {
+        let path = Path::new("/repo/src/a.rs");
+        let matches = Matches::from_buffer(path, "// CODESYNC()\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let comment = matches.comments().next().unwrap();
+        let diagnostic = db.empty_label_diagnostic(comment).unwrap();
+        let file = |db: &FilesDB| {
+            let json = db.diagnostic_to_json(&diagnostic).unwrap();
+            let label = &json.get("labels").unwrap().as_array().unwrap()[0];
+            label.get("file").unwrap().as_str().unwrap().to_string()
+        };
+        assert_eq!(file(&db), "/repo/src/a.rs");
+        db.display_base = Some(PathBuf::from("/repo"));
+        assert_eq!(file(&db), "src/a.rs");
+        db.display_base = Some(PathBuf::from("/repo/src/"));
+        assert_eq!(file(&db), "a.rs");
+    }
+
+    #[test]
+    fn json_label_locations() {
+        let matches = Matches::from_buffer(Path::new("a.rs"), "é\n/* ü */ // CODESYNC()\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let comment = matches.comments().next().unwrap();
+        let diagnostic = db.empty_label_diagnostic(comment).unwrap();
+        let json = db.diagnostic_to_json(&diagnostic).unwrap();
+        let label = &json.get("labels").unwrap().as_array().unwrap()[0];
+        let field = |name| label.get(name).unwrap().to_string();
+        assert_eq!(field("start"), "24");
+        assert_eq!(
+            ["line", "column", "end_line", "end_column"].map(field),
+            ["2", "21", "2", "21"]
+        );
+    }
+
+    #[test]
+    fn jsonl_schema_matches_output() {
+        let schema = report::Jsonl::schema(explain::codes());
+        let matches = Matches::from_buffer(Path::new("a.rs"), "// CODESYNC()\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let comment = matches.comments().next().unwrap();
+        let diagnostic = db.empty_label_diagnostic(comment).unwrap();
+        assert_matches_schema(&db.diagnostic_to_json(&diagnostic).unwrap(), &schema);
+
+        let diagnostic = Diagnostic::note().with_notes(vec!["note".to_string()]);
+        assert_matches_schema(&db.diagnostic_to_json(&diagnostic).unwrap(), &schema);
+    }
+
+    #[test]
+    fn label_length_boundary() {
+        assert_eq!(label_too_long("abcd", 4), None);
+        assert_eq!(label_too_long("abcde", 4), Some(5));
+        // Characters are counted, not bytes
+        assert_eq!(label_too_long("día", 3), None);
+        assert_eq!(label_too_long("", 0), None);
+    }
+}
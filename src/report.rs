@@ -0,0 +1,671 @@
+//! Backends printing diagnostics in each of the formats selected with `--format`.
+//!
+//! Checks produce [`Diagnostic`]s independently of the output format. A diagnostic's code,
+//! severity, message, labels, and notes are enough for every backend, and spans are resolved to
+//! paths and lines through the [`FilesDB`] passed alongside it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label, LabelStyle, Severity},
+    files::{self, Files},
+    term::{self, termcolor::WriteColor},
+};
+
+use crate::{json, FileId, FilesDB};
+
+/// An output backend for diagnostics.
+pub trait Reporter {
+    fn report(&mut self, db: &FilesDB, diagnostic: &Diagnostic<FileId>)
+        -> Result<(), files::Error>;
+
+    /// Print anything buffered and flush the output. Called once after all diagnostics have been
+    /// reported.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Human readable diagnostics rendered with `codespan_reporting`.
+pub struct Human {
+    writer: Box<dyn WriteColor>,
+    config: term::Config,
+}
+
+impl Human {
+    pub fn new(writer: Box<dyn WriteColor>) -> Self {
+        Self {
+            writer,
+            config: term::Config::default(),
+        }
+    }
+}
+
+impl Reporter for Human {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), files::Error> {
+        term::emit(&mut *self.writer, &self.config, &db.files, diagnostic)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// One JSON object per diagnostic printed as soon as it's reported.
+pub struct Jsonl {
+    writer: Box<dyn WriteColor>,
+}
+
+impl Jsonl {
+    pub fn new(writer: Box<dyn WriteColor>) -> Self {
+        Self { writer }
+    }
+
+    /// A [JSON Schema](https://json-schema.org) describing each printed object. `codes` are the
+    /// possible values of the `code` field.
+    pub fn schema(codes: impl IntoIterator<Item = &'static str>) -> json::Value {
+        let label = closed_object(vec![
+            ("file", typed("string", "Path of the file")),
+            (
+                "start",
+                typed("integer", "Byte offset of the start of the span"),
+            ),
+            (
+                "end",
+                typed("integer", "Byte offset of the end of the span (exclusive)"),
+            ),
+            (
+                "line",
+                typed("integer", "1-based line of the start of the span"),
+            ),
+            (
+                "column",
+                typed(
+                    "integer",
+                    "1-based column of the start of the span, counting Unicode scalar values",
+                ),
+            ),
+            (
+                "end_line",
+                typed("integer", "1-based line of the end of the span"),
+            ),
+            (
+                "end_column",
+                typed(
+                    "integer",
+                    "1-based column after the end of the span, counting Unicode scalar values",
+                ),
+            ),
+            ("text", typed("string", "Source text under the span")),
+            (
+                "message",
+                typed("string", "Message attached to the span, possibly empty"),
+            ),
+        ]);
+        let severities = ["bug", "error", "warning", "note", "help"].map(json::Value::from);
+        let codes = json::Value::array(
+            codes
+                .into_iter()
+                .map(json::Value::from)
+                .chain([json::Value::Null]),
+        );
+        let diagnostic = closed_object(vec![
+            (
+                "severity",
+                json::Value::object([("enum", json::Value::array(severities))]),
+            ),
+            ("code", json::Value::object([("enum", codes)])),
+            ("message", typed("string", "Main message of the diagnostic")),
+            (
+                "labels",
+                json::Value::object([("type", "array".into()), ("items", label)]),
+            ),
+            (
+                "notes",
+                json::Value::object([
+                    ("type", "array".into()),
+                    ("items", typed("string", "Additional note")),
+                ]),
+            ),
+        ]);
+        let json::Value::Object(fields) = diagnostic else {
+            unreachable!("`closed_object` returns an object")
+        };
+        json::Value::object(
+            [
+                (
+                    "$schema",
+                    "https://json-schema.org/draft/2020-12/schema".into(),
+                ),
+                ("title", "codesync diagnostic".into()),
+                (
+                    "description",
+                    "A diagnostic printed by `codesync check --format jsonl`, one per line".into(),
+                ),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .chain(fields),
+        )
+    }
+}
+
+/// The schema of a value of JSON type `ty`.
+fn typed(ty: &str, description: &str) -> json::Value {
+    json::Value::object([("type", ty.into()), ("description", description.into())])
+}
+
+/// The schema of an object with exactly the given properties.
+fn closed_object(properties: Vec<(&str, json::Value)>) -> json::Value {
+    let required = json::Value::array(properties.iter().map(|(name, _)| (*name).into()));
+    json::Value::object([
+        ("type", "object".into()),
+        ("properties", json::Value::object(properties)),
+        ("required", required),
+        ("additionalProperties", false.into()),
+    ])
+}
+
+impl Reporter for Jsonl {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), files::Error> {
+        let value = db.diagnostic_to_json(diagnostic)?;
+        writeln!(self.writer, "{value}")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// One line per diagnostic in the style of GNU tools, `file:line:col: error: message [code]`,
+/// which editors' default error formats parse out of the box. Every other location of the
+/// diagnostic follows as a `note:` line. Notes without a location are left out.
+pub struct Gnu {
+    writer: Box<dyn WriteColor>,
+}
+
+impl Gnu {
+    pub fn new(writer: Box<dyn WriteColor>) -> Self {
+        Self { writer }
+    }
+}
+
+impl Reporter for Gnu {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), files::Error> {
+        let severity = match diagnostic.severity {
+            Severity::Bug | Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note | Severity::Help => "note",
+        };
+        let code = match &diagnostic.code {
+            Some(code) => format!(" [{code}]"),
+            None => String::new(),
+        };
+        let primary = diagnostic
+            .labels
+            .iter()
+            .position(|label| label.style == LabelStyle::Primary)
+            .unwrap_or(0);
+        let Some(label) = diagnostic.labels.get(primary) else {
+            writeln!(
+                self.writer,
+                "codesync: {severity}: {}{code}",
+                diagnostic.message
+            )?;
+            return Ok(());
+        };
+        writeln!(
+            self.writer,
+            "{}: {severity}: {}{code}",
+            gnu_location(db, label)?,
+            diagnostic.message
+        )?;
+        for (_, label) in diagnostic
+            .labels
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != primary)
+        {
+            let message = if label.message.is_empty() {
+                &diagnostic.message
+            } else {
+                &label.message
+            };
+            writeln!(self.writer, "{}: note: {message}", gnu_location(db, label)?)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The `file:line:col` of the start of `label`, with 1-based lines and columns.
+fn gnu_location(db: &FilesDB, label: &Label<FileId>) -> Result<String, files::Error> {
+    let name = db.display_name(label.file_id)?;
+    let location = db.files.location(label.file_id, label.range.start)?;
+    Ok(format!(
+        "{name}:{}:{}",
+        location.line_number, location.column_number
+    ))
+}
+
+/// A Code Climate report. Issues are buffered and printed as a single array when finishing.
+pub struct CodeClimate {
+    writer: Box<dyn WriteColor>,
+    issues: Vec<json::Value>,
+    /// Number of issues seen so far for each fingerprint key, used to tell apart otherwise
+    /// identical issues.
+    occurrences: HashMap<String, usize>,
+    /// Whether the report is indented for reading instead of printed on a single line.
+    pretty: bool,
+}
+
+impl CodeClimate {
+    pub fn new(writer: Box<dyn WriteColor>) -> Self {
+        Self {
+            writer,
+            issues: vec![],
+            occurrences: HashMap::new(),
+            pretty: false,
+        }
+    }
+
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+}
+
+impl Reporter for CodeClimate {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), files::Error> {
+        let key = db.fingerprint_key(diagnostic)?;
+        let occurrence = self.occurrences.entry(key.clone()).or_default();
+        let fingerprint = fingerprint(&format!("{key}\0{occurrence}"));
+        *occurrence += 1;
+        self.issues
+            .push(db.diagnostic_to_codeclimate(diagnostic, fingerprint)?);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let issues = json::Value::Array(std::mem::take(&mut self.issues));
+        if self.pretty {
+            writeln!(self.writer, "{issues:#}")?;
+        } else {
+            writeln!(self.writer, "{issues}")?;
+        }
+        self.writer.flush()
+    }
+}
+
+/// The [`codesync::stable_hash`] of `key` in hex.
+fn fingerprint(key: &str) -> String {
+    format!("{:016x}", codesync::stable_hash([key]))
+}
+
+/// A JUnit XML report with one test case per file with diagnostics. Errors are reported as
+/// failures and diagnostics of lower severity as output of the test case. If there are no
+/// diagnostics a single passing test case is printed so the run still shows up.
+pub struct Junit {
+    writer: Box<dyn WriteColor>,
+    cases: Vec<TestCase>,
+}
+
+/// Test case named after a file, or `codesync` for diagnostics without labels.
+struct TestCase {
+    name: String,
+    failures: Vec<String>,
+    output: Vec<String>,
+}
+
+impl Junit {
+    pub fn new(writer: Box<dyn WriteColor>) -> Self {
+        Self {
+            writer,
+            cases: vec![],
+        }
+    }
+}
+
+impl Reporter for Junit {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), files::Error> {
+        let (name, location) = match diagnostic.labels.first() {
+            Some(label) => {
+                let name = db.display_name(label.file_id)?;
+                let location = db.files.location(label.file_id, label.range.start)?;
+                let location =
+                    format!("{name}:{}:{}", location.line_number, location.column_number);
+                (name, Some(location))
+            }
+            None => ("codesync".to_string(), None),
+        };
+        let mut text = match location {
+            Some(location) => format!("{location}: {}", diagnostic.message),
+            None => diagnostic.message.clone(),
+        };
+        for note in &diagnostic.notes {
+            text.push_str("\n= ");
+            text.push_str(note);
+        }
+
+        let case = match self.cases.iter_mut().find(|case| case.name == name) {
+            Some(case) => case,
+            None => {
+                self.cases.push(TestCase {
+                    name,
+                    failures: vec![],
+                    output: vec![],
+                });
+                self.cases.last_mut().unwrap()
+            }
+        };
+        if diagnostic.severity >= Severity::Error {
+            case.failures.push(format!(
+                "<failure type=\"{}\" message=\"{}\">{}</failure>",
+                escape_xml(diagnostic.code.as_deref().unwrap_or("error")),
+                escape_xml(&diagnostic.message),
+                escape_xml(&text),
+            ));
+        } else {
+            case.output.push(text);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.cases.is_empty() {
+            self.cases.push(TestCase {
+                name: "codesync".to_string(),
+                failures: vec![],
+                output: vec![],
+            });
+        }
+        let tests = self.cases.len();
+        let failures = self
+            .cases
+            .iter()
+            .filter(|case| !case.failures.is_empty())
+            .count();
+        let w = &mut self.writer;
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<testsuites name="codesync" tests="{tests}" failures="{failures}">"#
+        )?;
+        writeln!(
+            w,
+            r#"  <testsuite name="codesync" tests="{tests}" failures="{failures}">"#
+        )?;
+        for case in &self.cases {
+            let name = escape_xml(&case.name);
+            if case.failures.is_empty() && case.output.is_empty() {
+                writeln!(w, r#"    <testcase name="{name}" classname="codesync"/>"#)?;
+                continue;
+            }
+            writeln!(w, r#"    <testcase name="{name}" classname="codesync">"#)?;
+            for failure in &case.failures {
+                writeln!(w, "      {failure}")?;
+            }
+            if !case.output.is_empty() {
+                let output = escape_xml(&case.output.join("\n"));
+                writeln!(w, "      <system-out>{output}</system-out>")?;
+            }
+            writeln!(w, "    </testcase>")?;
+        }
+        writeln!(w, "  </testsuite>")?;
+        writeln!(w, "</testsuites>")?;
+        w.flush()
+    }
+}
+
+/// Only the number of diagnostics by code and by file, printed when finishing.
+pub struct Summary {
+    writer: Box<dyn WriteColor>,
+    /// Number of diagnostics by severity and code.
+    codes: HashMap<(Severity, String), usize>,
+    /// Number of diagnostics by the file of their first label.
+    files: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    pub fn new(writer: Box<dyn WriteColor>) -> Self {
+        Self {
+            writer,
+            codes: HashMap::new(),
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl Reporter for Summary {
+    fn report(
+        &mut self,
+        db: &FilesDB,
+        diagnostic: &Diagnostic<FileId>,
+    ) -> Result<(), files::Error> {
+        let code = diagnostic.code.clone().unwrap_or_default();
+        *self.codes.entry((diagnostic.severity, code)).or_default() += 1;
+        if let Some(label) = diagnostic.labels.first() {
+            *self.files.entry(db.files.name(label.file_id)?).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let w = &mut self.writer;
+        let mut codes: Vec<_> = self.codes.iter().collect();
+        // Most severe first
+        codes.sort_by(|((a, a_code), _), ((b, b_code), _)| {
+            b.partial_cmp(a).unwrap().then_with(|| a_code.cmp(b_code))
+        });
+        for ((severity, code), n) in codes {
+            let severity = crate::severity_name(*severity);
+            match code.as_str() {
+                "" => writeln!(w, "{severity}: {n}")?,
+                code => writeln!(w, "{severity}[{code}]: {n}")?,
+            }
+        }
+        if !self.files.is_empty() {
+            writeln!(w)?;
+            for (file, n) in &self.files {
+                writeln!(w, "{file}: {n}")?;
+            }
+        }
+        let count = |pred: fn(Severity) -> bool| -> usize {
+            self.codes
+                .iter()
+                .filter(|((severity, _), _)| pred(*severity))
+                .map(|(_, n)| n)
+                .sum()
+        };
+        let errors = count(|severity| severity >= Severity::Error);
+        let warnings = count(|severity| severity == Severity::Warning);
+        if !self.codes.is_empty() {
+            writeln!(w)?;
+        }
+        writeln!(
+            w,
+            "found {errors} {} and {warnings} {}",
+            crate::pluralize("error", errors),
+            crate::pluralize("warning", warnings),
+        )?;
+        w.flush()
+    }
+}
+
+/// Escape `s` for use in XML text and attribute values. Characters that aren't allowed in XML
+/// 1.0 documents are replaced with U+FFFD.
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || matches!(c, '\u{FFFE}' | '\u{FFFF}') => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io::Write, path::Path, rc::Rc};
+
+    use codespan_reporting::term::termcolor::NoColor;
+    use codesync::{vfs::MemoryFileSystem, CollectOptions, Matches};
+
+    use super::*;
+
+    /// A writer whose output can still be read after it's handed to a reporter.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn gnu_output() {
+        let path = Path::new("src/a.rs");
+        let matches =
+            Matches::from_buffer(path, "fn a() {}\n  // CODESYNC(a, 3)\n// CODESYNC(a)\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let buffer = SharedBuffer::default();
+        let mut gnu = Gnu::new(Box::new(NoColor::new(buffer.clone())));
+
+        let error = Diagnostic::error()
+            .with_message("expected 3 comments with label `a`, found 2")
+            .with_code("incorrect-count")
+            .with_labels(vec![
+                db.label(path, 15..29).unwrap(),
+                db.secondary_label(path, 33..44)
+                    .unwrap()
+                    .with_message("also labeled `a`"),
+            ])
+            .with_notes(vec!["left out".to_string()]);
+        gnu.report(&db, &error).unwrap();
+        let warning = Diagnostic::warning()
+            .with_message("label `a` is deprecated")
+            .with_code("deprecated-label")
+            .with_labels(vec![db.label(path, 33..44).unwrap()]);
+        gnu.report(&db, &warning).unwrap();
+        let unlocated = Diagnostic::error()
+            .with_message("no codesync comments found")
+            .with_code("empty");
+        gnu.report(&db, &unlocated).unwrap();
+        gnu.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            "\
+src/a.rs:2:6: error: expected 3 comments with label `a`, found 2 [incorrect-count]
+src/a.rs:3:4: note: also labeled `a`
+src/a.rs:3:4: warning: label `a` is deprecated [deprecated-label]
+codesync: error: no codesync comments found [empty]
+"
+        );
+    }
+
+    #[test]
+    fn pretty_codeclimate() {
+        let path = Path::new("a.rs");
+        let matches = Matches::from_buffer(path, "// CODESYNC(a, 3)\n");
+        let mut db = FilesDB::new();
+        db.add_sources(&matches);
+        let diagnostic = Diagnostic::error()
+            .with_message("expected 3 comments with label `a`, found 1")
+            .with_code("incorrect-count")
+            .with_labels(vec![db.label(path, 3..17).unwrap()]);
+        let output = |pretty| {
+            let buffer = SharedBuffer::default();
+            let mut codeclimate =
+                CodeClimate::new(Box::new(NoColor::new(buffer.clone()))).pretty(pretty);
+            codeclimate.report(&db, &diagnostic).unwrap();
+            codeclimate.finish().unwrap();
+            String::from_utf8(buffer.0.take()).unwrap()
+        };
+
+        let compact = output(false);
+        assert_eq!(compact.lines().count(), 1);
+        let pretty = output(true);
+        assert!(pretty.starts_with("[\n  {\n    \"type\": \"issue\",\n"));
+        assert!(pretty.ends_with("\n  }\n]\n"));
+        assert_eq!(
+            json::Value::parse(&pretty).unwrap(),
+            json::Value::parse(&compact).unwrap()
+        );
+        assert_eq!(format!("{:#}", json::Value::array([])), "[]");
+    }
+
+    #[test]
+    fn codeclimate_fingerprints_ignore_working_directory() {
+        let mut fs = MemoryFileSystem::new();
+        fs.insert("proj/src/a.rs", "// CODESYNC(a, 3)\n");
+        fs.insert("/work/proj/src/a.rs", "// CODESYNC(a, 3)\n");
+        // The same project searched from `/work` and from `/`
+        let fingerprint = |root: &str| {
+            let matches = Matches::collect_from(&fs, &CollectOptions::new().root(root)).unwrap();
+            let comment = matches.comments().next().unwrap();
+            let mut db = FilesDB::new();
+            db.add_sources(&matches);
+            let diagnostic = Diagnostic::error()
+                .with_message("expected 3 comments with label `a`, found 1")
+                .with_code("incorrect-count")
+                .with_labels(vec![db.label(comment.file(), comment.span()).unwrap()]);
+            let buffer = SharedBuffer::default();
+            let mut codeclimate = CodeClimate::new(Box::new(NoColor::new(buffer.clone())));
+            codeclimate.report(&db, &diagnostic).unwrap();
+            codeclimate.finish().unwrap();
+            let issues = json::Value::parse(&String::from_utf8(buffer.0.take()).unwrap()).unwrap();
+            let issue = &issues.as_array().unwrap()[0];
+            issue.get("fingerprint").unwrap().clone()
+        };
+        assert_eq!(fingerprint("proj"), fingerprint("/work/proj"));
+        assert_ne!(fingerprint("proj"), fingerprint("proj/src"));
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">&'b'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;b&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape_xml("a\u{1}b\tc"), "a\u{FFFD}b\tc");
+    }
+}
@@ -0,0 +1,144 @@
+//! The file system as seen when collecting matches.
+//!
+//! Collection only needs to list the files under a root and read their contents, so both are
+//! behind the [`FileSystem`] trait. [`OsFileSystem`] is the real file system and
+//! [`MemoryFileSystem`] keeps files in memory, which is handy for tests.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::CollectOptions;
+
+/// A source of files to search.
+pub trait FileSystem: Sync {
+    /// Read the whole contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// List the files to search under `root`, or `root` itself if it's a file.
+    fn walk(&self, root: &Path, opts: &CollectOptions) -> Result<Vec<PathBuf>, ignore::Error>;
+
+    /// The path of the file at `path` with symbolic links resolved. Paths with the same canonical
+    /// path are the same file. Only needed when a file may be reached through more than one path,
+    /// see [`Matches::collect_from`](crate::Matches::collect_from). Defaults to `path` without `.`
+    /// components, which is enough for file systems without symbolic links.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(normalize(path))
+    }
+}
+
+/// The file system of the operating system. Walking respects the ignore rules and filters in
+/// [`CollectOptions`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn walk(&self, root: &Path, opts: &CollectOptions) -> Result<Vec<PathBuf>, ignore::Error> {
+        let metadata = fs::metadata(root)?;
+        if metadata.is_file() {
+            return Ok(vec![root.to_path_buf()]);
+        }
+        if !metadata.is_dir() {
+            let msg = format!("`{}` is neither a file nor a directory", root.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+        }
+        let mut paths = vec![];
+        for result in opts.walker(root)? {
+            let dir = result?;
+            if dir.file_type().is_some_and(|file_type| file_type.is_file()) {
+                paths.push(dir.into_path());
+            }
+        }
+        Ok(paths)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// A file system whose files are kept in memory. Paths are compared ignoring `.` components, but
+/// ignore rules and filters in [`CollectOptions`] aren't applied when walking.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFileSystem {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file replacing any previous one at the same path.
+    pub fn insert(&mut self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        self.files.insert(normalize(path.as_ref()), contents.into());
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn walk(&self, root: &Path, _: &CollectOptions) -> Result<Vec<PathBuf>, ignore::Error> {
+        let prefix = normalize(root);
+        if self.files.contains_key(&prefix) {
+            return Ok(vec![root.to_path_buf()]);
+        }
+        let paths: Vec<_> = self
+            .files
+            .keys()
+            .filter_map(|path| Some(root.join(path.strip_prefix(&prefix).ok()?)))
+            .collect();
+        if paths.is_empty() {
+            return Err(not_found(root).into());
+        }
+        Ok(paths)
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    let msg = format!("`{}` not found", path.display());
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_memory_file_system() {
+        let mut fs = MemoryFileSystem::new();
+        fs.insert("src/a.rs", "a");
+        fs.insert("./src/b/c.rs", "c");
+        fs.insert("docs/d.md", "d");
+        let opts = CollectOptions::new();
+
+        assert_eq!(
+            fs.walk(Path::new("./src"), &opts).unwrap(),
+            [Path::new("./src/a.rs"), Path::new("./src/b/c.rs")]
+        );
+        assert_eq!(
+            fs.walk(Path::new("docs/d.md"), &opts).unwrap(),
+            [Path::new("docs/d.md")]
+        );
+        assert!(fs.walk(Path::new("missing"), &opts).is_err());
+        assert_eq!(fs.read(Path::new("./docs/d.md")).unwrap(), b"d");
+        assert!(fs.read(Path::new("docs")).is_err());
+    }
+}
@@ -0,0 +1,603 @@
+use std::{fs, path::Path};
+
+use codesync::{config::Config, BinaryDetection, CollectOptions, CountCheck, Matches};
+use tempfile::TempDir;
+
+/// Create a temporary directory populated with the given `(path, contents)` fixtures.
+fn fixture(files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for (path, contents) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn relative<'a>(dir: &TempDir, path: &'a Path) -> &'a Path {
+    path.strip_prefix(dir.path()).unwrap()
+}
+
+/// Return `(label, file, byte_offset)` for every valid comment in deterministic order.
+fn comments(dir: &TempDir, matches: &Matches) -> Vec<(String, String, usize)> {
+    matches
+        .group_by_label()
+        .into_values()
+        .flatten()
+        .map(|c| {
+            let file = relative(dir, c.file()).display().to_string();
+            (c.label().to_string(), file, c.byte_offset())
+        })
+        .collect()
+}
+
+#[test]
+fn collects_comments_in_nested_directories() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("nested/b.rs", "fn main() {}\n// CODESYNC(foo)\n"),
+        ("nested/deeper/c.py", "# CODESYNC(bar, 1)\n"),
+    ]);
+    let matches = Matches::collect_in(dir.path()).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![
+            ("bar".to_string(), "nested/deeper/c.py".to_string(), 2),
+            ("foo".to_string(), "a.rs".to_string(), 3),
+            ("foo".to_string(), "nested/b.rs".to_string(), 16),
+        ]
+    );
+    assert_eq!(matches.invalid_matches().count(), 0);
+}
+
+#[test]
+fn collects_single_file() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("b.rs", "// CODESYNC(foo)\n"),
+    ]);
+    let matches = Matches::collect_in(dir.path().join("b.rs")).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![("foo".to_string(), "b.rs".to_string(), 3)]
+    );
+}
+
+#[test]
+fn fails_on_missing_root() {
+    let dir = fixture(&[]);
+    assert!(Matches::collect_in(dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn skips_gitignored_files() {
+    let dir = fixture(&[
+        (".gitignore", "ignored/\n"),
+        ("a.rs", "// CODESYNC(foo, 1)\n"),
+        ("ignored/b.rs", "// CODESYNC(foo, 1)\n"),
+    ]);
+    // `.gitignore` files are only honored inside a git repository
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    let matches = Matches::collect_in(dir.path()).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![("foo".to_string(), "a.rs".to_string(), 3)]
+    );
+}
+
+#[test]
+fn searches_files_reincluded_by_gitignore_negations() {
+    let dir = fixture(&[
+        (".gitignore", "*.generated.rs\n!keep.generated.rs\n"),
+        ("a.generated.rs", "// CODESYNC(foo, 1)\n"),
+        ("keep.generated.rs", "// CODESYNC(foo, 1)\n"),
+        ("nested/keep.generated.rs", "// CODESYNC(bar, 1)\n"),
+        ("nested/b.generated.rs", "// CODESYNC(bar, 1)\n"),
+    ]);
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    let matches = Matches::collect_in(dir.path()).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![
+            ("bar".to_string(), "nested/keep.generated.rs".to_string(), 3),
+            ("foo".to_string(), "keep.generated.rs".to_string(), 3),
+        ]
+    );
+}
+
+#[test]
+fn searches_ignored_files_without_standard_filters() {
+    let dir = fixture(&[
+        (".gitignore", "ignored/\n"),
+        (".git/config", "# CODESYNC(foo, 1)\n"),
+        (".hidden.rs", "// CODESYNC(foo, 1)\n"),
+        ("ignored/b.rs", "// CODESYNC(foo, 1)\n"),
+    ]);
+    let opts = CollectOptions::new()
+        .root(dir.path())
+        .include_hidden(true)
+        .no_ignore(true);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![
+            ("foo".to_string(), ".hidden.rs".to_string(), 3),
+            ("foo".to_string(), "ignored/b.rs".to_string(), 3),
+        ]
+    );
+}
+
+#[test]
+fn reports_invalid_matches() {
+    let dir = fixture(&[(
+        "a.rs",
+        "// CODESYNC\n// CODESYNC(foo, bar)\n// CODESYNC(foo, 1)\n",
+    )]);
+    let matches = Matches::collect_in(dir.path()).unwrap();
+
+    let spans: Vec<_> = matches.invalid_matches().map(|m| m.span()).collect();
+    assert_eq!(spans, vec![3..11, 15..23]);
+    assert_eq!(matches.comments().count(), 1);
+}
+
+#[test]
+fn checks_counts_per_label() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(ok)\n// CODESYNC(missing, 3)\n"),
+        ("b.rs", "// CODESYNC(ok)\n// CODESYNC(missing)\n"),
+        (
+            "c.rs",
+            "// CODESYNC(conflict, 1)\n// CODESYNC(conflict, 2)\n",
+        ),
+        ("d.rs", "// CODESYNC(var, $N)\n// CODESYNC(var)\n"),
+        ("codesync.toml", "[vars]\nN = 2\n"),
+    ]);
+    let config = Config::load(dir.path()).unwrap();
+    let matches = Matches::collect_in(dir.path()).unwrap();
+
+    let checks: Vec<_> = matches
+        .group_by_label()
+        .into_iter()
+        .map(|(label, comments)| (label, codesync::check_count(&comments, &config.vars)))
+        .collect();
+    assert_eq!(
+        checks,
+        vec![
+            ("conflict", CountCheck::Conflicting),
+            (
+                "missing",
+                CountCheck::Mismatch {
+                    expected: 3,
+                    found: 2
+                }
+            ),
+            ("ok", CountCheck::Ok),
+            ("var", CountCheck::Ok),
+        ]
+    );
+}
+
+#[test]
+fn collects_with_options() {
+    let dir = fixture(&[
+        ("a.rs", "// SYNC(foo)\n// SYNC(foo)\n"),
+        ("b.py", "# SYNC(foo)\n"),
+        ("c.rs", "// SYNC\n"),
+        ("large.rs", &format!("// SYNC(foo)\n{}", "x".repeat(1000))),
+    ]);
+    let opts = CollectOptions::new()
+        .root(dir.path())
+        .keyword("SYNC")
+        .types(["rust"])
+        .max_filesize(Some(100))
+        .threads(4);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![
+            ("foo".to_string(), "a.rs".to_string(), 3),
+            ("foo".to_string(), "a.rs".to_string(), 16),
+        ]
+    );
+    assert_eq!(matches.invalid_matches().count(), 1);
+}
+
+#[test]
+fn filters_paths_with_globs() {
+    let dir = fixture(&[
+        ("src/a.rs", "// CODESYNC(foo)\n"),
+        ("src/gen/b.rs", "// CODESYNC(foo)\n"),
+        ("src/c.min.js", "// CODESYNC(foo)\n"),
+        ("tests/d.rs", "// CODESYNC(foo)\n"),
+    ]);
+    let opts = CollectOptions::new()
+        .root(dir.path())
+        .include(["src/**"])
+        .exclude(["src/gen/**", "*.min.js"]);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![("foo".to_string(), "src/a.rs".to_string(), 3)]
+    );
+}
+
+#[test]
+fn limits_depth() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("one/b.rs", "// CODESYNC(foo)\n"),
+        ("one/two/c.rs", "// CODESYNC(foo)\n"),
+    ]);
+    let files = |depth| {
+        let opts = CollectOptions::new().root(dir.path()).max_depth(depth);
+        let matches = Matches::collect_with_options(&opts).unwrap();
+        comments(&dir, &matches)
+            .into_iter()
+            .map(|(_, file, _)| file)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(files(Some(0)), Vec::<String>::new());
+    assert_eq!(files(Some(1)), ["a.rs"]);
+    assert_eq!(files(Some(2)), ["a.rs", "one/b.rs"]);
+    assert_eq!(files(None), ["a.rs", "one/b.rs", "one/two/c.rs"]);
+}
+
+#[test]
+fn searches_listed_files_once() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("b.rs", "// CODESYNC(foo)\n"),
+        ("c.rs", "// CODESYNC(foo)\n"),
+    ]);
+    let a = dir.path().join("a.rs");
+    let c = dir.path().join("c.rs");
+    let opts = CollectOptions::new().roots([&c, &a, &c]);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+    let files: Vec<_> = comments(&dir, &matches)
+        .into_iter()
+        .map(|(_, file, _)| file)
+        .collect();
+    assert_eq!(files, ["a.rs", "c.rs"]);
+}
+
+#[test]
+fn searches_only_given_files() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("b.rs", "// CODESYNC(foo)\n"),
+        ("nested/c.rs", "// CODESYNC(foo)\n"),
+        ("nested/d.rs", "// CODESYNC(foo)\n"),
+    ]);
+    let only = [
+        dir.path().join("a.rs"),
+        dir.path().join("nested/./c.rs"),
+        dir.path().join("missing.rs"),
+    ];
+    let opts = CollectOptions::new().root(dir.path()).only_files(&only);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+    let files: Vec<_> = comments(&dir, &matches)
+        .into_iter()
+        .map(|(_, file, _)| file)
+        .collect();
+    assert_eq!(files, ["a.rs", "nested/c.rs"]);
+}
+
+#[test]
+fn filters_by_extension() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("b.TS", "// CODESYNC(foo)\n"),
+        ("c.py", "# CODESYNC(foo)\n"),
+        ("d", "CODESYNC(foo)\n"),
+        ("nested/e.rs", "// CODESYNC(foo)\n"),
+        ("skipped/f.rs", "// CODESYNC(foo)\n"),
+        (".ignore", "skipped/\n"),
+    ]);
+    let opts = CollectOptions::new()
+        .root(dir.path())
+        .extensions(["rs", ".ts"]);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+    let files: Vec<_> = comments(&dir, &matches)
+        .into_iter()
+        .map(|(_, file, _)| file)
+        .collect();
+    assert_eq!(files, ["a.rs", "b.TS", "nested/e.rs"]);
+
+    // Roots that are files are searched regardless of their extension
+    let opts = CollectOptions::new()
+        .root(dir.path().join("c.py"))
+        .extensions(["rs"]);
+    assert_eq!(
+        Matches::collect_with_options(&opts)
+            .unwrap()
+            .comments()
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn selects_keyword_by_glob() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n// DOCSYNC(bar)\n"),
+        (
+            "docs/b.md",
+            "<!-- DOCSYNC(foo) -->\n<!-- CODESYNC(baz) -->\n",
+        ),
+        ("docs/legacy/c.md", "<!-- CODESYNC(foo) -->\n"),
+    ]);
+    let opts = CollectOptions::new()
+        .root(dir.path())
+        .keyword_for("**/*.md", "DOCSYNC")
+        .keyword_for("docs/legacy/**", "CODESYNC");
+    let matches = Matches::collect_with_options(&opts).unwrap();
+    let groups: Vec<_> = matches
+        .group_by_label()
+        .into_iter()
+        .map(|(label, comments)| {
+            let files: Vec<_> = comments
+                .iter()
+                .map(|c| relative(&dir, c.file()).display().to_string())
+                .collect();
+            (label, files)
+        })
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            ("DOCSYNC:foo", vec!["docs/b.md".to_string()]),
+            (
+                "foo",
+                vec!["a.rs".to_string(), "docs/legacy/c.md".to_string()]
+            ),
+        ]
+    );
+    assert_eq!(
+        matches
+            .comments()
+            .map(|c| c.label().to_string())
+            .collect::<Vec<_>>(),
+        ["foo", "foo", "foo"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn follows_symlinks_when_enabled() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("real/b.rs", "// CODESYNC(bar)\n"),
+    ]);
+    std::os::unix::fs::symlink(dir.path().join("real"), dir.path().join("link")).unwrap();
+    let labels = |follow| {
+        let opts = CollectOptions::new()
+            .root(dir.path())
+            .follow_symlinks(follow);
+        let matches = Matches::collect_with_options(&opts).unwrap();
+        comments(&dir, &matches)
+            .into_iter()
+            .map(|(label, file, _)| format!("{label}@{file}"))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(labels(false), ["bar@real/b.rs", "foo@a.rs"]);
+    // The file is searched once, under the first of its paths
+    assert_eq!(labels(true), ["bar@link/b.rs", "foo@a.rs"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn searches_symlinked_file_once() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("b.rs", "// CODESYNC(foo)\n"),
+    ]);
+    std::os::unix::fs::symlink(dir.path().join("b.rs"), dir.path().join("c.rs")).unwrap();
+    let opts = CollectOptions::new().root(dir.path()).follow_symlinks(true);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+    let files: Vec<_> = comments(&dir, &matches)
+        .into_iter()
+        .map(|(_, file, _)| file)
+        .collect();
+    assert_eq!(files, ["a.rs", "b.rs"]);
+    let vars = Default::default();
+    assert!(matches!(
+        codesync::check_count(&matches.group_by_label()["foo"], &vars),
+        CountCheck::Ok
+    ));
+}
+
+#[test]
+fn searches_files_under_overlapping_roots_once() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("src/b.rs", "// CODESYNC(foo)\n"),
+        ("src/nested/c.rs", "// CODESYNC(bar, 1)\n"),
+    ]);
+    let root = dir.path().join(".");
+    let opts = CollectOptions::new().roots([root.clone(), root.join("./src"), root.join("src/")]);
+    let matches = Matches::collect_with_options(&opts).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![
+            ("bar".to_string(), "src/nested/c.rs".to_string(), 3),
+            ("foo".to_string(), "a.rs".to_string(), 3),
+            ("foo".to_string(), "src/b.rs".to_string(), 3),
+        ]
+    );
+    assert_eq!(matches.sources().count(), 3);
+    let vars = Default::default();
+    assert!(matches!(
+        codesync::check_count(&matches.group_by_label()["foo"], &vars),
+        CountCheck::Ok
+    ));
+}
+
+#[test]
+fn reports_binary_files() {
+    let dir = fixture(&[
+        ("a.rs", "// CODESYNC(foo)\n"),
+        ("b.bin", "// CODESYNC(foo)\n\0\n"),
+    ]);
+    let collect = |detection| {
+        let opts = CollectOptions::new()
+            .root(dir.path())
+            .binary_detection(detection);
+        Matches::collect_with_options(&opts).unwrap()
+    };
+    let binary = |matches: &Matches| -> Vec<_> {
+        matches
+            .binary_files()
+            .iter()
+            .map(|(path, offset)| {
+                (
+                    path.strip_prefix(dir.path()).unwrap().to_path_buf(),
+                    *offset,
+                )
+            })
+            .collect()
+    };
+
+    let matches = collect(BinaryDetection::None);
+    assert!(binary(&matches).is_empty());
+    assert_eq!(matches.comments().count(), 2);
+
+    let matches = collect(BinaryDetection::Quit);
+    assert_eq!(binary(&matches), [("b.bin".into(), 17)]);
+    assert_eq!(matches.comments().count(), 1);
+
+    let matches = collect(BinaryDetection::Convert);
+    assert_eq!(binary(&matches), [("b.bin".into(), 17)]);
+    assert_eq!(matches.comments().count(), 2);
+}
+
+#[test]
+fn limits_matches_per_file() {
+    let many = "// CODESYNC(foo)\n// CODESYNC(bar\n".repeat(3);
+    let dir = fixture(&[("a.rs", &many), ("b.rs", "// CODESYNC(foo)\n")]);
+    let collect = |max| {
+        let opts = CollectOptions::new()
+            .root(dir.path())
+            .max_matches_per_file(max);
+        Matches::collect_with_options(&opts).unwrap()
+    };
+
+    let matches = collect(None);
+    assert!(matches.truncated_files().is_empty());
+    assert_eq!(matches.comments().count(), 4);
+    assert_eq!(matches.invalid_matches().count(), 3);
+
+    // Invalid matches count towards the limit
+    let matches = collect(Some(6));
+    assert!(matches.truncated_files().is_empty());
+    let matches = collect(Some(3));
+    let truncated: Vec<_> = matches
+        .truncated_files()
+        .iter()
+        .map(|path| relative(&dir, path))
+        .collect();
+    assert_eq!(truncated, [Path::new("a.rs")]);
+    assert_eq!(matches.comments().count(), 3);
+    assert_eq!(matches.invalid_matches().count(), 1);
+}
+
+#[test]
+fn profile_counts_searched_files_and_bytes() {
+    let dir = fixture(&[("a.rs", "// CODESYNC(foo)\n"), ("b.rs", "fn main() {}\n")]);
+    for threads in [1, 2] {
+        let opts = CollectOptions::new().root(dir.path()).threads(threads);
+        let profile = Matches::collect_with_options(&opts)
+            .unwrap()
+            .profile()
+            .clone();
+        assert_eq!(profile.files, 2);
+        assert_eq!(profile.bytes, 30);
+    }
+}
+
+#[test]
+fn output_does_not_depend_on_threads() {
+    let files: Vec<_> = (0..20)
+        .map(|i| {
+            let contents = format!(
+                "// CODESYNC(foo)\n// CODESYNC(bar-{})\n// CODESYNC\n",
+                i % 3
+            );
+            (format!("dir{}/file{i}.rs", i % 4), contents)
+        })
+        .collect();
+    let files: Vec<_> = files
+        .iter()
+        .map(|(p, c)| (p.as_str(), c.as_str()))
+        .collect();
+    let dir = fixture(&files);
+
+    let output = |threads| {
+        let opts = CollectOptions::new().root(dir.path()).threads(threads);
+        let matches = Matches::collect_with_options(&opts).unwrap();
+        let mut lines: Vec<_> = matches.comments().map(|c| c.to_string()).collect();
+        lines.extend(matches.invalid_matches().map(|m| m.to_string()));
+        lines.join("\n")
+    };
+    let expected = output(1);
+    for threads in [0, 2, 8] {
+        assert_eq!(output(threads), expected);
+    }
+
+    let matches = Matches::collect_in(dir.path()).unwrap();
+    let locations: Vec<_> = matches
+        .comments()
+        .map(|c| (c.file().to_path_buf(), c.byte_offset()))
+        .collect();
+    assert!(locations.windows(2).all(|w| w[0] < w[1]));
+    let invalid: Vec<_> = matches
+        .invalid_matches()
+        .map(|m| (m.file().to_path_buf(), m.span().start))
+        .collect();
+    assert_eq!(invalid.len(), matches.invalid_count());
+    assert!(invalid.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn finds_comment_on_last_line_without_newline() {
+    let contents = "fn main() {}\n// CODESYNC(foo)";
+    let dir = fixture(&[("a.rs", contents), ("b.rs", "/* CODESYNC(foo) */")]);
+    let matches = Matches::collect_in(dir.path()).unwrap();
+
+    assert_eq!(
+        comments(&dir, &matches),
+        vec![
+            ("foo".to_string(), "a.rs".to_string(), 16),
+            ("foo".to_string(), "b.rs".to_string(), 3),
+        ]
+    );
+    let foo = &matches.group_by_label()["foo"];
+    assert_eq!(&contents[foo[0].span()], "CODESYNC(foo)");
+    assert_eq!(&contents[foo[0].full_span()], "// CODESYNC(foo)");
+    assert_eq!(foo[1].full_span(), 0..19);
+    assert_eq!(matches.invalid_matches().count(), 0);
+}
+
+#[test]
+fn recovers_from_invalid_utf8() {
+    let dir = fixture(&[("b.rs", "// CODESYNC(foo)\n")]);
+    fs::write(
+        dir.path().join("a.rs"),
+        b"\xff// \xfe\xffCODESYNC(foo)\xc3\n\xe2\x82CODESYNC(bar, 1)\n",
+    )
+    .unwrap();
+    let opts = CollectOptions::new().root(dir.path());
+    let matches = Matches::collect_with_options(&opts).unwrap();
+    let labels: Vec<_> = comments(&dir, &matches)
+        .into_iter()
+        .map(|(label, file, _)| format!("{label}@{file}"))
+        .collect();
+    assert_eq!(labels, ["bar@a.rs", "foo@a.rs", "foo@b.rs"]);
+}